@@ -0,0 +1,73 @@
+import pytest
+
+from pyo3Tree import (
+    Tree, Node, TreeMap, NodeMap,
+    NotInTreeError, NodeNotFoundError, DuplicateIdError, CycleError, MalformedInputError,
+)
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_add_many_pairs_in_any_order():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b, c = node_class("a"), node_class("b"), node_class("c")
+        tree.add_many(((b, a), (a, None), (c, a)))
+
+        assert ids(tree.root.children) == [a.id]
+        assert ids(a.children) == [b.id, c.id]
+        assert tree.find_by_id(b.id).data == "b"
+
+def test_add_many_leaves_tree_unchanged_on_error():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b = node_class(), node_class()
+
+        with pytest.raises(NotInTreeError):
+            tree.add_many([(a, None), (b, node_class())])
+        with pytest.raises(CycleError):
+            tree.add_many([(a, b), (b, a)])
+        with pytest.raises(MalformedInputError):
+            tree.add_many([(a, None), "not a pair"])
+
+        assert tree.root.children == []
+        assert a.parent is None
+
+def test_add_many_with_a_pruned_parent():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b = node_class(), node_class()
+        tree.add(a)
+        tree.remove(a)
+
+        with pytest.raises(NotInTreeError):
+            tree.add_many([(b, a)])
+        with pytest.raises(NotInTreeError):
+            tree.add_many([(b, None), (node_class(), a)])
+
+        assert tree.root.children == []
+        assert b.parent is None
+
+def test_extend_records():
+
+    for tree in (Tree(), TreeMap()):
+        records = (record for record in [("b", "a", 2), ("a", None, 1), ("c", "b", None)])
+        nodes = tree.extend(records)
+
+        assert ids(nodes) == ["b", "a", "c"]
+        assert tree.find_by_id("b").data == 2
+        assert tree.find_by_id("c").parent.id == "b"
+        assert ids(tree.root.children) == ["a"]
+
+def test_extend_validates_records():
+
+    for tree in (Tree(), TreeMap()):
+        tree.extend([("a", None, None)])
+
+        with pytest.raises(NodeNotFoundError):
+            tree.extend([("b", "a", None), ("c", "missing", None)])
+        with pytest.raises(DuplicateIdError):
+            tree.extend([("a", None, None)])
+
+        assert ids(tree.root.children) == ["a"]
+        assert tree.find_by_id("a").children == []
@@ -0,0 +1,46 @@
+from pyo3Tree import TreeMap as Tree
+from pyo3Tree import NodeMap as Node
+
+def test_loading_a_tree_keeps_other_tree_data():
+
+    first_data = {
+        "id": "c1f3e0a4-7b7e-4f5e-8a57-0d4a1c9e0a01",
+        "data": "first root",
+        "children": [
+            {"id": "c1f3e0a4-7b7e-4f5e-8a57-0d4a1c9e0a02", "data": "first child"},
+        ]
+    }
+
+    second_data = {
+        "id": "d1f3e0a4-7b7e-4f5e-8a57-0d4a1c9e0b01",
+        "data": "second root",
+    }
+
+    first_tree = Tree.load(first_data)
+    detached = Node("detached")
+    Tree.load(second_data)
+
+    assert first_tree.find_by_id("c1f3e0a4-7b7e-4f5e-8a57-0d4a1c9e0a02").data == "first child"
+    assert first_tree.export() == first_data
+    assert detached.data == "detached"
+
+def test_detached_data_follows_node_into_tree():
+
+    tree = Tree()
+    node = Node({"name": "leaf"})
+
+    tree.add(node)
+    node.data = {"name": "renamed"}
+
+    assert tree.find_by_id(node.id).data == {"name": "renamed"}
+    assert tree.export()["children"][0]["data"] == {"name": "renamed"}
+
+def test_node_without_data():
+
+    tree = Tree()
+    node = Node()
+
+    tree.add(node)
+
+    assert node.data is None
+    assert "data" not in tree.export()["children"][0]
@@ -0,0 +1,72 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, Node, NodeMap, NotInTreeError
+
+data = {
+    "id": "root",
+    "children": [
+        {
+            "id": "a",
+            "children": [
+                {"id": "c"},
+                {"id": "d", "children": [{"id": "e"}]},
+            ]
+        },
+        {"id": "b"},
+    ]
+}
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_tree_queries():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        root = tree.root
+        a = tree.find_by_id("a")
+        e = tree.find_by_id("e")
+
+        assert ids(tree.get_descendants(root)) == ["a", "c", "d", "e", "b"]
+        assert ids(tree.get_descendants(a)) == ["c", "d", "e"]
+        assert tree.get_descendants(e) == []
+        assert ids(tree.get_leaves(root)) == ["c", "e", "b"]
+        assert ids(tree.get_leaves(e)) == ["e"]
+        assert tree.subtree_size(root) == 6
+        assert tree.subtree_size(a) == 4
+        assert tree.is_leaf(e) and not tree.is_leaf(a)
+        assert tree.is_root(root) and not tree.is_root(a)
+
+def test_node_queries():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        a = tree.find_by_id("a")
+
+        assert ids(a.get_descendants()) == ["c", "d", "e"]
+        assert ids(a.get_leaves()) == ["c", "e"]
+        assert a.subtree_size() == 4
+        assert tree.root.is_root and not a.is_root
+        assert tree.find_by_id("b").is_leaf and not a.is_leaf
+
+def test_queries_outside_tree():
+
+    for tree, node_type in ((Tree.load(data), Node), (TreeMap.load(data), NodeMap)):
+        stray = node_type()
+        with pytest.raises(NotInTreeError):
+            tree.get_descendants(stray)
+        with pytest.raises(NotInTreeError):
+            tree.is_leaf(stray)
+        with pytest.raises(NotInTreeError):
+            tree.is_root(stray)
+
+    tree = TreeMap.load(data)
+    stray = NodeMap()
+
+    with pytest.raises(NotInTreeError):
+        stray.is_root
+    removed = tree.find_by_id("b")
+    tree.remove(removed)
+    with pytest.raises(NotInTreeError):
+        removed.is_root
+
+    with pytest.raises(NotInTreeError):
+        stray.subtree_size()
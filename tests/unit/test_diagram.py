@@ -0,0 +1,71 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, MalformedInputError
+
+data = {
+    "id": "root",
+    "data": "top",
+    "children": [
+        {"id": "a", "data": 'say "hi"', "children": [{"id": "c"}]},
+        {"id": "b", "data": 2},
+    ]
+}
+
+def test_to_dot():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        assert tree.to_dot(highlight={"c"}).splitlines() == [
+            "digraph tree {",
+            "    rankdir=TB;",
+            "    node [shape=box];",
+            '    "root" [label="top"];',
+            '    "a" [label="say \\"hi\\""];',
+            '    "c" [label="c", style=filled, fillcolor="#ffd54f"];',
+            '    "b" [label="2"];',
+            '    "root" -> "a";',
+            '    "a" -> "c";',
+            '    "root" -> "b";',
+            "}",
+        ]
+        dot = tree.to_dot(label=lambda node: node.id.upper(), max_depth=1, direction="LR")
+        assert "rankdir=LR;" in dot
+        assert '"a" [label="A"];' in dot
+        assert '"c"' not in dot
+
+def test_to_mermaid():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        assert tree.to_mermaid(direction="BT", highlight=["a", "b", "missing"]).splitlines() == [
+            "flowchart BT",
+            '    n0["top"]',
+            '    n1["say #quot;hi#quot;"]',
+            '    n2["c"]',
+            '    n3["2"]',
+            "    n0 --> n1",
+            "    n1 --> n2",
+            "    n0 --> n3",
+            "    classDef highlight fill:#ffd54f",
+            "    class n1,n3 highlight",
+        ]
+        labels = tree.to_mermaid(label=lambda node: len(node.children), max_depth=0).splitlines()
+        assert labels == ["flowchart TB", '    n0["2"]']
+
+def test_diagram_errors():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        with pytest.raises(MalformedInputError, match="unknown direction"):
+            tree.to_dot(direction="up")
+        with pytest.raises(MalformedInputError, match="cannot label node"):
+            tree.to_mermaid(label=lambda node: 1 / 0)
+        with pytest.raises(MalformedInputError):
+            tree.to_dot(highlight=[1])
+
+def test_diagram_label_uses_tree():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        assert tree.to_dot(label=lambda node: tree.find_by_id(node.id).id).count("label=") == 4
+        assert '    n2["3"]' in tree.to_mermaid(label=lambda node: str(len(tree.to_records()) - 1))
@@ -0,0 +1,80 @@
+import random
+
+import pytest
+
+from pyo3Tree import Tree, TreeMap, MalformedInputError
+
+data = {
+    "id": "root",
+    "data": "top",
+    "children": [
+        {"id": "a", "data": 1, "children": [{"id": "c"}, {"id": "d", "data": {"x": 4}}]},
+        {"id": "b"},
+    ]
+}
+
+def test_nested_sets():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        sets = tree.nested_sets()
+        assert sets == [("root", 1, 10), ("a", 2, 7), ("c", 3, 4), ("d", 5, 6), ("b", 8, 9)]
+
+        payloads = {id: value for id, _, value in tree.to_records()}
+        rows = [(id, lft, rgt, payloads[id]) for id, lft, rgt in sets]
+        random.shuffle(rows)
+        assert tree_class.from_nested_sets(rows).export() == data
+        assert tree_class.from_nested_sets(sets).nested_sets() == sets
+
+        with pytest.raises(MalformedInputError, match="overlap"):
+            tree_class.from_nested_sets([("r", 1, 6), ("a", 2, 4), ("b", 3, 5)])
+        with pytest.raises(MalformedInputError, match="more than one root"):
+            tree_class.from_nested_sets([("r", 1, 2), ("s", 3, 4)])
+        with pytest.raises(MalformedInputError):
+            tree_class.from_nested_sets([("r", 2, 1)])
+        with pytest.raises(MalformedInputError):
+            tree_class.from_nested_sets([("r", 1)])
+
+def test_materialized_paths():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        paths = tree.materialized_paths()
+        assert paths == ["/root", "/root/a", "/root/a/c", "/root/a/d", "/root/b"]
+        assert tree.materialized_paths(separator=".")[3] == ".root.a.d"
+
+        rows = [("/root", "top"), ("/root/a", 1), "/root/a/c", ("/root/a/d", {"x": 4}), "/root/b"]
+        assert tree_class.from_materialized_paths(rows).export() == data
+        assert tree_class.from_materialized_paths(reversed(paths)).materialized_paths() == ["/root", "/root/b", "/root/a", "/root/a/d", "/root/a/c"]
+        assert tree_class.from_materialized_paths(["root", "root.a"], separator=".").materialized_paths() == ["/root", "/root/a"]
+
+        with pytest.raises(MalformedInputError, match="does not continue"):
+            tree_class.from_materialized_paths(["/r", "/r/a", "/r/a/x", "/r/x/b"])
+        with pytest.raises(MalformedInputError, match="empty segment"):
+            tree_class.from_materialized_paths(["/r", "/r//a"])
+        with pytest.raises(MalformedInputError, match="separator"):
+            tree_class.from_records([("r", None), ("a/b", "r")]).materialized_paths()
+        with pytest.raises(MalformedInputError):
+            tree_class.from_materialized_paths([1])
+
+def test_closure_table():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        closure = tree.closure_table()
+        assert closure[:6] == [("root", "root", 0), ("a", "a", 0), ("root", "a", 1), ("c", "c", 0), ("a", "c", 1), ("root", "c", 2)]
+        assert len(closure) == 11
+        assert {(ancestor, descendant) for ancestor, descendant, depth in closure if depth == 1} == {
+            ("root", "a"), ("root", "b"), ("a", "c"), ("a", "d"),
+        }
+
+        copy = tree_class.from_closure_table(closure)
+        assert sorted(copy.closure_table()) == sorted(closure)
+        assert [node.id for node in copy.find_by_id("a").children] == ["c", "d"]
+
+        with pytest.raises(MalformedInputError, match="do not match"):
+            tree_class.from_closure_table(closure[:5] + closure[6:])
+        with pytest.raises(MalformedInputError, match="more than one parent"):
+            tree_class.from_closure_table(closure + [("b", "c", 1)])
+        with pytest.raises(MalformedInputError):
+            tree_class.from_closure_table([("root", "root")])
@@ -0,0 +1,83 @@
+import pytest
+
+from pyo3Tree import Tree, Node, TreeMap, NodeMap
+from pyo3Tree import (
+    TreeError,
+    NodeNotFoundError,
+    CycleError,
+    DuplicateIdError,
+    MalformedInputError,
+    NotInTreeError,
+    AttachedElsewhereError,
+)
+
+def test_exception_hierarchy():
+
+    assert issubclass(NodeNotFoundError, TreeError)
+    assert issubclass(NodeNotFoundError, KeyError)
+    assert issubclass(CycleError, TreeError)
+    assert issubclass(CycleError, ValueError)
+    assert issubclass(MalformedInputError, ValueError)
+
+def test_find_missing_id():
+
+    for tree in (Tree(), TreeMap()):
+        with pytest.raises(NodeNotFoundError):
+            tree.find_by_id("missing")
+        with pytest.raises(KeyError):
+            tree.find_by_id("missing")
+
+def test_move_into_descendant():
+
+    for tree, node_type in ((Tree(), Node), (TreeMap(), NodeMap)):
+        child = node_type()
+        grand_child = node_type()
+        tree.add(child)
+        tree.add(grand_child, child)
+
+        with pytest.raises(CycleError):
+            tree.move_node(child, grand_child)
+        assert grand_child.parent.id == child.id
+
+def test_duplicate_and_foreign_nodes():
+
+    tree = TreeMap()
+    other_tree = TreeMap()
+    node = NodeMap()
+    tree.add(node)
+
+    with pytest.raises(DuplicateIdError):
+        tree.add(node)
+    with pytest.raises(AttachedElsewhereError):
+        other_tree.add(node)
+    with pytest.raises(NotInTreeError):
+        tree.add(NodeMap(), NodeMap())
+
+def test_malformed_input():
+
+    with pytest.raises(MalformedInputError):
+        TreeMap.load({"children": []})
+    with pytest.raises(MalformedInputError):
+        Tree.load({"id": "root", "children": [{"data": "no id"}]})
+    for tree_class in (Tree, TreeMap):
+        with pytest.raises(DuplicateIdError):
+            tree_class.load({"id": "root-id", "children": [{"id": "child"}, {"id": "child"}]})
+        with pytest.raises(DuplicateIdError):
+            tree_class.load({"id": "r", "children": [{"id": "a"}, {"id": "a", "children": [{"id": "b"}]}]})
+
+def test_root_parent_is_none():
+
+    tree = TreeMap()
+
+    assert tree.root.parent is None
+    assert NodeMap().parent is None
+
+def test_stray_parent():
+
+    for tree, node_type in ((Tree(), Node), (TreeMap(), NodeMap)):
+        child = node_type()
+        with pytest.raises(NotInTreeError):
+            tree.add(child, node_type())
+        assert child.parent is None
+        tree.add(child)
+        assert child.parent.id == tree.root.id
@@ -0,0 +1,36 @@
+import pytest
+
+from pyo3Tree import Tree, Node, NodeNotFoundError
+
+data = {
+    "id": "root",
+    "children": [
+        {"id": "a", "children": [{"id": "c"}]},
+        {"id": "b"},
+    ]
+}
+
+def test_find_loaded_nodes():
+
+    tree = Tree.load(data)
+
+    assert tree.find_by_id("root").id == "root"
+    assert tree.find_by_id("c").parent.id == "a"
+
+def test_find_follows_add_move_and_remove():
+
+    tree = Tree.load(data)
+    node = Node("new")
+    tree.add(node, tree.find_by_id("c"))
+    assert tree.find_by_id(node.id).data == "new"
+
+    tree.move_node(tree.find_by_id("a"), tree.find_by_id("b"))
+    assert tree.find_by_id(node.id).parent.id == "c"
+
+    removed = tree.remove(tree.find_by_id("a"))
+    for id in ("a", "c", node.id):
+        with pytest.raises(NodeNotFoundError):
+            tree.find_by_id(id)
+
+    tree.add(removed)
+    assert tree.find_by_id(node.id).parent.id == "c"
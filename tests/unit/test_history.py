@@ -0,0 +1,111 @@
+import pytest
+
+from pyo3Tree import (
+    Tree, Node, TreeMap, NodeMap,
+    CheckpointNotFoundError, HistoryClearedError, HistoryDisabledError,
+)
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_history_must_be_enabled():
+
+    for tree in (Tree(), TreeMap()):
+        with pytest.raises(HistoryDisabledError):
+            tree.undo()
+        with pytest.raises(HistoryDisabledError):
+            tree.history
+
+        tree.enable_history()
+        assert tree.undo() is False
+        assert tree.history == []
+
+def test_undo_and_redo():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        tree.enable_history(limit=10)
+        a, b, c = node_class("a"), node_class("b"), node_class("c")
+        tree.add(a)
+        tree.add(b)
+        tree.add(c, a)
+        tree.move_node(c, b)
+        c.data = "changed"
+        tree.remove(a)
+
+        assert [[kind for kind, _ in entry] for entry in tree.history] == [["add"], ["add"], ["add"], ["move"], ["set_data"], ["remove"]]
+        assert tree.history[3][0][1].id == c.id
+
+        assert tree.undo() and tree.undo() and tree.undo()
+        assert ids(tree.root.children) == [a.id, b.id]
+        assert ids(tree.find_by_id(a.id).children) == [c.id]
+        assert tree.find_by_id(c.id).data == "c"
+
+        assert tree.redo() and tree.redo() and tree.redo()
+        assert tree.redo() is False
+        assert ids(tree.root.children) == [b.id]
+        assert tree.find_by_id(c.id).parent.id == b.id
+        assert tree.find_by_id(c.id).data == "changed"
+
+def test_transaction_is_one_entry():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        tree.enable_history()
+        a, b = node_class(), node_class()
+        with tree.transaction() as tx:
+            tx.add(a)
+            tx.add(b, a)
+
+        assert len(tree.history) == 1
+        tree.undo()
+        assert tree.root.children == []
+
+def test_checkpoints():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        tree.enable_history(limit=2)
+        a, b, c = node_class(), node_class(), node_class()
+        tree.add(a)
+        tree.checkpoint("start")
+        tree.add(b)
+        tree.add(c)
+
+        tree.restore_checkpoint("start")
+        assert ids(tree.root.children) == [a.id]
+
+        with pytest.raises(CheckpointNotFoundError):
+            tree.restore_checkpoint("missing")
+
+        tree.redo()
+        tree.add(c, b)
+        tree.move_node(c, a)
+        with pytest.raises(CheckpointNotFoundError):
+            tree.restore_checkpoint("start")
+
+def test_node_data_off_the_tree_is_not_recorded():
+
+    tree = Tree()
+    tree.enable_history()
+    node = Node("before")
+    node.data = "detached"
+    tree.add(node)
+    tree.remove(node)
+    node.data = "removed"
+    assert [[kind for kind, _ in entry] for entry in tree.history] == [["add"], ["remove"]]
+
+def test_undo_past_detach_subtree():
+
+    tree = TreeMap()
+    tree.enable_history()
+    a, b = NodeMap("a"), NodeMap("b")
+    tree.add(a)
+    tree.add(b)
+    tree.detach_subtree(a)
+    assert tree.history == []
+
+    b.data = "changed"
+    assert tree.undo()
+    assert b.data == "b"
+    with pytest.raises(HistoryClearedError):
+        tree.undo()
+    assert tree.redo()
+    assert b.data == "changed"
@@ -0,0 +1,178 @@
+import pytest
+
+from pyo3Tree import Tree, Node, TreeMap, NodeMap, AfterHookError, TreeError
+
+def test_events_carry_parents_and_positions():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        root_id = tree.root.id
+        a, b, c = node_class(), node_class(), node_class()
+        events = []
+        tree.on_change(events.append)
+
+        tree.add(a)
+        tree.add(b)
+        tree.insert_child(a, c, 0)
+        tree.move_node(c, tree.root, index=1)
+        tree.remove(b)
+
+        assert [(event.kind, event.node_id) for event in events] == [
+            ("add", a.id), ("add", b.id), ("add", c.id), ("move", c.id), ("remove", b.id),
+        ]
+        move = events[3]
+        assert (move.old_parent, move.old_index, move.new_parent, move.new_index) == (a.id, 0, root_id, 1)
+        remove = events[4]
+        assert (remove.old_parent, remove.old_index, remove.new_parent, remove.new_index) == (root_id, 2, None, None)
+
+def test_data_changes_fire_hooks():
+
+    tree, node = Tree(), Node()
+    tree.add(node)
+    events = []
+    tree.on_change(events.append)
+    tree.set_data(node, 5)
+    assert tree.find_by_id(node.id).data == 5
+    tree.find_by_id(node.id).data = 7
+    assert node.data == 7
+
+    tree_map, node_map = TreeMap(), NodeMap()
+    tree_map.add(node_map)
+    tree_map.on_change(events.append)
+    tree_map.find_by_id(node_map.id).data = 6
+    assert node_map.data == 6
+
+    assert [(event.kind, event.node_id, event.new_index) for event in events] == [("set_data", node.id, 0), ("set_data", node.id, 0), ("set_data", node_map.id, 0)]
+
+    detached = Node()
+    detached.data = 8
+    assert len(events) == 3
+
+def test_transactions_fire_hooks():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b = node_class(), node_class()
+        events = []
+        tree.on_change(events.append)
+        with tree.transaction() as tx:
+            tx.add(a)
+            tx.add(b, a)
+            tx.set_data(b, "b")
+            tx.move_node(b, tree.root)
+
+        assert [(event.kind, event.node_id, event.new_parent, event.new_index) for event in events] == [
+            ("add", a.id, tree.root.id, 0), ("add", b.id, a.id, 0), ("set_data", b.id, a.id, 0), ("move", b.id, tree.root.id, 1),
+        ]
+
+        def veto(event):
+            raise PermissionError(event.node_id)
+
+        hook_id = tree.on_before_change(veto)
+        with pytest.raises(PermissionError):
+            with tree.transaction() as tx:
+                tx.remove(a)
+        assert len(tree.root.children) == 2
+        tree.remove_hook(hook_id)
+
+def test_reorder_undo_and_redo_fire_hooks():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        tree.enable_history()
+        a, b, c = node_class(), node_class(), node_class()
+        for node in (a, b, c):
+            tree.add(node)
+        tree.checkpoint("three")
+        events = []
+        tree.on_change(events.append)
+
+        tree.reorder_children(tree.root, [c.id, a.id, b.id])
+        assert [(event.kind, event.node_id, event.old_index, event.new_index) for event in events] == [
+            ("move", c.id, 2, 0), ("move", a.id, 0, 1), ("move", b.id, 1, 2),
+        ]
+        del events[:]
+
+        tree.remove(a)
+        assert tree.undo()
+        assert tree.redo()
+        assert [(event.kind, event.node_id) for event in events] == [("remove", a.id), ("add", a.id), ("remove", a.id)]
+        assert (events[1].new_parent, events[1].new_index) == (tree.root.id, 1)
+        del events[:]
+
+        tree.restore_checkpoint("three")
+        assert [(event.kind, event.node_id) for event in events][0] == ("add", a.id)
+        assert [node.id for node in tree.root.children] == [a.id, b.id, c.id]
+
+def test_removing_a_subtree_reports_every_node():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        tree.extend([("a", None, None), ("b", "a", None), ("c", "b", None), ("d", "a", None)])
+        events = []
+        tree.on_change(events.append)
+
+        tree.remove(tree.find_by_id("a"))
+        assert [(event.kind, event.node_id, event.old_parent, event.old_index) for event in events] == [
+            ("remove", "a", tree.root.id, 0), ("remove", "b", "a", 0), ("remove", "c", "b", 0), ("remove", "d", "a", 1),
+        ]
+
+    tree = TreeMap()
+    tree.extend([("a", None, None), ("b", "a", None)])
+    events = []
+    tree.on_change(events.append)
+
+    def veto(event):
+        raise PermissionError(event.node_id)
+
+    hook_id = tree.on_before_change(veto)
+    with pytest.raises(PermissionError):
+        tree.detach_subtree(tree.find_by_id("a"))
+    assert tree.find_by_id("b").parent.id == "a"
+    tree.remove_hook(hook_id)
+    subtree = tree.detach_subtree(tree.find_by_id("a"))
+    assert [(event.kind, event.node_id) for event in events] == [("remove", "a"), ("remove", "b")]
+    assert subtree.find_by_id("b").parent.id == "a"
+
+def test_before_hook_vetoes_change():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a = node_class()
+        after = []
+
+        def veto(event):
+            raise PermissionError(event.node_id)
+
+        hook_id = tree.on_before_change(veto)
+        tree.on_change(after.append)
+        with pytest.raises(PermissionError):
+            tree.add(a)
+        assert tree.root.children == []
+        assert after == []
+
+        assert tree.remove_hook(hook_id)
+        assert not tree.remove_hook(hook_id)
+        tree.add(a)
+        assert len(after) == 1
+
+def test_after_hook_error_keeps_the_change():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a = node_class()
+
+        def fail(event):
+            raise PermissionError(event.node_id)
+
+        tree.on_change(fail)
+        with pytest.raises(AfterHookError) as raised:
+            tree.add(a)
+        assert isinstance(raised.value, TreeError)
+        assert isinstance(raised.value.__cause__, PermissionError)
+        assert tree.find_by_id(a.id).parent.id == tree.root.id
+
+def test_hooks_can_use_the_tree():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        seen = []
+        tree.on_before_change(lambda event: seen.append(len(tree.root.children)))
+        tree.on_change(lambda event: seen.append(tree.find_by_id(event.node_id).parent.id))
+        nodes = tree.extend([("a", None, None), ("b", "a", None)])
+
+        assert seen == [0, 0, tree.root.id, "a"]
+        assert [node.id for node in nodes] == ["a", "b"]
@@ -0,0 +1,76 @@
+import pytest
+
+from pyo3Tree import TreeMap, NodeMap, DuplicateIdError, MalformedInputError
+
+def build():
+    tree = TreeMap()
+    tree.enable_journal()
+    a, b, c = NodeMap({"name": "a"}), NodeMap(["b"]), NodeMap(3)
+    tree.add(a)
+    tree.add(b)
+    tree.insert_child(a, c, 0)
+    return tree, a, b, c
+
+def test_journal_is_off_by_default():
+
+    tree = TreeMap()
+    assert tree.journal() is None
+    assert tree.journal_seq is None
+
+    tree.enable_journal()
+    assert tree.journal_seq == 0
+    tree.disable_journal()
+    assert tree.journal() is None
+
+def test_replay_rebuilds_the_tree():
+
+    tree, a, b, c = build()
+    tree.move_node(c, b)
+    c.data = "changed"
+    tree.reorder_children(tree.root, [b.id, a.id])
+    tree.remove(a, False)
+    assert tree.journal_seq == 7
+
+    log = tree.journal()
+    assert isinstance(log, bytes)
+    copy = TreeMap.replay(log)
+    assert copy.export() == tree.export()
+    assert copy.find_by_id(c.id).data == "changed"
+
+def test_apply_advances_a_copy():
+
+    tree, a, b, c = build()
+    seq = tree.journal_seq
+    copy = TreeMap.replay(tree.journal())
+
+    tree.enable_history()
+    tree.move_node(c, b)
+    with tree.transaction() as transaction:
+        transaction.set_data(a, None)
+        transaction.remove(b, False)
+    tree.undo()
+    copy.apply(tree.journal(since=seq))
+    assert copy.export() == tree.export()
+
+def test_apply_is_all_or_nothing():
+
+    tree, a, b, c = build()
+    copy = TreeMap.replay(tree.journal())
+    exported = copy.export()
+
+    with pytest.raises(DuplicateIdError):
+        copy.apply(tree.journal())
+    assert copy.export() == exported
+
+    with pytest.raises(MalformedInputError):
+        copy.apply(tree.journal()[:-1])
+    with pytest.raises(MalformedInputError):
+        TreeMap.replay(b"not a journal")
+
+def test_unpicklable_data_is_rejected():
+
+    tree = TreeMap()
+    tree.enable_journal()
+    tree.add(NodeMap(lambda: None))
+    with pytest.raises(MalformedInputError):
+        tree.journal()
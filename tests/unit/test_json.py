@@ -0,0 +1,84 @@
+import json
+import os
+import pathlib
+import tempfile
+
+import pytest
+
+from pyo3Tree import Tree, TreeMap, DuplicateIdError, MalformedInputError
+
+data = {
+    "id": "root",
+    "data": {"name": "root", "tags": ["a", "b"], "size": 2.5},
+    "children": [
+        {"id": "a", "data": [1, None, True], "children": [{"id": "c", "data": "text"}]},
+        {"id": "b", "data": 12345678901234},
+    ]
+}
+
+def test_to_json_matches_export():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        assert json.loads(tree.to_json()) == tree.export() == data
+        assert tree.to_json(indent=2) == json.dumps(data, indent=2)
+
+def test_from_json_round_trip():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.from_json(json.dumps(data))
+        assert isinstance(tree, tree_class)
+        assert tree.export() == data
+        assert tree.find_by_id("c").data == "text"
+        # tuples come back as lists, as they do through the json module
+        tree.find_by_id("b").data = (1, 2)
+        copy = tree_class.from_json(tree.to_json())
+        assert copy.find_by_id("b").data == [1, 2]
+
+def test_json_files():
+
+    for tree_class in (Tree, TreeMap):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "tree.json")
+            assert tree_class.load(data).to_json(path) is None
+            assert tree_class.from_json(path).export() == data
+            assert tree_class.from_json(pathlib.Path(path)).export() == data
+            with pytest.raises(FileNotFoundError):
+                tree_class.from_json(os.path.join(directory, "missing.json"))
+
+def test_data_that_is_not_json():
+
+    for tree_class in (Tree, TreeMap):
+        for value in ({1, 2}, float("nan"), {1: "int key"}, 2 ** 70, object()):
+            tree = tree_class.load(data)
+            tree.find_by_id("c").data = value
+            with pytest.raises(MalformedInputError, match="node 'c'"):
+                tree.to_json()
+
+def test_circular_data():
+
+    looped = []
+    looped.append(looped)
+    nested = {"inner": {}}
+    nested["inner"]["outer"] = nested
+    shared = [1]
+    for tree_class in (Tree, TreeMap):
+        for value in (looped, nested):
+            tree = tree_class.load(data)
+            tree.find_by_id("c").data = value
+            with pytest.raises(MalformedInputError, match="circular reference"):
+                tree.to_json()
+        # The same container twice side by side is not a cycle
+        tree = tree_class.load(data)
+        tree.find_by_id("c").data = [shared, shared]
+        assert '"data":[[1],[1]]' in tree.to_json()
+
+def test_malformed_json():
+
+    for tree_class in (Tree, TreeMap):
+        with pytest.raises(MalformedInputError):
+            tree_class.from_json('{"id": "root", "children": [')
+        with pytest.raises(MalformedInputError):
+            tree_class.from_json('{"children": []}')
+        with pytest.raises(DuplicateIdError):
+            tree_class.from_json('{"id": "root", "children": [{"id": "a"}, {"id": "a"}]}')
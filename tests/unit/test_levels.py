@@ -0,0 +1,54 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, Node, NodeMap, NotInTreeError
+
+data = {
+    "id": "root",
+    "children": [
+        {
+            "id": "a",
+            "children": [
+                {"id": "c"},
+                {"id": "d", "children": [{"id": "e"}]},
+            ]
+        },
+        {"id": "b"},
+    ]
+}
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_depth_and_height():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        assert tree.depth(tree.root) == 0
+        assert tree.depth(tree.find_by_id("e")) == 3
+        assert tree.height(tree.root) == 3
+        assert tree.height(tree.find_by_id("a")) == 2
+        assert tree.height(tree.find_by_id("b")) == 0
+
+def test_nodes_at_level():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        assert ids(tree.nodes_at_level(0)) == ["root"]
+        assert ids(tree.nodes_at_level(2)) == ["c", "d"]
+        assert tree.nodes_at_level(4) == []
+
+def test_depth_follows_moves():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        d = tree.find_by_id("d")
+        tree.move_node(d, tree.root)
+
+        assert tree.depth(d) == 1
+        assert tree.depth(tree.find_by_id("e")) == 2
+        assert ids(tree.nodes_at_level(1)) == ["a", "b", "d"]
+
+def test_depth_outside_tree():
+
+    for tree, node_type in ((Tree.load(data), Node), (TreeMap.load(data), NodeMap)):
+        with pytest.raises(NotInTreeError):
+            tree.depth(node_type())
+        with pytest.raises(NotInTreeError):
+            tree.height(node_type())
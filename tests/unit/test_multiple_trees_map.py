@@ -0,0 +1,41 @@
+from pyo3Tree import TreeMap as Tree
+from pyo3Tree import NodeMap as Node
+
+def test_second_tree_does_not_replace_first():
+
+    first_tree = Tree()
+    second_tree = Tree()
+    first_node = Node()
+    second_node = Node()
+
+    first_tree.add(first_node)
+    second_tree.add(second_node)
+
+    assert first_tree.root.id != second_tree.root.id
+    assert [child.id for child in first_tree.root.children] == [first_node.id]
+    assert [child.id for child in second_tree.root.children] == [second_node.id]
+
+def test_loaded_trees_are_independent():
+
+    first_data = {
+        "id": "a6a0c7f2-2d4e-4c54-9d36-5b4e6d2f0a01",
+        "children": [
+            {"id": "a6a0c7f2-2d4e-4c54-9d36-5b4e6d2f0a02"},
+        ]
+    }
+
+    second_data = {
+        "id": "b6a0c7f2-2d4e-4c54-9d36-5b4e6d2f0b01",
+        "children": [
+            {"id": "b6a0c7f2-2d4e-4c54-9d36-5b4e6d2f0b02"},
+        ]
+    }
+
+    first_tree = Tree.load(first_data)
+    second_tree = Tree.load(second_data)
+
+    first_child = first_tree.find_by_id("a6a0c7f2-2d4e-4c54-9d36-5b4e6d2f0a02")
+    assert first_child.parent.id == "a6a0c7f2-2d4e-4c54-9d36-5b4e6d2f0a01"
+
+    assert first_tree.export() == first_data
+    assert second_tree.export() == second_data
@@ -0,0 +1,43 @@
+import pytest
+
+from pyo3Tree import Tree, Node, MalformedInputError
+
+def test_from_newick():
+
+    tree = Tree.from_newick("((A:0.1,'C d''s':2.5e-1)B[an internal node]:0.3, E_f)root;")
+    assert [node.data for node in tree.iter()] == ["root", "B", "A", "C d's", "E f"]
+    assert [node.branch_length for node in tree.iter()] == [None, 0.3, 0.1, 0.25, None]
+    assert tree.root.children[0].children[1].data == "C d's"
+
+    unlabelled = Tree.from_newick("(,(,));")
+    assert [node.data for node in unlabelled.iter()] == [None] * 5
+    assert len({node.id for node in unlabelled.iter()}) == 5
+
+def test_to_newick_round_trip():
+
+    text = "((A:0.1,'C d''s':0.25)B:0.3,'E f')root;"
+    tree = Tree.from_newick(text)
+    assert tree.to_newick() == text
+    assert Tree.from_newick(tree.to_newick()).to_newick() == text
+
+    tree = Tree()
+    node = Node(42)
+    node.branch_length = 1.5
+    tree.add(node, tree.root)
+    assert tree.to_newick() == "(42:1.5);"
+    node.branch_length = None
+    assert tree.to_newick() == "(42);"
+
+def test_newick_errors():
+
+    for text, match in [
+        ("(A,B)", "missing ';'"),
+        ("(A,B;", "never closed"),
+        ("(A,B));", "unexpected '\\)'"),
+        ("(A:x,B);", "invalid branch length 'x'"),
+        ("('A,B);", "quoted label"),
+        ("(A,B)[root;", "comment"),
+        ("(A,B); C", "after the closing"),
+    ]:
+        with pytest.raises(MalformedInputError, match=match):
+            Tree.from_newick(text)
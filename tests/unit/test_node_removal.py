@@ -0,0 +1,60 @@
+import pytest
+
+from pyo3Tree import Tree, Node, RootNodeError
+
+data = {
+    "id": "343708ec-f679-4345-a7a9-1eb11f974c81",
+    "children": [
+        {"id": "dbe14fc0-aeef-4745-a4b0-41c98cbbaea8"},
+        {
+            "id": "d7582511-8d32-47d9-a38a-becceb9b88e7",
+            "data": "branch",
+            "children": [
+                {"id": "9b73a757-da9c-46c0-8ee2-52bd1160ef96", "data": "leaf"},
+                {"id": "d062c7c0-ffff-4c1c-8275-168b8bfe5d39"},
+            ]
+        },
+        {"id": "b0862e33-81a1-4b26-b152-1f993b5c9349"},
+    ]
+}
+
+def test_remove_node_keep_children():
+
+    tree = Tree.load(data)
+    branch = tree.find_by_id("d7582511-8d32-47d9-a38a-becceb9b88e7")
+
+    removed = tree.remove(branch, keep_children=True)
+
+    assert removed.id == branch.id
+    assert removed.parent is None
+    assert removed.children == []
+    assert [child.id for child in tree.root.children] == [
+        "dbe14fc0-aeef-4745-a4b0-41c98cbbaea8",
+        "9b73a757-da9c-46c0-8ee2-52bd1160ef96",
+        "d062c7c0-ffff-4c1c-8275-168b8bfe5d39",
+        "b0862e33-81a1-4b26-b152-1f993b5c9349",
+    ]
+    assert tree.find_by_id("9b73a757-da9c-46c0-8ee2-52bd1160ef96").parent.id == tree.root.id
+
+def test_remove_subtree_and_reattach():
+
+    tree = Tree.load(data)
+    branch = tree.find_by_id("d7582511-8d32-47d9-a38a-becceb9b88e7")
+
+    subtree = tree.remove(branch)
+
+    assert subtree.parent is None
+    assert [child.data for child in subtree.children] == ["leaf", None]
+    assert len(tree.root.children) == 2
+
+    other_tree = Tree(Node("other root"))
+    other_tree.add(subtree)
+    leaf = other_tree.find_by_id("9b73a757-da9c-46c0-8ee2-52bd1160ef96")
+    assert [ancestor.id for ancestor in other_tree.get_ancestors(leaf)] == [branch.id, other_tree.root.id]
+
+def test_remove_root_fails():
+
+    tree = Tree.load(data)
+
+    with pytest.raises(RootNodeError):
+        tree.remove(tree.root)
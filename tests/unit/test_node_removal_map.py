@@ -0,0 +1,67 @@
+import pytest
+
+from pyo3Tree import TreeMap as Tree
+from pyo3Tree import NodeMap as Node
+from pyo3Tree import NodeNotFoundError
+
+data = {
+    "id": "343708ec-f679-4345-a7a9-1eb11f974c81",
+    "children": [
+        {"id": "dbe14fc0-aeef-4745-a4b0-41c98cbbaea8"},
+        {
+            "id": "d7582511-8d32-47d9-a38a-becceb9b88e7",
+            "data": "branch",
+            "children": [
+                {"id": "9b73a757-da9c-46c0-8ee2-52bd1160ef96", "data": "leaf"},
+                {"id": "d062c7c0-ffff-4c1c-8275-168b8bfe5d39"},
+            ]
+        },
+        {"id": "b0862e33-81a1-4b26-b152-1f993b5c9349"},
+    ]
+}
+
+def test_remove_node_keep_children():
+
+    tree = Tree.load(data)
+    branch = tree.find_by_id("d7582511-8d32-47d9-a38a-becceb9b88e7")
+
+    tree.remove(branch, keep_children=True)
+
+    assert [child.id for child in tree.root.children] == [
+        "dbe14fc0-aeef-4745-a4b0-41c98cbbaea8",
+        "9b73a757-da9c-46c0-8ee2-52bd1160ef96",
+        "d062c7c0-ffff-4c1c-8275-168b8bfe5d39",
+        "b0862e33-81a1-4b26-b152-1f993b5c9349",
+    ]
+    assert tree.find_by_id("9b73a757-da9c-46c0-8ee2-52bd1160ef96").parent.id == tree.root.id
+    assert branch.data == "branch"
+
+def test_remove_subtree():
+
+    tree = Tree.load(data)
+    branch = tree.find_by_id("d7582511-8d32-47d9-a38a-becceb9b88e7")
+
+    tree.remove(branch)
+
+    assert [child.id for child in tree.root.children] == [
+        "dbe14fc0-aeef-4745-a4b0-41c98cbbaea8",
+        "b0862e33-81a1-4b26-b152-1f993b5c9349",
+    ]
+    with pytest.raises(NodeNotFoundError):
+        tree.find_by_id("9b73a757-da9c-46c0-8ee2-52bd1160ef96")
+
+def test_detach_subtree():
+
+    tree = Tree.load(data)
+    branch = tree.find_by_id("d7582511-8d32-47d9-a38a-becceb9b88e7")
+
+    subtree = tree.detach_subtree(branch)
+
+    assert subtree.root.id == branch.id
+    assert subtree.find_by_id("9b73a757-da9c-46c0-8ee2-52bd1160ef96").data == "leaf"
+    assert len(tree.root.children) == 2
+
+    new_tree = Tree()
+    node = Node("fresh")
+    new_tree.add(node)
+    assert node.parent.id == new_tree.root.id
@@ -0,0 +1,54 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, Node, NodeMap, NotInTreeError
+
+data = {
+    "id": "root",
+    "children": [
+        {
+            "id": "a",
+            "children": [
+                {"id": "c"},
+                {"id": "d", "children": [{"id": "e"}]},
+            ]
+        },
+        {"id": "b"},
+    ]
+}
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_path_to_root():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        assert ids(tree.path_to_root(tree.find_by_id("e"))) == ["e", "d", "a", "root"]
+        assert ids(tree.path_to_root(tree.root)) == ["root"]
+
+def test_lowest_common_ancestor():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        c, e, b = (tree.find_by_id(id) for id in ("c", "e", "b"))
+
+        assert tree.lowest_common_ancestor(c, e).id == "a"
+        assert tree.lowest_common_ancestor(e, b).id == "root"
+        assert tree.lowest_common_ancestor(tree.find_by_id("d"), e).id == "d"
+
+def test_path_between():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        c, e, b = (tree.find_by_id(id) for id in ("c", "e", "b"))
+
+        assert ids(tree.path_between(c, e)) == ["c", "a", "d", "e"]
+        assert ids(tree.path_between(e, b)) == ["e", "d", "a", "root", "b"]
+        assert ids(tree.path_between(b, b)) == ["b"]
+
+def test_nodes_outside_the_tree():
+
+    tree = Tree.load(data)
+    with pytest.raises(NotInTreeError):
+        tree.lowest_common_ancestor(tree.root, Node())
+
+    tree_map = TreeMap.load(data)
+    with pytest.raises(NotInTreeError):
+        tree_map.path_between(tree_map.root, NodeMap())
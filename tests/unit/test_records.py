@@ -0,0 +1,59 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, CycleError, DuplicateIdError, MalformedInputError
+
+def test_from_records_in_any_order():
+
+    rows = [
+        ("c", "a", {"value": 3}),
+        {"id": "b", "parent_id": "root"},
+        ("root", None, "top"),
+        ("d", "a"),
+        {"id": "a", "parent_id": "root", "data": [1]},
+    ]
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.from_records(iter(rows))
+        assert isinstance(tree, tree_class)
+        assert tree.export() == {
+            "id": "root",
+            "data": "top",
+            "children": [
+                {"id": "b"},
+                {"id": "a", "data": [1], "children": [{"id": "c", "data": {"value": 3}}, {"id": "d"}]},
+            ]
+        }
+        assert tree.to_records() == [
+            ("root", None, "top"),
+            ("b", "root", None),
+            ("a", "root", [1]),
+            ("c", "a", {"value": 3}),
+            ("d", "a", None),
+        ]
+
+def test_to_records_round_trip():
+
+    for tree_class, other_class in ((Tree, TreeMap), (TreeMap, Tree)):
+        tree = tree_class.from_records([("r", None, 0)] + [(str(i), str(i // 2) if i > 1 else "r", i) for i in range(1, 200)])
+        assert other_class.from_records(tree.to_records()).export() == tree.export()
+        # Children ahead of their parents still work, siblings then come in the order of their rows
+        copy = other_class.from_records(reversed(tree.to_records()))
+        assert sorted(copy.to_records()) == sorted(tree.to_records())
+        assert [node.id for node in copy.find_by_id("1").children] == ["3", "2"]
+
+def test_from_records_errors():
+
+    for tree_class in (Tree, TreeMap):
+        with pytest.raises(MalformedInputError, match="orphan"):
+            tree_class.from_records([("r", None), ("a", "missing")])
+        with pytest.raises(MalformedInputError, match="more than one root"):
+            tree_class.from_records([("r", None), ("s", None)])
+        with pytest.raises(MalformedInputError, match="root"):
+            tree_class.from_records([])
+        with pytest.raises(CycleError):
+            tree_class.from_records([("r", None), ("a", "b"), ("b", "a")])
+        with pytest.raises(DuplicateIdError):
+            tree_class.from_records([("r", None), ("a", "r"), ("a", "r")])
+        with pytest.raises(MalformedInputError):
+            tree_class.from_records([("r",)])
+        with pytest.raises(MalformedInputError):
+            tree_class.from_records([{"parent_id": None}])
@@ -0,0 +1,61 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, MalformedInputError
+
+data = {
+    "id": "root",
+    "data": "top",
+    "children": [
+        {"id": "b", "data": "two\nlines", "children": [{"id": "d", "children": [{"id": "e"}]}, {"id": "c"}]},
+        {"id": "a", "data": 1},
+    ]
+}
+
+def test_render():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        outline = tree.render()
+        assert outline.splitlines() == [
+            "top",
+            "├── two",
+            "│   lines",
+            "│   ├── d",
+            "│   │   └── e",
+            "│   └── c",
+            "└── 1",
+        ]
+        assert outline.endswith("\n")
+        assert str(tree) == outline[:-1]
+
+def test_render_options():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        assert tree.render(label=lambda node: node.id, sort_key=lambda node: node.id, ascii=True).splitlines() == [
+            "root",
+            "|-- a",
+            "`-- b",
+            "    |-- c",
+            "    `-- d",
+            "        `-- e",
+        ]
+        assert tree.render(start=tree.find_by_id("b"), label=lambda node: node.id, max_depth=1) == "b\n├── d\n└── c\n"
+        assert tree.render(max_depth=0) == "top\n"
+        assert tree.render(sort_key=lambda node: -len(node.children), label=lambda node: node.id).splitlines()[1:3] == ["├── b", "│   ├── d"]
+
+def test_render_errors():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        with pytest.raises(MalformedInputError, match="cannot sort children"):
+            tree.render(sort_key=lambda node: node.data)
+        with pytest.raises(MalformedInputError, match="cannot label node"):
+            tree.render(label=lambda node: 1 / 0)
+
+def test_render_callables_use_tree():
+
+    for tree_class in (Tree, TreeMap):
+        tree = tree_class.load(data)
+        outline = tree.render(label=lambda node: str(len(tree.to_records())), sort_key=lambda node: tree.find_by_id(node.id).id)
+        assert outline.splitlines()[:2] == ["6", "├── 6"]
@@ -0,0 +1,70 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, Node, NodeMap, MalformedInputError, NotInTreeError
+
+data = {
+    "id": "root",
+    "children": [
+        {"id": "a", "children": [{"id": "d"}]},
+        {"id": "b"},
+        {"id": "c"},
+    ]
+}
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_sibling_queries():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        a, b, c = (tree.find_by_id(id) for id in ("a", "b", "c"))
+
+        assert ids(tree.siblings(b)) == ["a", "c"]
+        assert tree.siblings(tree.root) == []
+        assert tree.index_in_parent(c) == 2
+        assert tree.index_in_parent(tree.root) is None
+        assert tree.next_sibling(a).id == "b"
+        assert tree.prev_sibling(a) is None
+        assert tree.prev_sibling(c).id == "b"
+        assert tree.next_sibling(c) is None
+        assert tree.first_child(tree.root).id == "a"
+        assert tree.last_child(tree.root).id == "c"
+        assert tree.first_child(b) is None
+
+def test_sibling_queries_outside_tree():
+
+    for tree, node_type in ((Tree.load(data), Node), (TreeMap.load(data), NodeMap)):
+        for query in (tree.siblings, tree.index_in_parent, tree.next_sibling, tree.first_child):
+            with pytest.raises(NotInTreeError):
+                query(node_type())
+
+def test_insert_child():
+
+    for tree, node_class in ((Tree.load(data), Node), (TreeMap.load(data), NodeMap)):
+        first = node_class()
+        last = node_class()
+        tree.insert_child(tree.root, first, 0)
+        tree.insert_child(tree.root, last, 99)
+
+        assert ids(tree.root.children) == [first.id, "a", "b", "c", last.id]
+
+def test_move_node_to_index():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        tree.move_node(tree.find_by_id("d"), tree.root, index=1)
+        assert ids(tree.root.children) == ["a", "d", "b", "c"]
+
+        tree.move_node(tree.find_by_id("a"), tree.root, index=3)
+        assert ids(tree.root.children) == ["d", "b", "c", "a"]
+
+def test_reorder_children():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        tree.reorder_children(tree.root, ["c", "a", "b"])
+        assert ids(tree.root.children) == ["c", "a", "b"]
+
+        with pytest.raises(MalformedInputError):
+            tree.reorder_children(tree.root, ["c", "a"])
+
+        with pytest.raises(MalformedInputError):
+            tree.reorder_children(tree.root, ["c", "a", "d"])
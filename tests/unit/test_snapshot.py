@@ -0,0 +1,64 @@
+import json
+import os
+import tempfile
+
+import pytest
+
+from pyo3Tree import Tree, TreeMap, MalformedInputError
+
+data = {
+    "id": "root",
+    "data": {"name": "root"},
+    "children": [
+        {"id": "a", "data": {1, 2}, "children": [{"id": "c", "data": ("tuple", 3)}, {"id": "d"}]},
+        {"id": "b", "data": b"bytes"},
+    ]
+}
+
+def test_snapshot_round_trip():
+
+    for tree_class in (Tree, TreeMap):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "tree.snapshot")
+            tree_class.load(data).save_snapshot(path)
+            tree = tree_class.load_snapshot(path)
+            assert isinstance(tree, tree_class)
+            assert tree.export() == data
+            assert [node.id for node in tree.find_by_id("a").children] == ["c", "d"]
+            assert tree.find_by_id("d").parent.id == "a"
+
+def test_snapshot_with_encoder():
+
+    for tree_class in (Tree, TreeMap):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "tree.snapshot")
+            plain = {"id": "root", "children": [{"id": "a", "data": [1, "x"]}]}
+            tree_class.load(plain).save_snapshot(path, encoder=lambda value: json.dumps(value).encode())
+            assert tree_class.load_snapshot(path, decoder=json.loads).export() == plain
+
+            with pytest.raises(MalformedInputError):
+                tree_class.load(plain).save_snapshot(path, encoder=str)
+            with pytest.raises(MalformedInputError):
+                tree_class.load_snapshot(path, decoder=lambda raw: 1 / 0)
+
+def test_corrupted_snapshot():
+
+    for tree_class in (Tree, TreeMap):
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "tree.snapshot")
+            tree_class.load(data).save_snapshot(path)
+            with open(path, "rb") as snapshot:
+                raw = bytearray(snapshot.read())
+
+            raw[-20] ^= 0xff
+            with open(path, "wb") as snapshot:
+                snapshot.write(raw)
+            with pytest.raises(MalformedInputError, match="checksum"):
+                tree_class.load_snapshot(path)
+
+            with open(path, "wb") as snapshot:
+                snapshot.write(b"TRJ1 not a snapshot")
+            with pytest.raises(MalformedInputError):
+                tree_class.load_snapshot(path)
+            with pytest.raises(FileNotFoundError):
+                tree_class.load_snapshot(os.path.join(directory, "missing"))
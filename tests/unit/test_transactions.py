@@ -0,0 +1,70 @@
+import pytest
+
+from pyo3Tree import Tree, Node, TreeMap, NodeMap, CycleError, NodeNotFoundError, NotInTreeError
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_transaction_applies_on_exit():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b = node_class("a"), node_class("b")
+        with tree.transaction() as tx:
+            tx.add(a)
+            tx.add(b, a)
+            tx.set_data(b, "changed")
+            assert len(tx) == 3
+            assert tree.root.children == []
+
+        assert ids(tree.root.children) == [a.id]
+        assert tree.find_by_id(b.id).parent.id == a.id
+        assert tree.find_by_id(b.id).data == "changed"
+
+def test_transaction_rolls_back_on_failed_operation():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b, c = node_class(), node_class(), node_class()
+        tree.add(a)
+        tree.add(b, a)
+
+        with pytest.raises(CycleError):
+            with tree.transaction() as tx:
+                tx.add(c, b)
+                tx.remove(b, keep_children=True)
+                tx.move_node(a, c)
+
+        assert ids(tree.root.children) == [a.id]
+        assert ids(tree.find_by_id(a.id).children) == [b.id]
+        assert c.parent is None
+        with pytest.raises(NodeNotFoundError):
+            tree.find_by_id(c.id)
+
+def test_transaction_with_a_stray_parent():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a, b, stray = node_class("a"), node_class(), node_class()
+        tree.add(a)
+
+        # Nothing is applied when any operation in the transaction is invalid
+        with pytest.raises(NotInTreeError):
+            with tree.transaction() as tx:
+                tx.set_data(a, "changed")
+                tx.add(b, a)
+                tx.add(node_class(), stray)
+
+        assert ids(tree.root.children) == [a.id]
+        assert tree.find_by_id(a.id).children == []
+        assert tree.find_by_id(a.id).data == "a"
+        assert b.parent is None
+
+def test_transaction_discarded_on_exception():
+
+    for tree, node_class in ((Tree(), Node), (TreeMap(), NodeMap)):
+        a = node_class()
+
+        with pytest.raises(RuntimeError):
+            with tree.transaction() as tx:
+                tx.add(a)
+                raise RuntimeError("abort")
+
+        assert tree.root.children == []
@@ -0,0 +1,62 @@
+import pytest
+
+from pyo3Tree import Tree, TreeMap, Node, NodeMap, MalformedInputError, NotInTreeError
+
+data = {
+    "id": "root",
+    "children": [
+        {
+            "id": "a",
+            "children": [
+                {"id": "c"},
+                {"id": "d", "children": [{"id": "e"}]},
+            ]
+        },
+        {"id": "b"},
+    ]
+}
+
+def ids(nodes):
+    return [node.id for node in nodes]
+
+def test_orders():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        assert ids(tree.iter()) == ["root", "a", "c", "d", "e", "b"]
+        assert ids(tree.iter(order="post")) == ["c", "e", "d", "a", "b", "root"]
+        assert ids(tree.iter(order="breadth")) == ["root", "a", "b", "c", "d", "e"]
+        assert [ids(level) for level in tree.iter(order="level")] == [["root"], ["a", "b"], ["c", "d"], ["e"]]
+
+def test_start_and_max_depth():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        a = tree.find_by_id("a")
+
+        assert ids(tree.iter(start=a)) == ["a", "c", "d", "e"]
+        assert ids(tree.iter(order="post", start=a, max_depth=1)) == ["c", "d", "a"]
+        assert ids(tree.iter(order="breadth", max_depth=0)) == ["root"]
+        assert [ids(level) for level in tree.iter(order="level", max_depth=1)] == [["root"], ["a", "b"]]
+
+def test_iter_is_lazy():
+
+    for tree in (Tree.load(data), TreeMap.load(data)):
+        walk = tree.iter()
+
+        assert iter(walk) is walk
+        assert next(walk).id == "root"
+        assert next(walk).id == "a"
+
+def test_unknown_order():
+
+    tree = TreeMap.load(data)
+
+    with pytest.raises(MalformedInputError):
+        tree.iter(order="sideways")
+
+def test_start_outside_tree():
+
+    for tree, node_type in ((Tree.load(data), Node), (TreeMap.load(data), NodeMap)):
+        with pytest.raises(NotInTreeError):
+            tree.iter(start=node_type())
+        with pytest.raises(NotInTreeError):
+            tree.render(start=node_type())
@@ -0,0 +1,106 @@
+use pyo3::prelude::*;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyTuple, PyType};
+use tree_rs::{HookError as HookError_rs, TreeError as TreeError_rs};
+
+create_exception!(pyo3Tree, TreeError, PyException, "Base class of every error raised by pyo3Tree.");
+create_exception!(pyo3Tree, AfterHookError, TreeError, "An after hook raised once the change had been made, the change is kept. The hook's exception is the __cause__.");
+
+// The specific errors derive from TreeError and from the builtin python code would already catch,
+// create_exception! only takes one base so they are built with type() on first use.
+struct ExceptionTypes {
+    node_not_found: Py<PyType>,
+    cycle: Py<PyType>,
+    duplicate_id: Py<PyType>,
+    malformed_input: Py<PyType>,
+    not_in_tree: Py<PyType>,
+    attached_elsewhere: Py<PyType>,
+    root_node: Py<PyType>,
+    checkpoint_not_found: Py<PyType>,
+    history_disabled: Py<PyType>,
+    history_cleared: Py<PyType>,
+}
+
+static EXCEPTION_TYPES: GILOnceCell<ExceptionTypes> = GILOnceCell::new();
+
+fn new_exception_type(py: Python, name: &str, builtin: Bound<PyType>) -> Py<PyType> {
+    let bases = PyTuple::new_bound(py, [py.get_type_bound::<TreeError>(), builtin]);
+    let namespace = PyDict::new_bound(py);
+    namespace.set_item("__module__", "pyo3Tree").unwrap();
+    py.get_type_bound::<PyType>()
+        .call1((name, bases, namespace)).unwrap()
+        .downcast_into::<PyType>().unwrap()
+        .unbind()
+}
+
+fn exception_types<'py>(py: Python<'py>) -> &'py ExceptionTypes {
+    EXCEPTION_TYPES.get_or_init(py, || ExceptionTypes {
+        node_not_found: new_exception_type(py, "NodeNotFoundError", py.get_type_bound::<PyKeyError>()),
+        cycle: new_exception_type(py, "CycleError", py.get_type_bound::<PyValueError>()),
+        duplicate_id: new_exception_type(py, "DuplicateIdError", py.get_type_bound::<PyValueError>()),
+        malformed_input: new_exception_type(py, "MalformedInputError", py.get_type_bound::<PyValueError>()),
+        not_in_tree: new_exception_type(py, "NotInTreeError", py.get_type_bound::<PyValueError>()),
+        attached_elsewhere: new_exception_type(py, "AttachedElsewhereError", py.get_type_bound::<PyValueError>()),
+        root_node: new_exception_type(py, "RootNodeError", py.get_type_bound::<PyValueError>()),
+        checkpoint_not_found: new_exception_type(py, "CheckpointNotFoundError", py.get_type_bound::<PyKeyError>()),
+        history_disabled: new_exception_type(py, "HistoryDisabledError", py.get_type_bound::<PyValueError>()),
+        history_cleared: new_exception_type(py, "HistoryClearedError", py.get_type_bound::<PyValueError>()),
+    })
+}
+
+pub fn to_py_err(err: TreeError_rs) -> PyErr {
+    Python::with_gil(|py| {
+        let types = exception_types(py);
+        let exception_type = match err {
+            TreeError_rs::NodeNotFound(_) => &types.node_not_found,
+            TreeError_rs::CycleDetected(_) => &types.cycle,
+            TreeError_rs::DuplicateId(_) => &types.duplicate_id,
+            TreeError_rs::MalformedInput(_) => &types.malformed_input,
+            TreeError_rs::NotInTree(_) => &types.not_in_tree,
+            TreeError_rs::AttachedElsewhere(_) => &types.attached_elsewhere,
+            TreeError_rs::RootNode(_) => &types.root_node,
+            TreeError_rs::CheckpointNotFound(_) => &types.checkpoint_not_found,
+            TreeError_rs::HistoryDisabled => &types.history_disabled,
+            TreeError_rs::HistoryCleared => &types.history_cleared,
+        };
+        PyErr::from_type_bound(exception_type.bind(py).clone(), err.to_string())
+    })
+}
+
+// Hooks registered from python fail with the exception the callback raised, it is passed on as it is
+pub fn hook_err(err: HookError_rs) -> PyErr {
+    match err.downcast::<PyErr>() {
+        Ok(err) => *err,
+        Err(err) => PyRuntimeError::new_err(err.to_string())
+    }
+}
+
+// After hooks run once the change has been made, their exception is raised as the __cause__ of an
+// AfterHookError so the caller can tell the change went through
+pub fn after_hook_err(err: HookError_rs) -> PyErr {
+    let cause = hook_err(err);
+    Python::with_gil(|py| {
+        let err = AfterHookError::new_err(format!("the change was made but an after hook raised {}", cause));
+        err.set_cause(py, Some(cause));
+        err
+    })
+}
+
+pub fn add_exceptions(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("TreeError", py.get_type_bound::<TreeError>())?;
+    m.add("AfterHookError", py.get_type_bound::<AfterHookError>())?;
+    let types = exception_types(py);
+    m.add("NodeNotFoundError", types.node_not_found.bind(py))?;
+    m.add("CycleError", types.cycle.bind(py))?;
+    m.add("DuplicateIdError", types.duplicate_id.bind(py))?;
+    m.add("MalformedInputError", types.malformed_input.bind(py))?;
+    m.add("NotInTreeError", types.not_in_tree.bind(py))?;
+    m.add("AttachedElsewhereError", types.attached_elsewhere.bind(py))?;
+    m.add("RootNodeError", types.root_node.bind(py))?;
+    m.add("CheckpointNotFoundError", types.checkpoint_not_found.bind(py))?;
+    m.add("HistoryDisabledError", types.history_disabled.bind(py))?;
+    m.add("HistoryClearedError", types.history_cleared.bind(py))?;
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+use serde_json::{Map, Number, Value};
+use tree_rs::TreeError as TreeError_rs;
+
+// Converts a payload the way the json module would, without going through it. Anything json.dumps
+// would refuse by default is an error rather than being turned into a string.
+pub fn py_to_json(value: &Bound<PyAny>) -> Result<Value, TreeError_rs> {
+    convert(value, &mut HashSet::new())
+}
+
+// containers holds the lists, tuples and dicts being converted around value, meeting one of them
+// again is a reference cycle
+fn convert(value: &Bound<PyAny>, containers: &mut HashSet<usize>) -> Result<Value, TreeError_rs> {
+    if value.is_none() {
+        return Ok(Value::Null);
+    } else if let Ok(value) = value.downcast::<PyBool>() {
+        return Ok(Value::Bool(value.is_true()));
+    } else if value.is_instance_of::<PyLong>() {
+        return if let Ok(value) = value.extract::<i64>() {
+            Ok(Value::from(value))
+        } else {
+            value.extract::<u64>().map(Value::from).map_err(|_| not_json("an int too large for JSON"))
+        };
+    } else if let Ok(value) = value.downcast::<PyFloat>() {
+        return Number::from_f64(value.value()).map(Value::Number).ok_or_else(|| not_json("nan and infinity are not valid JSON"));
+    } else if let Ok(value) = value.downcast::<PyString>() {
+        return Ok(Value::String(value.to_cow().map_err(|_| not_json("a str that is not valid unicode"))?.to_string()));
+    }
+
+    let address = value.as_ptr() as usize;
+    if !containers.insert(address) {
+        return Err(not_json("circular reference detected"));
+    }
+    let converted = if let Ok(value) = value.downcast::<PyList>() {
+        value.iter().map(|item| convert(&item, containers)).collect::<Result<Vec<_>, _>>().map(Value::Array)
+    } else if let Ok(value) = value.downcast::<PyTuple>() {
+        value.iter().map(|item| convert(&item, containers)).collect::<Result<Vec<_>, _>>().map(Value::Array)
+    } else if let Ok(value) = value.downcast::<PyDict>() {
+        let mut object = Map::with_capacity(value.len());
+        value.iter().try_for_each(|(key, item)| {
+            let key = key.downcast::<PyString>().map_err(|_| not_json("dict keys have to be str"))?;
+            object.insert(key.to_cow().map_err(|_| not_json("a str that is not valid unicode"))?.to_string(), convert(&item, containers)?);
+            Ok(())
+        }).map(|_| Value::Object(object))
+    } else {
+        let type_name = value.get_type().name().map(|name| name.to_string()).unwrap_or_else(|_| "?".to_string());
+        Err(not_json(&format!("{} is not JSON serializable", type_name)))
+    };
+    containers.remove(&address);
+    converted
+}
+
+fn not_json(message: &str) -> TreeError_rs {
+    TreeError_rs::MalformedInput(message.to_string())
+}
+
+pub fn json_to_py(py: Python, value: Value) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Bool(value) => value.into_py(py),
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(value), _) => value.into_py(py),
+            (None, Some(value)) => value.into_py(py),
+            _ => number.as_f64().unwrap().into_py(py)
+        },
+        Value::String(value) => value.into_py(py),
+        Value::Array(items) => PyList::new_bound(py, items.into_iter().map(|item| json_to_py(py, item))).into_py(py),
+        Value::Object(object) => {
+            let dict = PyDict::new_bound(py);
+            for (key, item) in object {
+                dict.set_item(key, json_to_py(py, item)).unwrap();
+            }
+            dict.into_py(py)
+        }
+    }
+}
+
+// A str holding a JSON object is parsed as it is, any other str or path-like names a file
+pub fn read_json(source: &Bound<PyAny>) -> PyResult<String> {
+    if let Ok(text) = source.downcast::<PyString>() {
+        let text = text.to_cow()?;
+        if text.trim_start().starts_with('{') {
+            return Ok(text.to_string());
+        }
+    }
+    let path: PathBuf = source.extract()?;
+    Ok(std::fs::read_to_string(path)?)
+}
+
+// Writes json to path and returns None, without a path the json is returned
+pub fn write_json(path: Option<PathBuf>, json: String) -> PyResult<Option<String>> {
+    match path {
+        Some(path) => {
+            std::fs::write(path, json)?;
+            Ok(None)
+        },
+        None => Ok(Some(json))
+    }
+}
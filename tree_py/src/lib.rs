@@ -1,17 +1,19 @@
-use std::collections::{HashMap, VecDeque};
+// The extension module has to be called pyo3Tree for the python import to resolve
+#![allow(non_snake_case)]
+
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex, RwLock};
 use pyo3::{prelude::*, PyObject, Python, ToPyObject};
-use pyo3::types::{PyDict, PyList};
-use tree_rs::{Node as Node_rs, Tree as Tree_rs, NodeMap as NodeMap_rs, TreeMap as TreeMap_rs};
-
-use dashmap::DashMap;
-use lazy_static::lazy_static;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use tree_rs::{Commit, DiagramOptions as DiagramOptions_rs, Direction as Direction_rs, Event as Event_rs, Hook as Hook_rs, HookError as HookError_rs, Hooked, Journal as Journal_rs, Op as Op_rs, Record as Record_rs, RenderOptions as RenderOptions_rs, RenderStyle as RenderStyle_rs, TreeError as TreeError_rs, Transaction as Transaction_rs, Undo, get_nodemap_data, set_nodemap_data};
+use tree_rs::py::{PyNode as Node_rs, PyTree as Tree_rs, PyNodeMap as NodeMap_rs, PyTreeMap as TreeMap_rs};
+use tree_rs::traversal::{NodeChildren, PreOrderIter};
 
-lazy_static! {
-    static ref DATA_MAP: DashMap<String, PyObject> = DashMap::new();
-    // TODO create a node cache for node_wrapper generation and pass Python only a weak reference, take ownership from this cache when added to the tree.
-    static ref TREE_MAP: Arc<RwLock<TreeMap_rs>> = Arc::new(RwLock::new(TreeMap_rs::new(None)));
-}
+mod errors;
+use errors::{add_exceptions, after_hook_err, hook_err, to_py_err};
+mod json;
+use json::{json_to_py, py_to_json, read_json, write_json};
 
 #[pyclass]
 #[pyo3(name = "TreeMap")]
@@ -22,134 +24,596 @@ impl TreeMapWrapper {
     #[new]
     fn new(root: Option<NodeMapWrapper>) -> Self {
         match root {
-            Some(wrapped_node) => {
-                let node = wrapped_node.0;
-                let new_tree = TreeMap_rs::new(Some(node));
-                TREE_MAP.write().unwrap().nodes = new_tree.nodes;
-                TreeMapWrapper(TREE_MAP.clone())
-            },
-            None => TreeMapWrapper(TREE_MAP.clone())
+            Some(wrapped_node) => TreeMapWrapper(TreeMap_rs::new(Some(wrapped_node.0))),
+            None => TreeMapWrapper(TreeMap_rs::new(None))
         }
     }
 
     #[getter]
     fn get_root(&self) -> PyResult<NodeMapWrapper> {
-        Ok(NodeMapWrapper(self.0.read().unwrap().nodes.read().unwrap().get("root").unwrap().clone()))
+        Ok(NodeMapWrapper(self.0.read().unwrap().root()))
     }
 
     fn add(&self, child: NodeMapWrapper, parent_node: Option<NodeMapWrapper>) -> PyResult<()>{
-        let result = match parent_node {
-            Some(parent) => {self.0.write().unwrap().add_child(&child.0, Some(&parent.0))},
-            None => {self.0.write().unwrap().add_child(&child.0, None)}
-        };
-
-        match result {
-            Ok(()) => Ok(()),
-            Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to add child: {}",e)))
-        }
+        let parent = parent_node.map(|parent| parent.0);
+        let ops = vec![Op_rs::Add {child: child.0.clone(), parent: parent.clone(), index: None}];
+        self.hooked(ops, |tree| tree.add_child(&child.0, parent.as_ref()))
     }
 
     pub fn find_by_id(&self, id: String) -> PyResult<NodeMapWrapper> {
-        Ok(NodeMapWrapper(self.0.read().unwrap().find_by_id(&id).unwrap()))
+        let node = self.0.read().unwrap().find_by_id(&id).map_err(to_py_err)?;
+        Ok(NodeMapWrapper(node))
     }
 
-    pub fn move_node(&self, tgt_node: NodeMapWrapper, new_parent_node: NodeMapWrapper) -> PyResult<()> {
-        match self.0.write().unwrap().move_node(&tgt_node.0, &new_parent_node.0) {
-            Ok(()) => return Ok(()),
-            Err(e) => return Err(pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to move node: {}",e)))
-        };
+    pub fn insert_child(&self, parent: NodeMapWrapper, child: NodeMapWrapper, index: usize) -> PyResult<()> {
+        let ops = vec![Op_rs::Add {child: child.0.clone(), parent: Some(parent.0.clone()), index: Some(index)}];
+        self.hooked(ops, |tree| tree.insert_child(&parent.0, &child.0, index))
+    }
+
+    // pairs is any iterable of (node, parent) tuples, parent None means the root
+    pub fn add_many(&self, pairs: &Bound<PyAny>) -> PyResult<()> {
+        let pairs: Vec<(NodeMapWrapper, Option<NodeMapWrapper>)> = extract_batch(pairs, "(node, parent)")?;
+        let pairs: Vec<_> = pairs.into_iter().map(|(child, parent)| (child.0, parent.map(|parent| parent.0))).collect();
+        let ops = pairs.iter().map(|(child, parent)| Op_rs::Add {child: child.clone(), parent: parent.clone(), index: None}).collect();
+        self.hooked(ops, |tree| tree.add_many(&pairs))
+    }
+
+    // records is any iterable of (id, parent_id, data) tuples, returns the new nodes
+    pub fn extend(&self, records: &Bound<PyAny>) -> PyResult<Vec<NodeMapWrapper>> {
+        let records = extract_batch(records, "(id, parent_id, data)")?;
+        let pairs = self.0.read().unwrap().pairs_from_records(records).map_err(to_py_err)?;
+        let ops = pairs.iter().map(|(child, parent)| Op_rs::Add {child: child.clone(), parent: parent.clone(), index: None}).collect();
+        self.hooked(ops, |tree| tree.add_many(&pairs))?;
+        Ok(pairs.into_iter().map(|(node, _)| NodeMapWrapper(node)).collect())
+    }
+
+    #[pyo3(signature = (tgt_node, new_parent_node, index=None))]
+    pub fn move_node(&self, tgt_node: NodeMapWrapper, new_parent_node: NodeMapWrapper, index: Option<usize>) -> PyResult<()> {
+        let ops = vec![Op_rs::Move {node: tgt_node.0.clone(), parent: new_parent_node.0.clone(), index}];
+        self.hooked(ops, |tree| match index {
+            Some(index) => tree.move_node_at(&tgt_node.0, &new_parent_node.0, index),
+            None => tree.move_node(&tgt_node.0, &new_parent_node.0)
+        })
+    }
+
+    #[pyo3(signature = (node, keep_children=false))]
+    pub fn remove(&self, node: NodeMapWrapper, keep_children: bool) -> PyResult<()> {
+        let ops = vec![Op_rs::Remove {node: node.0.clone(), keep_children}];
+        self.hooked(ops, |tree| tree.remove_node(&node.0, keep_children))
+    }
+
+    // The hooks see a remove for the node and every node below it
+    pub fn detach_subtree(&self, node: NodeMapWrapper) -> PyResult<TreeMapWrapper> {
+        let ops = vec![Op_rs::Remove {node: node.0.clone(), keep_children: false}];
+        let subtree = self.hooked(ops, |tree| tree.detach_subtree(&node.0))?;
+        Ok(TreeMapWrapper(subtree))
     }
 
     pub fn get_ancestors(&self, node: NodeMapWrapper) -> PyResult<Vec<NodeMapWrapper>> {
-        if let Ok(ancestors) = self.0.read().unwrap().get_ancestors(&node.0){
-            let ancestors_nodemap: Vec<NodeMapWrapper> = ancestors
-                .into_iter()
-                .map(NodeMapWrapper)
-                .collect();
+        let ancestors = self.0.read().unwrap().get_ancestors(&node.0).map_err(to_py_err)?;
+        Ok(ancestors
+            .into_iter()
+            .map(NodeMapWrapper)
+            .collect())
+    }
 
-            return Ok(ancestors_nodemap);
-        }
-        return Err(pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to get ancestors for node with id: {}", node.0.read().unwrap().id.clone())))
+    pub fn get_descendants(&self, node: NodeMapWrapper) -> PyResult<Vec<NodeMapWrapper>> {
+        let descendants = self.0.read().unwrap().get_descendants(&node.0).map_err(to_py_err)?;
+        Ok(descendants.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    pub fn get_leaves(&self, node: NodeMapWrapper) -> PyResult<Vec<NodeMapWrapper>> {
+        let leaves = self.0.read().unwrap().get_leaves(&node.0).map_err(to_py_err)?;
+        Ok(leaves.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    pub fn subtree_size(&self, node: NodeMapWrapper) -> PyResult<usize> {
+        self.0.read().unwrap().subtree_size(&node.0).map_err(to_py_err)
+    }
+
+    pub fn is_leaf(&self, node: NodeMapWrapper) -> PyResult<bool> {
+        self.0.read().unwrap().is_leaf(&node.0).map_err(to_py_err)
+    }
+
+    pub fn is_root(&self, node: NodeMapWrapper) -> PyResult<bool> {
+        self.0.read().unwrap().is_root(&node.0).map_err(to_py_err)
+    }
+
+    pub fn depth(&self, node: NodeMapWrapper) -> PyResult<usize> {
+        self.0.read().unwrap().depth(&node.0).map_err(to_py_err)
+    }
+
+    pub fn height(&self, node: NodeMapWrapper) -> PyResult<usize> {
+        self.0.read().unwrap().height(&node.0).map_err(to_py_err)
+    }
+
+    pub fn nodes_at_level(&self, level: usize) -> PyResult<Vec<NodeMapWrapper>> {
+        let nodes = self.0.read().unwrap().nodes_at_level(level);
+        Ok(nodes.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    pub fn path_to_root(&self, node: NodeMapWrapper) -> PyResult<Vec<NodeMapWrapper>> {
+        let path = self.0.read().unwrap().path_to_root(&node.0).map_err(to_py_err)?;
+        Ok(path.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    pub fn lowest_common_ancestor(&self, a: NodeMapWrapper, b: NodeMapWrapper) -> PyResult<NodeMapWrapper> {
+        let ancestor = self.0.read().unwrap().lowest_common_ancestor(&a.0, &b.0).map_err(to_py_err)?;
+        Ok(NodeMapWrapper(ancestor))
+    }
+
+    pub fn path_between(&self, a: NodeMapWrapper, b: NodeMapWrapper) -> PyResult<Vec<NodeMapWrapper>> {
+        let path = self.0.read().unwrap().path_between(&a.0, &b.0).map_err(to_py_err)?;
+        Ok(path.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    pub fn siblings(&self, node: NodeMapWrapper) -> PyResult<Vec<NodeMapWrapper>> {
+        let siblings = self.0.read().unwrap().siblings(&node.0).map_err(to_py_err)?;
+        Ok(siblings.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    pub fn index_in_parent(&self, node: NodeMapWrapper) -> PyResult<Option<usize>> {
+        self.0.read().unwrap().index_in_parent(&node.0).map_err(to_py_err)
+    }
+
+    pub fn next_sibling(&self, node: NodeMapWrapper) -> PyResult<Option<NodeMapWrapper>> {
+        let sibling = self.0.read().unwrap().next_sibling(&node.0).map_err(to_py_err)?;
+        Ok(sibling.map(NodeMapWrapper))
+    }
+
+    pub fn prev_sibling(&self, node: NodeMapWrapper) -> PyResult<Option<NodeMapWrapper>> {
+        let sibling = self.0.read().unwrap().prev_sibling(&node.0).map_err(to_py_err)?;
+        Ok(sibling.map(NodeMapWrapper))
+    }
+
+    pub fn first_child(&self, node: NodeMapWrapper) -> PyResult<Option<NodeMapWrapper>> {
+        let child = self.0.read().unwrap().first_child(&node.0).map_err(to_py_err)?;
+        Ok(child.map(NodeMapWrapper))
+    }
+
+    pub fn last_child(&self, node: NodeMapWrapper) -> PyResult<Option<NodeMapWrapper>> {
+        let child = self.0.read().unwrap().last_child(&node.0).map_err(to_py_err)?;
+        Ok(child.map(NodeMapWrapper))
+    }
+
+    pub fn reorder_children(&self, parent: NodeMapWrapper, ids: Vec<String>) -> PyResult<()> {
+        let ops = vec![Op_rs::Reorder {parent: parent.0.clone(), ids: ids.clone()}];
+        self.hooked(ops, |tree| tree.reorder_children(&parent.0, &ids))
     }
 
     #[staticmethod]
     pub fn load(py: Python, python_tree: &Bound<PyDict>) -> PyResult<Self> {
-        // Clear data
-        DATA_MAP.clear();
         // Define queue as containing a node (defined as a PyDict) and its parent_id
         let mut queue: VecDeque<(Bound<PyDict>,Arc<RwLock<NodeMap_rs>>)> = VecDeque::new();
         // initial parent is 'root'
-        let id = extract_id_from_pyobject(python_tree);
-        import_node_data_from_pyobject(py, &id, python_tree);
-        let root: Arc<RwLock<NodeMap_rs>> = Arc::new(RwLock::new(NodeMap_rs {id, children: Vec::with_capacity(5), parent: None}));
+        let id = extract_id_from_pyobject(python_tree)?;
+        let data = import_node_data_from_pyobject(py, python_tree);
+        let root: Arc<RwLock<NodeMap_rs>> = loaded_nodemap(id, data);
         
         // Create the tree with this root node
         let tree = TreeMap_rs::new(Some(root.clone()));
-        let children_dicts = extract_children_from_pyobject(python_tree.clone());
+        let tree_guard = tree.read().unwrap();
+        let children_dicts = extract_children_from_pyobject(python_tree.clone())?;
             queue.extend(children_dicts.iter().map(|child| (child.clone(), root.clone())));
 
         while let Some((obj,parent)) = queue.pop_front() {
-            let id = extract_id_from_pyobject(&obj);
-            import_node_data_from_pyobject(py, &id, &obj);
-            let node: Arc<RwLock<NodeMap_rs>> = Arc::new(RwLock::new(NodeMap_rs {id, children: Vec::with_capacity(5), parent: None}));
-            tree.add_child(&node, Some(&parent)).unwrap();
+            let id = extract_id_from_pyobject(&obj)?;
+            let data = import_node_data_from_pyobject(py, &obj);
+            let node: Arc<RwLock<NodeMap_rs>> = loaded_nodemap(id, data);
+            tree_guard.add_child(&node, Some(&parent)).map_err(to_py_err)?;
 
-            let children_dicts = extract_children_from_pyobject(obj);
+            let children_dicts = extract_children_from_pyobject(obj)?;
             queue.extend(children_dicts.iter().map(|child| (child.clone(), node.clone())));
 
         };
+        drop(tree_guard);
+
+        Ok(TreeMapWrapper(tree))
+    }
+
+    // Buffers mutations until the with block ends, then applies all of them or none
+    pub fn transaction(&self) -> TreeMapTransaction {
+        TreeMapTransaction {tree: self.0.clone(), transaction: Transaction_rs::new()}
+    }
+
+    // callback(event) runs before every add, insert_child, add_many, extend, move_node, remove, data
+    // change, reorder_children, committed transaction, undo, redo and restore_checkpoint, raising in
+    // it cancels the change. Returns an id for remove_hook.
+    pub fn on_before_change(&self, callback: PyObject) -> u64 {
+        self.0.read().unwrap().add_before_hook(py_hook(callback))
+    }
+
+    // callback(event) runs once the change has been made
+    pub fn on_change(&self, callback: PyObject) -> u64 {
+        self.0.read().unwrap().add_after_hook(py_hook(callback))
+    }
+
+    pub fn remove_hook(&self, hook_id: u64) -> bool {
+        self.0.read().unwrap().remove_hook(hook_id)
+    }
+
+    // Records every mutation from here on, keeping the last limit entries for undo
+    #[pyo3(signature = (limit=100))]
+    pub fn enable_history(&self, limit: usize) {
+        self.0.write().unwrap().enable_history(limit);
+    }
+
+    pub fn disable_history(&self) {
+        self.0.write().unwrap().disable_history();
+    }
+
+    // undo, redo and restore_checkpoint go through the hooks with the events of what they revert
+    pub fn undo(&self) -> PyResult<bool> {
+        let ops = self.0.read().unwrap().undo_ops().map_err(to_py_err)?;
+        self.hooked(ops, |tree| tree.undo())
+    }
+
+    pub fn redo(&self) -> PyResult<bool> {
+        let ops = self.0.read().unwrap().redo_ops().map_err(to_py_err)?;
+        self.hooked(ops, |tree| tree.redo())
+    }
+
+    pub fn checkpoint(&self, name: &str) -> PyResult<()> {
+        self.0.write().unwrap().checkpoint(name).map_err(to_py_err)
+    }
+
+    pub fn restore_checkpoint(&self, name: &str) -> PyResult<()> {
+        let ops = self.0.read().unwrap().restore_ops(name).map_err(to_py_err)?;
+        self.hooked(ops, |tree| tree.restore_checkpoint(name))
+    }
+
+    // The entries undo can step back through, oldest first, each a list of (kind, node) tuples
+    #[getter]
+    pub fn get_history(&self) -> PyResult<Vec<Vec<(&'static str, NodeMapWrapper)>>> {
+        let history = self.0.write().unwrap().history().map_err(to_py_err)?;
+        Ok(history.into_iter().map(|entry| entry.into_iter().map(|(kind, node)| (kind, NodeMapWrapper(node))).collect()).collect())
+    }
+
+    // Logs every mutation from here on, journal() returns the log as bytes
+    pub fn enable_journal(&self) -> PyResult<()> {
+        self.0.write().unwrap().enable_journal().map_err(to_py_err)
+    }
+
+    pub fn disable_journal(&self) {
+        self.0.write().unwrap().disable_journal();
+    }
+
+    // Sequence number of the last logged mutation, None while the journal is off
+    #[getter]
+    pub fn get_journal_seq(&self) -> Option<u64> {
+        self.0.read().unwrap().journal_seq()
+    }
+
+    // The mutations logged after since as bytes for apply and replay, payloads are pickled
+    #[pyo3(signature = (since=0))]
+    pub fn journal(&self, py: Python, since: u64) -> PyResult<Option<PyObject>> {
+        // Pickling runs python code, the tree is not locked while it does
+        let Some(journal) = self.0.read().unwrap().journal(since) else {
+            return Ok(None);
+        };
+        let bytes = journal.to_bytes(|data| pickle_dumps(py, data)).map_err(to_py_err)?;
+        Ok(Some(PyBytes::new_bound(py, &bytes).into()))
+    }
+
+    // Makes the mutations of a journal, all of them or none. Hooks do not run for them.
+    pub fn apply(&self, py: Python, log: &[u8]) -> PyResult<()> {
+        let journal = Journal_rs::from_bytes(log, |bytes| pickle_loads(py, bytes)).map_err(to_py_err)?;
+        self.0.write().unwrap().apply(&journal).map_err(to_py_err)
+    }
 
-        TREE_MAP.write().unwrap().nodes = tree.nodes;
-        Ok(TreeMapWrapper(TREE_MAP.clone()))
+    // Builds the tree a journal that was started on a new tree describes
+    #[staticmethod]
+    pub fn replay(py: Python, log: &[u8]) -> PyResult<Self> {
+        let journal = Journal_rs::from_bytes(log, |bytes| pickle_loads(py, bytes)).map_err(to_py_err)?;
+        TreeMap_rs::replay(&journal).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // Lazy walk over the tree, order is one of "pre", "post", "breadth" or "level"
+    #[pyo3(signature = (order="pre", start=None, max_depth=None))]
+    pub fn iter(&self, order: &str, start: Option<NodeMapWrapper>, max_depth: Option<usize>) -> PyResult<TreeMapIterator> {
+        let tree_guard = self.0.read().unwrap();
+        let start = start.map(|node| node.0);
+        let walk: Walk<Arc<RwLock<NodeMap_rs>>> = match order {
+            "pre" => Walk::Nodes(Box::new(tree_guard.pre_order(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            "post" => Walk::Nodes(Box::new(tree_guard.post_order(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            "breadth" => Walk::Nodes(Box::new(tree_guard.breadth_first(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            "level" => Walk::Levels(Box::new(tree_guard.level_order(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            _ => return Err(to_py_err(unknown_order(order)))
+        };
+        Ok(TreeMapIterator(walk))
     }
 
     pub fn export(&self, py: Python) -> PyResult<PyObject> {
-        Ok(set_py_dict_recursively_map(py, self.0.read().unwrap().nodes.read().unwrap().get("root").unwrap()))
+        let tree_guard = self.0.read().unwrap();
+        Ok(set_py_dict_recursively_map(py, &tree_guard, &tree_guard.root()))
+    }
+
+    // export straight to JSON, written to path when there is one, otherwise returned as a str
+    #[pyo3(signature = (path=None, indent=None))]
+    pub fn to_json(&self, py: Python, path: Option<PathBuf>, indent: Option<usize>) -> PyResult<Option<String>> {
+        let json = self.0.read().unwrap().to_json_with(indent, &|data| py_to_json(data.bind(py))).map_err(to_py_err)?;
+        write_json(path, json)
+    }
+
+    // Builds a tree from a JSON str in the layout of export, or from the file a path names
+    #[staticmethod]
+    pub fn from_json(py: Python, source: &Bound<PyAny>) -> PyResult<Self> {
+        let json = read_json(source)?;
+        TreeMap_rs::from_json_with(&json, |value| Ok(json_to_py(py, value))).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // Builds a tree from flat rows in any order, see extract_records. The one row without a
+    // parent_id becomes the root.
+    #[staticmethod]
+    pub fn from_records(records: &Bound<PyAny>) -> PyResult<Self> {
+        TreeMap_rs::from_records(extract_records(records)?).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // Every node as an (id, parent_id, data) tuple, parents before their children
+    pub fn to_records(&self) -> Vec<Record_rs<PyObject>> {
+        self.0.read().unwrap().to_records()
+    }
+
+    // Writes the tree to path in the binary snapshot format, encoder(data) -> bytes defaults to pickle.dumps
+    #[pyo3(signature = (path, encoder=None))]
+    pub fn save_snapshot(&self, py: Python, path: PathBuf, encoder: Option<PyObject>) -> PyResult<()> {
+        let bytes = self.0.read().unwrap().to_snapshot(|data| encode_data(py, encoder.as_ref(), data)).map_err(to_py_err)?;
+        Ok(std::fs::write(path, bytes)?)
+    }
+
+    // Reads a tree save_snapshot wrote, decoder(bytes) -> data defaults to pickle.loads
+    #[staticmethod]
+    #[pyo3(signature = (path, decoder=None))]
+    pub fn load_snapshot(py: Python, path: PathBuf, decoder: Option<PyObject>) -> PyResult<Self> {
+        let bytes = std::fs::read(path)?;
+        TreeMap_rs::from_snapshot(&bytes, |bytes| decode_data(py, decoder.as_ref(), bytes)).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // (id, lft, rgt) nested set numbers for every node, parents before their children
+    pub fn nested_sets(&self) -> Vec<(String, usize, usize)> {
+        self.0.read().unwrap().nested_sets()
+    }
+
+    // Builds a tree from (id, lft, rgt) or (id, lft, rgt, data) rows in any order
+    #[staticmethod]
+    pub fn from_nested_sets(rows: &Bound<PyAny>) -> PyResult<Self> {
+        TreeMap_rs::from_nested_sets(extract_nested_sets(rows)?).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // The "/root/a/b" path of every node, parents before their children
+    #[pyo3(signature = (separator="/"))]
+    pub fn materialized_paths(&self, separator: &str) -> PyResult<Vec<String>> {
+        self.0.read().unwrap().materialized_paths(separator).map_err(to_py_err)
+    }
+
+    // Builds a tree from path strs or (path, data) tuples, the last id of a path is the node's
+    #[staticmethod]
+    #[pyo3(signature = (rows, separator="/"))]
+    pub fn from_materialized_paths(rows: &Bound<PyAny>, separator: &str) -> PyResult<Self> {
+        TreeMap_rs::from_materialized_paths(extract_paths(rows)?, separator).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // (ancestor, descendant, depth) rows, every node is its own ancestor at depth 0
+    pub fn closure_table(&self) -> Vec<(String, String, usize)> {
+        self.0.read().unwrap().closure_table()
+    }
+
+    // Builds a tree without data from the rows of closure_table
+    #[staticmethod]
+    pub fn from_closure_table(rows: &Bound<PyAny>) -> PyResult<Self> {
+        let rows = extract_batch::<(String, String, usize)>(rows, "(ancestor, descendant, depth)")?;
+        TreeMap_rs::from_closure_table(&rows).map(TreeMapWrapper).map_err(to_py_err)
+    }
+
+    // The tree as a Graphviz digraph, see diagram_options and node_label
+    #[pyo3(signature = (label=None, max_depth=None, direction="TB", highlight=None))]
+    pub fn to_dot(&self, py: Python, label: Option<PyObject>, max_depth: Option<usize>, direction: &str, highlight: Option<&Bound<PyAny>>) -> PyResult<String> {
+        let options = diagram_options(max_depth, direction, highlight)?;
+        // label may use the tree, so it is called once the tree is unlocked
+        let subtree = self.0.read().unwrap().subtree(None, options.max_depth).map_err(to_py_err)?;
+        subtree.to_dot(&options, |node| node_label(py, label.as_ref(), NodeMapWrapper(node.clone()).into_py(py), || {
+            let id = node.read().unwrap().id.clone();
+            (self.0.read().unwrap().get_data(&id), id)
+        })).map_err(to_py_err)
+    }
+
+    // The tree as a Mermaid flowchart, takes the same arguments as to_dot
+    #[pyo3(signature = (label=None, max_depth=None, direction="TB", highlight=None))]
+    pub fn to_mermaid(&self, py: Python, label: Option<PyObject>, max_depth: Option<usize>, direction: &str, highlight: Option<&Bound<PyAny>>) -> PyResult<String> {
+        let options = diagram_options(max_depth, direction, highlight)?;
+        // label may use the tree, so it is called once the tree is unlocked
+        let subtree = self.0.read().unwrap().subtree(None, options.max_depth).map_err(to_py_err)?;
+        subtree.to_mermaid(&options, |node| node_label(py, label.as_ref(), NodeMapWrapper(node.clone()).into_py(py), || {
+            let id = node.read().unwrap().id.clone();
+            (self.0.read().unwrap().get_data(&id), id)
+        })).map_err(to_py_err)
+    }
+
+    // The tree, or the subtree under start, as an outline drawn with box-drawing characters or ascii.
+    // label is as for to_dot, sort_key(node) orders the children of every node.
+    #[pyo3(signature = (start=None, label=None, max_depth=None, sort_key=None, ascii=false))]
+    pub fn render(&self, py: Python, start: Option<NodeMapWrapper>, label: Option<PyObject>, max_depth: Option<usize>, sort_key: Option<PyObject>, ascii: bool) -> PyResult<String> {
+        let options = RenderOptions_rs {max_depth, style: if ascii { RenderStyle_rs::Ascii } else { RenderStyle_rs::BoxDrawing }};
+        let mut sort = |children: Vec<Arc<RwLock<NodeMap_rs>>>| {
+            let children = sort_children(py, sort_key.as_ref().unwrap(), children.into_iter().map(NodeMapWrapper).collect())?;
+            Ok(children.into_iter().map(|child| child.0).collect())
+        };
+        // label and sort_key may use the tree, so they are called once it is unlocked
+        let subtree = self.0.read().unwrap().subtree(start.map(|node| node.0).as_ref(), options.max_depth).map_err(to_py_err)?;
+        subtree.render(options.style, |node| node_label(py, label.as_ref(), NodeMapWrapper(node.clone()).into_py(py), || {
+            let id = node.read().unwrap().id.clone();
+            (self.0.read().unwrap().get_data(&id), id)
+        }), sort_key.is_some().then_some(&mut sort)).map_err(to_py_err)
+    }
+
+    fn __str__(&self, py: Python) -> PyResult<String> {
+        let outline = self.render(py, None, None, None, None, false)?;
+        Ok(outline.strip_suffix('\n').unwrap_or(&outline).to_string())
     }
 }
 
-fn import_node_data_from_pyobject(py: Python, id: &String, obj: &Bound<PyDict>) {
-    match obj.get_item("data") {
-        Ok(Some(value)) => (DATA_MAP.insert(id.clone(),value.to_object(py))),
-        Ok(None) => None,
-        Err(err) => None,
+fn pickle_dumps(py: Python, data: &PyObject) -> Result<Vec<u8>, TreeError_rs> {
+    py.import_bound("pickle").and_then(|pickle| Ok(pickle.call_method1("dumps", (data,))?.downcast::<PyBytes>()?.as_bytes().to_vec()))
+        .map_err(|err| TreeError_rs::MalformedInput(format!("cannot pickle data: {}", err)))
+}
+
+fn pickle_loads(py: Python, bytes: &[u8]) -> Result<PyObject, TreeError_rs> {
+    py.import_bound("pickle").and_then(|pickle| pickle.call_method1("loads", (PyBytes::new_bound(py, bytes),)))
+        .map(|data| data.unbind())
+        .map_err(|err| TreeError_rs::MalformedInput(format!("cannot unpickle data: {}", err)))
+}
+
+// Snapshot payloads go through encoder / decoder when one is given, otherwise through pickle
+fn encode_data(py: Python, encoder: Option<&PyObject>, data: &PyObject) -> Result<Vec<u8>, TreeError_rs> {
+    let Some(encoder) = encoder else {
+        return pickle_dumps(py, data);
     };
+    encoder.call1(py, (data,)).and_then(|bytes| Ok(bytes.downcast_bound::<PyBytes>(py)?.as_bytes().to_vec()))
+        .map_err(|err| TreeError_rs::MalformedInput(format!("encoder has to return bytes: {}", err)))
 }
 
-fn extract_id_from_pyobject(obj: &Bound<PyDict>) -> String {
-    let id = match obj.get_item("id") {
-        Ok(Some(value)) => value.extract::<String>().map_err(|_| pyo3::exceptions::PyTypeError::new_err("Failed to extract 'id'")),
-        Ok(None) => Err(pyo3::exceptions::PyTypeError::new_err("'id' key not found")),
-        Err(err) => Err(err),
+fn decode_data(py: Python, decoder: Option<&PyObject>, bytes: &[u8]) -> Result<PyObject, TreeError_rs> {
+    let Some(decoder) = decoder else {
+        return pickle_loads(py, bytes);
     };
+    decoder.call1(py, (PyBytes::new_bound(py, bytes),))
+        .map_err(|err| TreeError_rs::MalformedInput(format!("decoder failed: {}", err)))
+}
+
+// The payload rides on the detached node until add_child moves it into the tree's data store
+fn import_node_data_from_pyobject(py: Python, obj: &Bound<PyDict>) -> Option<PyObject> {
+    match obj.get_item("data") {
+        Ok(Some(value)) => Some(value.to_object(py)),
+        _ => None,
+    }
+}
+
+// A detached NodeMap with the id and data read from a dict
+fn loaded_nodemap(id: String, data: Option<PyObject>) -> Arc<RwLock<NodeMap_rs>> {
+    let node = NodeMap_rs::new();
+    {
+        let mut node_guard = node.write().unwrap();
+        node_guard.id = id;
+        node_guard.data = data;
+    }
+    node
+}
 
-    return id.unwrap()
+fn extract_id_from_pyobject(obj: &Bound<PyDict>) -> PyResult<String> {
+    match obj.get_item("id")? {
+        Some(value) => value.extract::<String>().map_err(|_| to_py_err(TreeError_rs::MalformedInput("'id' must be a string".to_string()))),
+        None => Err(to_py_err(TreeError_rs::MalformedInput("'id' key not found".to_string()))),
+    }
 }
 
-fn extract_children_from_pyobject(obj: Bound<PyDict>) -> Vec<Bound<PyDict>> {
-    let children = match obj.get_item("children") {
-        Ok(Some(value)) => value.extract::<Vec<Bound<PyDict>>>(),
-        Ok(None) => Ok(Vec::new()),
-        Err(err) => Err(err),
+// Collects an iterable of tuples up front, so the tree is only locked once everything has been read
+fn extract_batch<'py, R: FromPyObject<'py>>(items: &Bound<'py, PyAny>, shape: &str) -> PyResult<Vec<R>> {
+    items.iter()?
+        .map(|item| item?.extract::<R>().map_err(|_| to_py_err(TreeError_rs::MalformedInput(format!("every item must be a {} tuple", shape)))))
+        .collect()
+}
+
+// Rows for from_records, each an (id, parent_id, data) or (id, parent_id) tuple or a dict with
+// "id", "parent_id" and "data" keys where parent_id and data may be left out
+fn extract_records(records: &Bound<PyAny>) -> PyResult<Vec<Record_rs<PyObject>>> {
+    let malformed = || to_py_err(TreeError_rs::MalformedInput("every record must be an (id, parent_id, data) tuple or a dict with an 'id'".to_string()));
+    records.iter()?.map(|item| {
+        let item = item?;
+        if let Ok(row) = item.downcast::<PyDict>() {
+            let id = extract_id_from_pyobject(row)?;
+            let parent_id = match row.get_item("parent_id")? {
+                Some(parent_id) => parent_id.extract::<Option<String>>().map_err(|_| malformed())?,
+                None => None
+            };
+            let data = row.get_item("data")?.filter(|data| !data.is_none()).map(|data| data.unbind());
+            Ok((id, parent_id, data))
+        } else if let Ok((id, parent_id)) = item.extract::<(String, Option<String>)>() {
+            Ok((id, parent_id, None))
+        } else {
+            item.extract::<Record_rs<PyObject>>().map_err(|_| malformed())
+        }
+    }).collect()
+}
+
+// Rows for from_nested_sets, (id, lft, rgt) or (id, lft, rgt, data) tuples
+#[allow(clippy::type_complexity)]
+fn extract_nested_sets(rows: &Bound<PyAny>) -> PyResult<Vec<(String, usize, usize, Option<PyObject>)>> {
+    rows.iter()?.map(|item| {
+        let item = item?;
+        if let Ok((id, lft, rgt)) = item.extract::<(String, usize, usize)>() {
+            Ok((id, lft, rgt, None))
+        } else {
+            item.extract::<(String, usize, usize, Option<PyObject>)>()
+                .map_err(|_| to_py_err(TreeError_rs::MalformedInput("every row must be an (id, lft, rgt) or (id, lft, rgt, data) tuple".to_string())))
+        }
+    }).collect()
+}
+
+// Rows for from_materialized_paths, path strs or (path, data) tuples
+fn extract_paths(rows: &Bound<PyAny>) -> PyResult<Vec<(String, Option<PyObject>)>> {
+    rows.iter()?.map(|item| {
+        let item = item?;
+        if let Ok(path) = item.extract::<String>() {
+            Ok((path, None))
+        } else {
+            item.extract::<(String, Option<PyObject>)>()
+                .map_err(|_| to_py_err(TreeError_rs::MalformedInput("every row must be a path or a (path, data) tuple".to_string())))
+        }
+    }).collect()
+}
+
+// Options of to_dot and to_mermaid, highlight is an iterable of node ids
+fn diagram_options(max_depth: Option<usize>, direction: &str, highlight: Option<&Bound<PyAny>>) -> PyResult<DiagramOptions_rs> {
+    let highlight = match highlight {
+        Some(highlight) => extract_batch::<String>(highlight, "node id")?.into_iter().collect(),
+        None => HashSet::new(),
     };
+    Ok(DiagramOptions_rs {max_depth, direction: Direction_rs::parse(direction).map_err(to_py_err)?, highlight})
+}
 
-    return children.unwrap()
+// The text shown for a node in diagrams and outlines, str(label(node)) when a callable is given,
+// otherwise str(data) or the id for a node without data
+fn node_label(py: Python, label: Option<&PyObject>, node: PyObject, data_and_id: impl FnOnce() -> (Option<PyObject>, String)) -> Result<String, TreeError_rs> {
+    let text = match label {
+        Some(label) => label.call1(py, (node,)),
+        None => match data_and_id() {
+            (Some(data), _) => Ok(data),
+            (None, id) => return Ok(id),
+        },
+    };
+    text.and_then(|text| Ok(text.bind(py).str()?.to_cow()?.into_owned()))
+        .map_err(|err| TreeError_rs::MalformedInput(format!("cannot label node: {}", err)))
 }
 
-fn set_py_dict_recursively_map(py: Python, node: &Arc<RwLock<NodeMap_rs>>) -> PyObject {
-    let node_lock = node.read().unwrap();
+// Orders children for render as list.sort(key=sort_key) would
+fn sort_children<W>(py: Python, sort_key: &PyObject, children: Vec<W>) -> Result<Vec<W>, TreeError_rs> where W: IntoPy<PyObject> + for<'py> FromPyObject<'py> {
+    let children = PyList::new_bound(py, children.into_iter().map(|child| child.into_py(py)));
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("key", sort_key).and_then(|_| children.call_method("sort", (), Some(&kwargs)))
+        .and_then(|_| children.extract::<Vec<W>>())
+        .map_err(|err| TreeError_rs::MalformedInput(format!("cannot sort children: {}", err)))
+}
+
+fn extract_children_from_pyobject(obj: Bound<PyDict>) -> PyResult<Vec<Bound<PyDict>>> {
+    match obj.get_item("children")? {
+        Some(value) => value.extract::<Vec<Bound<PyDict>>>().map_err(|_| to_py_err(TreeError_rs::MalformedInput("'children' must be a list of dicts".to_string()))),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn set_py_dict_recursively_map(py: Python, tree: &TreeMap_rs, node: &Arc<RwLock<NodeMap_rs>>) -> PyObject {
+    let id = node.read().unwrap().id.clone();
     let py_dict = PyDict::new_bound(py);
 
-    py_dict.set_item("id", node_lock.id.clone()).unwrap();
+    py_dict.set_item("id", id.clone()).unwrap();
 
-    if let Some(data) = DATA_MAP.get(&node_lock.id) {
-        py_dict.set_item("data", data.clone()).unwrap();
+    if let Some(data) = tree.get_data(&id) {
+        py_dict.set_item("data", data).unwrap();
     }
 
-    if node_lock.children.len() > 0 {
-        let children_list = PyList::new_bound(py, node_lock.children.iter().map(|child| {
-            set_py_dict_recursively_map(py, TREE_MAP.read().unwrap().nodes.read().unwrap().get(child).unwrap())
-        }));
+    let children = tree.children(node).unwrap();
+    if !children.is_empty() {
+        let children_list = PyList::new_bound(py, children.iter().map(|child| set_py_dict_recursively_map(py, tree, child)));
         py_dict.set_item("children", children_list).unwrap();
     }   
 
@@ -164,11 +628,8 @@ struct TreeWrapper(Arc<Mutex<Tree_rs>>);
 #[pymethods]
 impl TreeWrapper {
     #[new]
-    fn new(root: Option<NodeWrapper>) -> Self {
-        match root {
-            Some(wrapped_node) => TreeWrapper(Tree_rs::new(Some(wrapped_node.0.clone()))),
-            None => TreeWrapper(Tree_rs::new(None))
-        }
+    fn new(root: Option<NodeWrapper>) -> PyResult<Self> {
+        Ok(TreeWrapper(Tree_rs::new(root.map(|wrapped_node| wrapped_node.0)).map_err(to_py_err)?))
     }
 
     #[getter]
@@ -178,24 +639,58 @@ impl TreeWrapper {
     }
 
     pub fn add(&self, child: NodeWrapper, parent: Option<NodeWrapper>) -> PyResult<()> {
-        match parent {
-            Some(parent_node) => {self.0.lock().unwrap().add_child(child.0.clone(), Some(parent_node.0.clone()))},
-            None => {self.0.lock().unwrap().add_child(child.0.clone(), None)}
-        }
-        Ok(())
+        let parent = parent.map(|parent| parent.0);
+        let ops = vec![Op_rs::Add {child: child.0.clone(), parent: parent.clone(), index: None}];
+        self.hooked(ops, |tree| tree.add_child(child.0.clone(), parent))
     }
 
     pub fn find_by_id(&self, id: String) -> PyResult<NodeWrapper> {
-        Ok(NodeWrapper(self.0.lock().unwrap().find_by_id(&id).unwrap()))
+        match self.0.lock().unwrap().find_by_id(&id) {
+            Some(node) => Ok(NodeWrapper(node)),
+            None => Err(to_py_err(TreeError_rs::NodeNotFound(id)))
+        }
+    }
+
+    pub fn insert_child(&self, parent: NodeWrapper, child: NodeWrapper, index: usize) -> PyResult<()> {
+        let ops = vec![Op_rs::Add {child: child.0.clone(), parent: Some(parent.0.clone()), index: Some(index)}];
+        self.hooked(ops, |tree| tree.insert_child(&parent.0, &child.0, index))
+    }
+
+    // pairs is any iterable of (node, parent) tuples, parent None means the root
+    pub fn add_many(&self, pairs: &Bound<PyAny>) -> PyResult<()> {
+        let pairs: Vec<(NodeWrapper, Option<NodeWrapper>)> = extract_batch(pairs, "(node, parent)")?;
+        let pairs: Vec<_> = pairs.into_iter().map(|(child, parent)| (child.0, parent.map(|parent| parent.0))).collect();
+        let ops = pairs.iter().map(|(child, parent)| Op_rs::Add {child: child.clone(), parent: parent.clone(), index: None}).collect();
+        self.hooked(ops, |tree| tree.add_many(&pairs))
     }
 
-    pub fn move_node(&self, tgt_node: NodeWrapper, new_parent_node: NodeWrapper) -> PyResult<()> {
-        self.0.lock().unwrap().move_node(&tgt_node.0, &new_parent_node.0);
-        Ok(())
+    // records is any iterable of (id, parent_id, data) tuples, returns the new nodes
+    pub fn extend(&self, records: &Bound<PyAny>) -> PyResult<Vec<NodeWrapper>> {
+        let records = extract_batch(records, "(id, parent_id, data)")?;
+        let pairs = self.0.lock().unwrap().pairs_from_records(records).map_err(to_py_err)?;
+        let ops = pairs.iter().map(|(child, parent)| Op_rs::Add {child: child.clone(), parent: parent.clone(), index: None}).collect();
+        self.hooked(ops, |tree| tree.add_many(&pairs))?;
+        Ok(pairs.into_iter().map(|(node, _)| NodeWrapper(node)).collect())
+    }
+
+    #[pyo3(signature = (tgt_node, new_parent_node, index=None))]
+    pub fn move_node(&self, tgt_node: NodeWrapper, new_parent_node: NodeWrapper, index: Option<usize>) -> PyResult<()> {
+        let ops = vec![Op_rs::Move {node: tgt_node.0.clone(), parent: new_parent_node.0.clone(), index}];
+        self.hooked(ops, |tree| match index {
+            Some(index) => tree.move_node_at(&tgt_node.0, &new_parent_node.0, index),
+            None => tree.move_node(&tgt_node.0, &new_parent_node.0)
+        })
+    }
+
+    #[pyo3(signature = (node, keep_children=false))]
+    pub fn remove(&self, node: NodeWrapper, keep_children: bool) -> PyResult<NodeWrapper> {
+        let ops = vec![Op_rs::Remove {node: node.0.clone(), keep_children}];
+        self.hooked(ops, |tree| tree.remove_node(&node.0, keep_children))
+            .map(NodeWrapper)
     }
 
     pub fn get_ancestors(&self, node: NodeWrapper) -> PyResult<Vec<NodeWrapper>> {
-        let ancestors = &self.0.lock().unwrap().get_ancestors(&node.0);
+        let ancestors = &self.0.lock().unwrap().get_ancestors(&node.0).map_err(to_py_err)?;
         let mut wrapped_ancestors: Vec<NodeWrapper> = vec![];
         for ancestor in ancestors.iter(){
             wrapped_ancestors.push(NodeWrapper(ancestor.clone()));
@@ -203,16 +698,316 @@ impl TreeWrapper {
         Ok(wrapped_ancestors)
     }
 
+    pub fn get_descendants(&self, node: NodeWrapper) -> PyResult<Vec<NodeWrapper>> {
+        let descendants = self.0.lock().unwrap().get_descendants(&node.0).map_err(to_py_err)?;
+        Ok(descendants.into_iter().map(NodeWrapper).collect())
+    }
+
+    pub fn get_leaves(&self, node: NodeWrapper) -> PyResult<Vec<NodeWrapper>> {
+        let leaves = self.0.lock().unwrap().get_leaves(&node.0).map_err(to_py_err)?;
+        Ok(leaves.into_iter().map(NodeWrapper).collect())
+    }
+
+    pub fn subtree_size(&self, node: NodeWrapper) -> PyResult<usize> {
+        self.0.lock().unwrap().subtree_size(&node.0).map_err(to_py_err)
+    }
+
+    pub fn is_leaf(&self, node: NodeWrapper) -> PyResult<bool> {
+        self.0.lock().unwrap().is_leaf(&node.0).map_err(to_py_err)
+    }
+
+    pub fn is_root(&self, node: NodeWrapper) -> PyResult<bool> {
+        self.0.lock().unwrap().is_root(&node.0).map_err(to_py_err)
+    }
+
+    pub fn depth(&self, node: NodeWrapper) -> PyResult<usize> {
+        self.0.lock().unwrap().depth(&node.0).map_err(to_py_err)
+    }
+
+    pub fn height(&self, node: NodeWrapper) -> PyResult<usize> {
+        self.0.lock().unwrap().height(&node.0).map_err(to_py_err)
+    }
+
+    pub fn nodes_at_level(&self, level: usize) -> PyResult<Vec<NodeWrapper>> {
+        let nodes = self.0.lock().unwrap().nodes_at_level(level);
+        Ok(nodes.into_iter().map(NodeWrapper).collect())
+    }
+
+    pub fn path_to_root(&self, node: NodeWrapper) -> PyResult<Vec<NodeWrapper>> {
+        let path = self.0.lock().unwrap().path_to_root(&node.0).map_err(to_py_err)?;
+        Ok(path.into_iter().map(NodeWrapper).collect())
+    }
+
+    pub fn lowest_common_ancestor(&self, a: NodeWrapper, b: NodeWrapper) -> PyResult<NodeWrapper> {
+        let ancestor = self.0.lock().unwrap().lowest_common_ancestor(&a.0, &b.0).map_err(to_py_err)?;
+        Ok(NodeWrapper(ancestor))
+    }
+
+    pub fn path_between(&self, a: NodeWrapper, b: NodeWrapper) -> PyResult<Vec<NodeWrapper>> {
+        let path = self.0.lock().unwrap().path_between(&a.0, &b.0).map_err(to_py_err)?;
+        Ok(path.into_iter().map(NodeWrapper).collect())
+    }
+
+    pub fn siblings(&self, node: NodeWrapper) -> PyResult<Vec<NodeWrapper>> {
+        let siblings = self.0.lock().unwrap().siblings(&node.0).map_err(to_py_err)?;
+        Ok(siblings.into_iter().map(NodeWrapper).collect())
+    }
+
+    pub fn index_in_parent(&self, node: NodeWrapper) -> PyResult<Option<usize>> {
+        self.0.lock().unwrap().index_in_parent(&node.0).map_err(to_py_err)
+    }
+
+    pub fn next_sibling(&self, node: NodeWrapper) -> PyResult<Option<NodeWrapper>> {
+        Ok(self.0.lock().unwrap().next_sibling(&node.0).map_err(to_py_err)?.map(NodeWrapper))
+    }
+
+    pub fn prev_sibling(&self, node: NodeWrapper) -> PyResult<Option<NodeWrapper>> {
+        Ok(self.0.lock().unwrap().prev_sibling(&node.0).map_err(to_py_err)?.map(NodeWrapper))
+    }
+
+    pub fn first_child(&self, node: NodeWrapper) -> PyResult<Option<NodeWrapper>> {
+        Ok(self.0.lock().unwrap().first_child(&node.0).map_err(to_py_err)?.map(NodeWrapper))
+    }
+
+    pub fn last_child(&self, node: NodeWrapper) -> PyResult<Option<NodeWrapper>> {
+        Ok(self.0.lock().unwrap().last_child(&node.0).map_err(to_py_err)?.map(NodeWrapper))
+    }
+
+    pub fn reorder_children(&self, parent: NodeWrapper, ids: Vec<String>) -> PyResult<()> {
+        let ops = vec![Op_rs::Reorder {parent: parent.0.clone(), ids: ids.clone()}];
+        self.hooked(ops, |tree| tree.reorder_children(&parent.0, &ids))
+    }
+
     #[staticmethod]
     pub fn load(py: Python, python_tree: &Bound<PyDict>) -> PyResult<Self> {
-        let big_node = load_py_tree(py, python_tree).unwrap();
+        let big_node = load_py_tree(py, python_tree)?;
         set_parents_recursively_from_py_tree(big_node.clone(), None);
-        Ok(TreeWrapper(Tree_rs::new(Some(big_node))))
+        Ok(TreeWrapper(Tree_rs::new(Some(big_node)).map_err(to_py_err)?))
+    }
+
+    // Buffers mutations until the with block ends, then applies all of them or none
+    pub fn transaction(&self) -> TreeTransaction {
+        TreeTransaction {tree: self.0.clone(), transaction: Transaction_rs::new()}
+    }
+
+    // Unlike assigning node.data this goes into the history and through the hooks
+    pub fn set_data(&self, node: NodeWrapper, data: Option<PyObject>) -> PyResult<()> {
+        let ops = vec![Op_rs::SetData {node: node.0.clone(), data: None}];
+        self.hooked(ops, |tree| {
+            tree.set_data(&node.0, data);
+            Ok(())
+        })
+    }
+
+    // callback(event) runs before every add, insert_child, add_many, extend, move_node, remove, data
+    // change, reorder_children, committed transaction, undo, redo and restore_checkpoint, raising in
+    // it cancels the change. Returns an id for remove_hook.
+    pub fn on_before_change(&self, callback: PyObject) -> u64 {
+        self.0.lock().unwrap().add_before_hook(py_hook(callback))
+    }
+
+    // callback(event) runs once the change has been made
+    pub fn on_change(&self, callback: PyObject) -> u64 {
+        self.0.lock().unwrap().add_after_hook(py_hook(callback))
+    }
+
+    pub fn remove_hook(&self, hook_id: u64) -> bool {
+        self.0.lock().unwrap().remove_hook(hook_id)
+    }
+
+    // Records every mutation from here on, keeping the last limit entries for undo
+    #[pyo3(signature = (limit=100))]
+    pub fn enable_history(&self, limit: usize) {
+        self.0.lock().unwrap().enable_history(limit);
+    }
+
+    pub fn disable_history(&self) {
+        self.0.lock().unwrap().disable_history();
+    }
+
+    // undo, redo and restore_checkpoint go through the hooks with the events of what they revert
+    pub fn undo(&self) -> PyResult<bool> {
+        let ops = self.0.lock().unwrap().undo_ops().map_err(to_py_err)?;
+        self.hooked(ops, |tree| tree.undo())
+    }
+
+    pub fn redo(&self) -> PyResult<bool> {
+        let ops = self.0.lock().unwrap().redo_ops().map_err(to_py_err)?;
+        self.hooked(ops, |tree| tree.redo())
+    }
+
+    pub fn checkpoint(&self, name: &str) -> PyResult<()> {
+        self.0.lock().unwrap().checkpoint(name).map_err(to_py_err)
+    }
+
+    pub fn restore_checkpoint(&self, name: &str) -> PyResult<()> {
+        let ops = self.0.lock().unwrap().restore_ops(name).map_err(to_py_err)?;
+        self.hooked(ops, |tree| tree.restore_checkpoint(name))
+    }
+
+    // The entries undo can step back through, oldest first, each a list of (kind, node) tuples
+    #[getter]
+    pub fn get_history(&self) -> PyResult<Vec<Vec<(&'static str, NodeWrapper)>>> {
+        let history = self.0.lock().unwrap().history().map_err(to_py_err)?;
+        Ok(history.into_iter().map(|entry| entry.into_iter().map(|(kind, node)| (kind, NodeWrapper(node))).collect()).collect())
+    }
+
+    // Lazy walk over the tree, order is one of "pre", "post", "breadth" or "level"
+    #[pyo3(signature = (order="pre", start=None, max_depth=None))]
+    pub fn iter(&self, order: &str, start: Option<NodeWrapper>, max_depth: Option<usize>) -> PyResult<TreeIterator> {
+        let tree_guard = self.0.lock().unwrap();
+        let start = start.map(|node| node.0);
+        let walk: Walk<Arc<Mutex<Node_rs>>> = match order {
+            "pre" => Walk::Nodes(Box::new(tree_guard.pre_order(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            "post" => Walk::Nodes(Box::new(tree_guard.post_order(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            "breadth" => Walk::Nodes(Box::new(tree_guard.breadth_first(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            "level" => Walk::Levels(Box::new(tree_guard.level_order(start.as_ref(), max_depth).map_err(to_py_err)?)),
+            _ => return Err(to_py_err(unknown_order(order)))
+        };
+        Ok(TreeIterator(walk))
     }
 
     pub fn export(&self, py: Python) -> PyResult<PyObject> {
         Ok(set_py_dict_recursively(py, self.0.lock().unwrap().root.clone()))
     }
+
+    // export straight to JSON, written to path when there is one, otherwise returned as a str
+    #[pyo3(signature = (path=None, indent=None))]
+    pub fn to_json(&self, py: Python, path: Option<PathBuf>, indent: Option<usize>) -> PyResult<Option<String>> {
+        let json = self.0.lock().unwrap().to_json_with(indent, &|data| py_to_json(data.bind(py))).map_err(to_py_err)?;
+        write_json(path, json)
+    }
+
+    // Builds a tree from a JSON str in the layout of export, or from the file a path names
+    #[staticmethod]
+    pub fn from_json(py: Python, source: &Bound<PyAny>) -> PyResult<Self> {
+        let json = read_json(source)?;
+        Tree_rs::from_json_with(&json, |value| Ok(json_to_py(py, value))).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // Builds a tree from flat rows in any order, see extract_records. The one row without a
+    // parent_id becomes the root.
+    #[staticmethod]
+    pub fn from_records(records: &Bound<PyAny>) -> PyResult<Self> {
+        Tree_rs::from_records(extract_records(records)?).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // Every node as an (id, parent_id, data) tuple, parents before their children
+    pub fn to_records(&self) -> Vec<Record_rs<PyObject>> {
+        self.0.lock().unwrap().to_records()
+    }
+
+    // Writes the tree to path in the binary snapshot format, encoder(data) -> bytes defaults to pickle.dumps
+    #[pyo3(signature = (path, encoder=None))]
+    pub fn save_snapshot(&self, py: Python, path: PathBuf, encoder: Option<PyObject>) -> PyResult<()> {
+        let bytes = self.0.lock().unwrap().to_snapshot(|data| encode_data(py, encoder.as_ref(), data)).map_err(to_py_err)?;
+        Ok(std::fs::write(path, bytes)?)
+    }
+
+    // Reads a tree save_snapshot wrote, decoder(bytes) -> data defaults to pickle.loads
+    #[staticmethod]
+    #[pyo3(signature = (path, decoder=None))]
+    pub fn load_snapshot(py: Python, path: PathBuf, decoder: Option<PyObject>) -> PyResult<Self> {
+        let bytes = std::fs::read(path)?;
+        Tree_rs::from_snapshot(&bytes, |bytes| decode_data(py, decoder.as_ref(), bytes)).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // (id, lft, rgt) nested set numbers for every node, parents before their children
+    pub fn nested_sets(&self) -> Vec<(String, usize, usize)> {
+        self.0.lock().unwrap().nested_sets()
+    }
+
+    // Builds a tree from (id, lft, rgt) or (id, lft, rgt, data) rows in any order
+    #[staticmethod]
+    pub fn from_nested_sets(rows: &Bound<PyAny>) -> PyResult<Self> {
+        Tree_rs::from_nested_sets(extract_nested_sets(rows)?).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // The "/root/a/b" path of every node, parents before their children
+    #[pyo3(signature = (separator="/"))]
+    pub fn materialized_paths(&self, separator: &str) -> PyResult<Vec<String>> {
+        self.0.lock().unwrap().materialized_paths(separator).map_err(to_py_err)
+    }
+
+    // Builds a tree from path strs or (path, data) tuples, the last id of a path is the node's
+    #[staticmethod]
+    #[pyo3(signature = (rows, separator="/"))]
+    pub fn from_materialized_paths(rows: &Bound<PyAny>, separator: &str) -> PyResult<Self> {
+        Tree_rs::from_materialized_paths(extract_paths(rows)?, separator).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // (ancestor, descendant, depth) rows, every node is its own ancestor at depth 0
+    pub fn closure_table(&self) -> Vec<(String, String, usize)> {
+        self.0.lock().unwrap().closure_table()
+    }
+
+    // Builds a tree without data from the rows of closure_table
+    #[staticmethod]
+    pub fn from_closure_table(rows: &Bound<PyAny>) -> PyResult<Self> {
+        let rows = extract_batch::<(String, String, usize)>(rows, "(ancestor, descendant, depth)")?;
+        Tree_rs::from_closure_table(&rows).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // Builds a tree from Newick text, labels become the data of their nodes and branch lengths the
+    // nodes' branch_length
+    #[staticmethod]
+    pub fn from_newick(py: Python, text: &str) -> PyResult<Self> {
+        Tree_rs::from_newick(text, |label| Ok(label.into_py(py))).map(TreeWrapper).map_err(to_py_err)
+    }
+
+    // The tree as Newick text, str(data) is the label of a node
+    pub fn to_newick(&self, py: Python) -> PyResult<String> {
+        self.0.lock().unwrap().to_newick(|data| {
+            data.bind(py).str().and_then(|label| Ok(label.to_cow()?.into_owned()))
+                .map_err(|err| TreeError_rs::MalformedInput(format!("cannot turn data into a label: {}", err)))
+        }).map_err(to_py_err)
+    }
+
+    // The tree as a Graphviz digraph, see diagram_options and node_label
+    #[pyo3(signature = (label=None, max_depth=None, direction="TB", highlight=None))]
+    pub fn to_dot(&self, py: Python, label: Option<PyObject>, max_depth: Option<usize>, direction: &str, highlight: Option<&Bound<PyAny>>) -> PyResult<String> {
+        let options = diagram_options(max_depth, direction, highlight)?;
+        // label may use the tree, so it is called once the tree is unlocked
+        let subtree = self.0.lock().unwrap().subtree(None, options.max_depth).map_err(to_py_err)?;
+        subtree.to_dot(&options, |node| node_label(py, label.as_ref(), NodeWrapper(node.clone()).into_py(py), || {
+            let node_guard = node.lock().unwrap();
+            (node_guard.data.clone(), node_guard.id.clone())
+        })).map_err(to_py_err)
+    }
+
+    // The tree as a Mermaid flowchart, takes the same arguments as to_dot
+    #[pyo3(signature = (label=None, max_depth=None, direction="TB", highlight=None))]
+    pub fn to_mermaid(&self, py: Python, label: Option<PyObject>, max_depth: Option<usize>, direction: &str, highlight: Option<&Bound<PyAny>>) -> PyResult<String> {
+        let options = diagram_options(max_depth, direction, highlight)?;
+        // label may use the tree, so it is called once the tree is unlocked
+        let subtree = self.0.lock().unwrap().subtree(None, options.max_depth).map_err(to_py_err)?;
+        subtree.to_mermaid(&options, |node| node_label(py, label.as_ref(), NodeWrapper(node.clone()).into_py(py), || {
+            let node_guard = node.lock().unwrap();
+            (node_guard.data.clone(), node_guard.id.clone())
+        })).map_err(to_py_err)
+    }
+
+    // The tree, or the subtree under start, as an outline drawn with box-drawing characters or ascii.
+    // label is as for to_dot, sort_key(node) orders the children of every node.
+    #[pyo3(signature = (start=None, label=None, max_depth=None, sort_key=None, ascii=false))]
+    pub fn render(&self, py: Python, start: Option<NodeWrapper>, label: Option<PyObject>, max_depth: Option<usize>, sort_key: Option<PyObject>, ascii: bool) -> PyResult<String> {
+        let options = RenderOptions_rs {max_depth, style: if ascii { RenderStyle_rs::Ascii } else { RenderStyle_rs::BoxDrawing }};
+        let mut sort = |children: Vec<Arc<Mutex<Node_rs>>>| {
+            let children = sort_children(py, sort_key.as_ref().unwrap(), children.into_iter().map(NodeWrapper).collect())?;
+            Ok(children.into_iter().map(|child| child.0).collect())
+        };
+        // label and sort_key may use the tree, so they are called once it is unlocked
+        let subtree = self.0.lock().unwrap().subtree(start.map(|node| node.0).as_ref(), options.max_depth).map_err(to_py_err)?;
+        subtree.render(options.style, |node| node_label(py, label.as_ref(), NodeWrapper(node.clone()).into_py(py), || {
+            let node_guard = node.lock().unwrap();
+            (node_guard.data.clone(), node_guard.id.clone())
+        }), sort_key.is_some().then_some(&mut sort)).map_err(to_py_err)
+    }
+
+    fn __str__(&self, py: Python) -> PyResult<String> {
+        let outline = self.render(py, None, None, None, None, false)?;
+        Ok(outline.strip_suffix('\n').unwrap_or(&outline).to_string())
+    }
 }   
 
 fn set_parents_recursively_from_py_tree(node: Arc<Mutex<Node_rs>>, parent: Option<Arc<Mutex<Node_rs>>>) {
@@ -228,34 +1023,22 @@ fn set_parents_recursively_from_py_tree(node: Arc<Mutex<Node_rs>>, parent: Optio
 }
 
 fn load_py_tree(py:Python<'_>, obj: &Bound<PyDict>) -> PyResult<Arc<Mutex<Node_rs>>> {
-    let id = match obj.get_item("id") {
-        Ok(Some(value)) => value.extract::<String>().map_err(|_| pyo3::exceptions::PyTypeError::new_err("Failed to extract 'id'")),
-        Ok(None) => Err(pyo3::exceptions::PyTypeError::new_err("'id' key not found")),
-        Err(err) => Err(err),
-    }?;
-
-    let data = match obj.get_item("data") {
-        Ok(Some(value)) => Ok(value.to_object(py)),
-        Ok(None) => Ok(py.None()),
-        Err(err) => Err(err),
-    }?;
+    let id = extract_id_from_pyobject(obj)?;
+
+    let data = import_node_data_from_pyobject(py, obj);
 
     // parent is not expected or needed in the incoming PyObject, it is inferred from the structure
 
-    let children = match obj.get_item("children") {
-        Ok(Some(value)) => value.extract::<Vec<Bound<PyDict>>>(),
-        Ok(None) => Ok(Vec::new()),
-        Err(err) => Err(err),
-    }?;
+    let children = extract_children_from_pyobject(obj.clone())?;
 
     let node_children: Arc<Mutex<Vec<Arc<Mutex<Node_rs>>>>> = Arc::new(Mutex::new(vec![]));
-    if children.len() > 0 {
+    if !children.is_empty() {
         for item in children.iter() {
-            node_children.lock().unwrap().push(load_py_tree(py, item).unwrap())
+            node_children.lock().unwrap().push(load_py_tree(py, item)?)
         }
     }
 
-    Ok(Arc::new(Mutex::new(Node_rs{id, data, children: node_children, parent: None})))
+    Ok(Arc::new(Mutex::new(Node_rs{id, data, children: node_children, parent: None, branch_length: None, tree: None})))
 }
 
 fn set_py_dict_recursively(py: Python, node: Arc<Mutex<Node_rs>>) -> PyObject {
@@ -264,8 +1047,8 @@ fn set_py_dict_recursively(py: Python, node: Arc<Mutex<Node_rs>>) -> PyObject {
 
     py_dict.set_item("id", node_lock.id.clone()).unwrap();
 
-    if !node_lock.data.is_none(py) {
-        py_dict.set_item("data", node_lock.data.clone()).unwrap();
+    if let Some(data) = node_lock.data.as_ref() {
+        py_dict.set_item("data", data).unwrap();
     }
 
     let children_lock = node_lock.children.lock().unwrap();
@@ -284,20 +1067,12 @@ fn set_py_dict_recursively(py: Python, node: Arc<Mutex<Node_rs>>) -> PyObject {
 #[derive(Clone)]
 struct NodeMapWrapper(Arc<RwLock<NodeMap_rs>>);
 
-// impl Drop for NodeMapWrapper {
-//     fn drop(&mut self){
-//         DATA_MAP.remove(&self.0.read().unwrap().id);
-//     }
-// }
-
 #[pymethods]
 impl NodeMapWrapper {
     #[new]
     fn new(data: Option<PyObject>) -> Self {
-        let node = NodeMap_rs::new(None);
-        if let Some(value) = data {
-            DATA_MAP.insert(node.read().unwrap().id.clone(), value);
-        }
+        let node = NodeMap_rs::new();
+        node.write().unwrap().data = data;
         NodeMapWrapper(node)
     }
 
@@ -307,38 +1082,166 @@ impl NodeMapWrapper {
     }
 
     #[getter]
-    fn get_data(&self) -> PyResult<PyObject>{
-        Ok(DATA_MAP.get(&self.0.read().unwrap().id).unwrap().clone())
+    fn get_data(&self) -> PyResult<Option<PyObject>>{
+        Ok(get_nodemap_data(&self.0))
     }
 
     #[setter]
     fn set_data(&self, data: Option<PyObject>) -> PyResult<()> {
-        if let Some(value) = data {
-            DATA_MAP.insert(self.0.read().unwrap().id.clone(), value);
+        let tree = self.0.read().unwrap().get_tree();
+        match tree {
+            // On a tree the change goes through that tree's hooks
+            Some(tree) => {
+                let id = self.0.read().unwrap().id.clone();
+                let ops = vec![Op_rs::SetData {node: self.0.clone(), data: None}];
+                TreeMapWrapper(tree).hooked(ops, |tree| {
+                    tree.set_data(&id, data);
+                    Ok(())
+                })
+            },
+            None => {
+                set_nodemap_data(&self.0, data);
+                Ok(())
+            }
         }
-        Ok(())
     }
 
+    // A node that is on no tree has no relatives
     #[getter]
     fn get_children(&self) -> PyResult<Vec<NodeMapWrapper>> {
-        let mut children: Vec<NodeMapWrapper> = Vec::with_capacity(50);
+        let Some(tree) = self.0.read().unwrap().get_tree() else {
+            return Ok(Vec::new());
+        };
+        let children = tree.read().unwrap().children(&self.0).map_err(to_py_err)?;
+        Ok(children.into_iter().map(NodeMapWrapper).collect())
+    }
 
-        let tree_map_node = TREE_MAP.read().unwrap();
-        let nodes_guard = tree_map_node.nodes.read().unwrap();
+    #[getter]
+    fn get_parent(&self) -> PyResult<Option<NodeMapWrapper>> {
+        let Some(tree) = self.0.read().unwrap().get_tree() else {
+            return Ok(None);
+        };
+        let parent = tree.read().unwrap().parent(&self.0).map_err(to_py_err)?;
+        Ok(parent.map(NodeMapWrapper))
+    }
 
-        let node_guard = self.0.read().unwrap();
-        for child_id in &node_guard.children{
-            children.push(NodeMapWrapper(nodes_guard.get(child_id).unwrap().clone()));
+    #[getter]
+    fn get_is_leaf(&self) -> PyResult<bool> {
+        let Some(tree) = self.0.read().unwrap().get_tree() else {
+            return Ok(true);
         };
-        children.shrink_to_fit();
-        Ok(children)
+        let is_leaf = tree.read().unwrap().is_leaf(&self.0).map_err(to_py_err)?;
+        Ok(is_leaf)
     }
 
+    // Only the root of the TreeMap the node is on, a node that is on none raises NotInTreeError
     #[getter]
-    fn get_parent(&self) -> PyResult<NodeMapWrapper> {
-        let tree_map_node = TREE_MAP.read().unwrap();
-        let nodes_guard = tree_map_node.nodes.read().unwrap();
-        Ok(NodeMapWrapper(nodes_guard.get(self.0.read().unwrap().parent.as_ref().unwrap()).unwrap().clone()))
+    fn get_is_root(&self) -> PyResult<bool> {
+        self.get_tree()?.read().unwrap().is_root(&self.0).map_err(to_py_err)
+    }
+
+    fn get_descendants(&self) -> PyResult<Vec<NodeMapWrapper>> {
+        let descendants = self.get_tree()?.read().unwrap().get_descendants(&self.0).map_err(to_py_err)?;
+        Ok(descendants.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    fn get_leaves(&self) -> PyResult<Vec<NodeMapWrapper>> {
+        let leaves = self.get_tree()?.read().unwrap().get_leaves(&self.0).map_err(to_py_err)?;
+        Ok(leaves.into_iter().map(NodeMapWrapper).collect())
+    }
+
+    fn subtree_size(&self) -> PyResult<usize> {
+        self.get_tree()?.read().unwrap().subtree_size(&self.0).map_err(to_py_err)
+    }
+}
+
+impl TreeMapWrapper {
+    // Makes a mutation through the tree's hooks: the before hooks see the events ops would cause and
+    // can cancel it by raising, then apply makes the change under the write lock and the after hooks
+    // run. An after hook that raises does not undo the change, its exception comes out as the cause
+    // of an AfterHookError. No lock of the tree is held while a hook runs, so hooks are free to use
+    // the tree.
+    fn hooked<R>(&self, ops: Vec<Op_rs<Arc<RwLock<NodeMap_rs>>, PyObject>>, apply: impl FnOnce(&TreeMap_rs) -> Result<R, TreeError_rs>) -> PyResult<R> {
+        let hooks = self.0.read().unwrap().hooks();
+        if hooks.is_empty() {
+            return apply(&self.0.write().unwrap()).map_err(to_py_err);
+        }
+        let events = self.0.read().unwrap().events_for(&ops).map_err(to_py_err)?;
+        hooks.before(&events).map_err(hook_err)?;
+        let value = apply(&self.0.write().unwrap()).map_err(to_py_err)?;
+        hooks.after(&events).map_err(after_hook_err)?;
+        Ok(value)
+    }
+}
+
+impl NodeMapWrapper {
+    // Resolves the TreeMap this node was added to, relationships are only meaningful within that tree
+    fn get_tree(&self) -> PyResult<Arc<RwLock<TreeMap_rs>>> {
+        let node_guard = self.0.read().unwrap();
+        node_guard.get_tree().ok_or_else(|| to_py_err(TreeError_rs::NotInTree(node_guard.id.clone())))
+    }
+}
+
+impl TreeWrapper {
+    // See TreeMapWrapper::hooked
+    fn hooked<R>(&self, ops: Vec<Op_rs<Arc<Mutex<Node_rs>>, PyObject>>, apply: impl FnOnce(&Tree_rs) -> Result<R, TreeError_rs>) -> PyResult<R> {
+        let hooks = self.0.lock().unwrap().hooks();
+        if hooks.is_empty() {
+            return apply(&self.0.lock().unwrap()).map_err(to_py_err);
+        }
+        let events = self.0.lock().unwrap().events_for(&ops).map_err(to_py_err)?;
+        hooks.before(&events).map_err(hook_err)?;
+        let value = apply(&self.0.lock().unwrap()).map_err(to_py_err)?;
+        hooks.after(&events).map_err(after_hook_err)?;
+        Ok(value)
+    }
+}
+
+// Wraps a python callable as a tree hook, an exception it raises comes back out of the call that
+// made the change
+fn py_hook(callback: PyObject) -> Hook_rs {
+    Arc::new(move |event: &Event_rs| {
+        Python::with_gil(|py| {
+            callback.call1(py, (ChangeEvent::from(event),))
+                .map(drop)
+                .map_err(|err| Box::new(err) as HookError_rs)
+        })
+    })
+}
+
+// What a change does to a node, handed to the hooks. kind is "add", "move", "remove" or "set_data",
+// the indices are positions among the parent's children before (old_) and after (new_) the change.
+#[pyclass(frozen)]
+struct ChangeEvent {
+    #[pyo3(get)]
+    kind: &'static str,
+    #[pyo3(get)]
+    node_id: String,
+    #[pyo3(get)]
+    old_parent: Option<String>,
+    #[pyo3(get)]
+    new_parent: Option<String>,
+    #[pyo3(get)]
+    old_index: Option<usize>,
+    #[pyo3(get)]
+    new_index: Option<usize>,
+}
+
+impl From<&Event_rs> for ChangeEvent {
+    fn from(event: &Event_rs) -> Self {
+        let event = event.clone();
+        ChangeEvent {kind: event.kind, node_id: event.node_id, old_parent: event.old_parent, new_parent: event.new_parent, old_index: event.old_index, new_index: event.new_index}
+    }
+}
+
+#[pymethods]
+impl ChangeEvent {
+    fn __repr__(&self) -> String {
+        fn field<V: std::fmt::Debug>(value: &Option<V>) -> String {
+            value.as_ref().map_or("None".to_string(), |value| format!("{:?}", value))
+        }
+        format!("ChangeEvent(kind={:?}, node_id={:?}, old_parent={}, new_parent={}, old_index={}, new_index={})",
+            self.kind, self.node_id, field(&self.old_parent), field(&self.new_parent), field(&self.old_index), field(&self.new_index))
     }
 }
 
@@ -351,10 +1254,7 @@ struct NodeWrapper(Arc<Mutex<Node_rs>>);
 impl NodeWrapper {
     #[new]
     fn new (data: Option<PyObject>) -> Self {
-        match data {
-            Some(value) => NodeWrapper(Node_rs::new(value, None)),
-            None => NodeWrapper(Node_rs::new(py_none(),None))
-        }
+        NodeWrapper(Node_rs::new(data, None))
     }
 
     #[getter]
@@ -363,16 +1263,31 @@ impl NodeWrapper {
     }
 
     #[getter]
-    fn get_data(&self, py: Python) -> PyResult<PyObject> {
-        let node = self.0.lock().unwrap();
-        Ok(DATA_MAP.get(&node.id).unwrap().clone())
+    fn get_data(&self) -> PyResult<Option<PyObject>> {
+        Ok(self.0.lock().unwrap().data.clone())
     }
 
     #[setter]
-    fn set_data(&self, data: PyObject) -> PyResult<()> {
-        let node = self.0.lock().unwrap();
-        DATA_MAP.insert(node.id.clone(), data);
-        Ok(())
+    fn set_data(&self, data: Option<PyObject>) -> PyResult<()> {
+        match Node_rs::get_tree(&self.0) {
+            // On a tree the change goes through that tree's hooks and into its history
+            Some(tree) => TreeWrapper(tree).set_data(self.clone(), data),
+            None => {
+                self.0.lock().unwrap().data = data;
+                Ok(())
+            }
+        }
+    }
+
+    // Length of the edge to the parent, as read from and written to Newick
+    #[getter]
+    fn get_branch_length(&self) -> Option<f64> {
+        self.0.lock().unwrap().branch_length
+    }
+
+    #[setter]
+    fn set_branch_length(&self, length: Option<f64>) {
+        self.0.lock().unwrap().branch_length = length;
     }
 
     #[getter]
@@ -387,25 +1302,225 @@ impl NodeWrapper {
 
     #[getter]
     fn get_parent(&self) -> PyResult<Option<NodeWrapper>> {
-        if let Some(weak_parent) = &self.0.lock().unwrap().parent {
-            Ok(Some(NodeWrapper(weak_parent.upgrade().unwrap())))
-        } else {
-            Ok(None)
+        let parent = self.0.lock().unwrap().parent.as_ref().and_then(|weak_parent| weak_parent.upgrade());
+        Ok(parent.map(NodeWrapper))
+    }
+
+    #[getter]
+    fn get_is_leaf(&self) -> PyResult<bool> {
+        Ok(self.0.lock().unwrap().children.lock().unwrap().is_empty())
+    }
+
+    #[getter]
+    fn get_is_root(&self) -> PyResult<bool> {
+        Ok(self.get_parent()?.is_none())
+    }
+
+    fn get_descendants(&self) -> PyResult<Vec<NodeWrapper>> {
+        Ok(self.walk_subtree().skip(1).map(NodeWrapper).collect())
+    }
+
+    fn get_leaves(&self) -> PyResult<Vec<NodeWrapper>> {
+        Ok(self.walk_subtree()
+            .filter(|node| node.lock().unwrap().children.lock().unwrap().is_empty())
+            .map(NodeWrapper)
+            .collect())
+    }
+
+    fn subtree_size(&self) -> PyResult<usize> {
+        Ok(self.walk_subtree().count())
+    }
+}
+
+impl NodeWrapper {
+    // A Node carries its own children, its subtree can be walked without the Tree it is on
+    fn walk_subtree(&self) -> PreOrderIter<NodeChildren<PyObject>> {
+        PreOrderIter::new(NodeChildren::new(), self.0.clone(), None)
+    }
+}
+
+// Collects mutations for a TreeMap, used as `with tree.transaction() as tx:`. Leaving the block
+// normally commits them under the tree's write lock, an exception inside the block discards them.
+#[pyclass]
+struct TreeMapTransaction {
+    tree: Arc<RwLock<TreeMap_rs>>,
+    transaction: Transaction_rs<Arc<RwLock<NodeMap_rs>>, PyObject>,
+}
+
+#[pymethods]
+impl TreeMapTransaction {
+    #[pyo3(signature = (child, parent_node=None))]
+    fn add(&mut self, child: NodeMapWrapper, parent_node: Option<NodeMapWrapper>) {
+        self.transaction.add_child(child.0, parent_node.map(|parent| parent.0));
+    }
+
+    fn insert_child(&mut self, parent: NodeMapWrapper, child: NodeMapWrapper, index: usize) {
+        self.transaction.insert_child(parent.0, child.0, index);
+    }
+
+    #[pyo3(signature = (tgt_node, new_parent_node, index=None))]
+    fn move_node(&mut self, tgt_node: NodeMapWrapper, new_parent_node: NodeMapWrapper, index: Option<usize>) {
+        match index {
+            Some(index) => self.transaction.move_node_at(tgt_node.0, new_parent_node.0, index),
+            None => self.transaction.move_node(tgt_node.0, new_parent_node.0)
+        };
+    }
+
+    #[pyo3(signature = (node, keep_children=false))]
+    fn remove(&mut self, node: NodeMapWrapper, keep_children: bool) {
+        self.transaction.remove_node(node.0, keep_children);
+    }
+
+    fn set_data(&mut self, node: NodeMapWrapper, data: Option<PyObject>) {
+        self.transaction.set_data(node.0, data);
+    }
+
+    fn reorder_children(&mut self, parent: NodeMapWrapper, ids: Vec<String>) {
+        self.transaction.reorder_children(parent.0, ids);
+    }
+
+    // Goes through the tree's hooks with the events of every collected op
+    fn commit(&mut self) -> PyResult<()> {
+        let transaction = std::mem::take(&mut self.transaction);
+        let ops = transaction.ops().iter().map(Op_rs::without_data).collect();
+        TreeMapWrapper(self.tree.clone()).hooked(ops, |tree| tree.commit(transaction))
+    }
+
+    fn __len__(&self) -> usize {
+        self.transaction.len()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, exc_type: Option<&Bound<PyAny>>, _exc_value: Option<&Bound<PyAny>>, _traceback: Option<&Bound<PyAny>>) -> PyResult<bool> {
+        match exc_type {
+            None => self.commit()?,
+            Some(_) => self.transaction = Transaction_rs::new()
         }
+        Ok(false)
     }
 }
 
-pub fn py_none() -> PyObject {
-    Python::with_gil(|py| {
-        py.None().to_object(py)
-    })
+// Collects mutations for a Tree, see TreeMapTransaction
+#[pyclass]
+struct TreeTransaction {
+    tree: Arc<Mutex<Tree_rs>>,
+    transaction: Transaction_rs<Arc<Mutex<Node_rs>>, PyObject>,
+}
+
+#[pymethods]
+impl TreeTransaction {
+    #[pyo3(signature = (child, parent=None))]
+    fn add(&mut self, child: NodeWrapper, parent: Option<NodeWrapper>) {
+        self.transaction.add_child(child.0, parent.map(|parent| parent.0));
+    }
+
+    fn insert_child(&mut self, parent: NodeWrapper, child: NodeWrapper, index: usize) {
+        self.transaction.insert_child(parent.0, child.0, index);
+    }
+
+    #[pyo3(signature = (tgt_node, new_parent_node, index=None))]
+    fn move_node(&mut self, tgt_node: NodeWrapper, new_parent_node: NodeWrapper, index: Option<usize>) {
+        match index {
+            Some(index) => self.transaction.move_node_at(tgt_node.0, new_parent_node.0, index),
+            None => self.transaction.move_node(tgt_node.0, new_parent_node.0)
+        };
+    }
+
+    #[pyo3(signature = (node, keep_children=false))]
+    fn remove(&mut self, node: NodeWrapper, keep_children: bool) {
+        self.transaction.remove_node(node.0, keep_children);
+    }
+
+    fn set_data(&mut self, node: NodeWrapper, data: Option<PyObject>) {
+        self.transaction.set_data(node.0, data);
+    }
+
+    fn reorder_children(&mut self, parent: NodeWrapper, ids: Vec<String>) {
+        self.transaction.reorder_children(parent.0, ids);
+    }
+
+    // Goes through the tree's hooks with the events of every collected op
+    fn commit(&mut self) -> PyResult<()> {
+        let transaction = std::mem::take(&mut self.transaction);
+        let ops = transaction.ops().iter().map(Op_rs::without_data).collect();
+        TreeWrapper(self.tree.clone()).hooked(ops, |tree| tree.commit(transaction))
+    }
+
+    fn __len__(&self) -> usize {
+        self.transaction.len()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, exc_type: Option<&Bound<PyAny>>, _exc_value: Option<&Bound<PyAny>>, _traceback: Option<&Bound<PyAny>>) -> PyResult<bool> {
+        match exc_type {
+            None => self.commit()?,
+            Some(_) => self.transaction = Transaction_rs::new()
+        }
+        Ok(false)
+    }
+}
+
+// Single nodes for the pre, post and breadth walks, whole levels for the level walk
+enum Walk<N> {
+    Nodes(Box<dyn Iterator<Item = N> + Send>),
+    Levels(Box<dyn Iterator<Item = Vec<N>> + Send>),
+}
+
+fn unknown_order(order: &str) -> TreeError_rs {
+    TreeError_rs::MalformedInput(format!("unknown traversal order '{}', expected 'pre', 'post', 'breadth' or 'level'", order))
+}
+
+#[pyclass]
+struct TreeIterator(Walk<Arc<Mutex<Node_rs>>>);
+
+#[pymethods]
+impl TreeIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<PyObject> {
+        match &mut slf.0 {
+            Walk::Nodes(nodes) => nodes.next().map(|node| NodeWrapper(node).into_py(py)),
+            Walk::Levels(levels) => levels.next().map(|level| level.into_iter().map(NodeWrapper).collect::<Vec<_>>().into_py(py)),
+        }
+    }
+}
+
+#[pyclass]
+struct TreeMapIterator(Walk<Arc<RwLock<NodeMap_rs>>>);
+
+#[pymethods]
+impl TreeMapIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<PyObject> {
+        match &mut slf.0 {
+            Walk::Nodes(nodes) => nodes.next().map(|node| NodeMapWrapper(node).into_py(py)),
+            Walk::Levels(levels) => levels.next().map(|level| level.into_iter().map(NodeMapWrapper).collect::<Vec<_>>().into_py(py)),
+        }
+    }
 }
 
 #[pymodule]
-fn pyo3Tree(_: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+fn pyo3Tree(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<NodeWrapper>()?;
     m.add_class::<TreeWrapper>()?;
     m.add_class::<NodeMapWrapper>()?;
     m.add_class::<TreeMapWrapper>()?;
+    m.add_class::<TreeIterator>()?;
+    m.add_class::<TreeMapIterator>()?;
+    m.add_class::<TreeTransaction>()?;
+    m.add_class::<ChangeEvent>()?;
+    m.add_class::<TreeMapTransaction>()?;
+    add_exceptions(py, m)?;
     Ok(())
 }
\ No newline at end of file
@@ -7,73 +7,73 @@ use rand::seq::SliceRandom;
 use tree_rs::{Tree, Node};
 
 fn add_node_to_empty_tree(){
-    let tree = Tree::new(None);
-    let child_node = Node::new(None);
+    let tree = Tree::new(None).unwrap();
+    let child_node = Node::<i32>::new(None, None);
 
-    tree.lock().unwrap().add_child(child_node.clone(), None);
+    tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
     
-    let child_parent_id = child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id.clone();
+    let _child_parent_id = child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id.clone();
     
-    let root_id = tree.lock().unwrap().root.lock().unwrap().id.clone();
+    let _root_id = tree.lock().unwrap().root.lock().unwrap().id.clone();
 }
 
 fn add_many_children(){
     let n_children = 5000;
-    let mut members: Vec<Arc<Mutex<Node>>> = Vec::with_capacity(n_children);
+    let mut members: Vec<Arc<Mutex<Node<i32>>>> = Vec::with_capacity(n_children);
     let mut rng = thread_rng();
 
-    let tree = Tree::new(None);
+    let tree = Tree::new(None).unwrap();
 
-    for i in 2..n_children {
-        let child_node = Node::new(None);
-        tree.lock().unwrap().add_child(child_node.clone(), members.choose(&mut rng).map(|n| n.clone()));
+    for _i in 2..n_children {
+        let child_node = Node::<i32>::new(None, None);
+        tree.lock().unwrap().add_child(child_node.clone(), members.choose(&mut rng).cloned()).unwrap();
         members.push(child_node.clone());
     }
 }
 
 fn add_node_two_deep(){
-    let tree = Tree::new(None);
-    let child_node = Node::new(None);
-    let childs_child_node = Node::new(None);
+    let tree = Tree::new(None).unwrap();
+    let child_node = Node::<i32>::new(None, None);
+    let childs_child_node = Node::<i32>::new(None, None);
 
-    tree.lock().unwrap().add_child(child_node.clone(), None);
-    tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone()));
+    tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+    tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
 }
 
 fn find_by_id(){
-    let tree = Tree::new(None);
-    let child_node = Node::new(None);
-    let childs_child_node = Node::new(None);
+    let tree = Tree::new(None).unwrap();
+    let child_node = Node::<i32>::new(None, None);
+    let childs_child_node = Node::<i32>::new(None, None);
 
     {
-        tree.lock().unwrap().add_child(child_node.clone(), None);
-        tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone()));
+        tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+        tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
     }
 }
 
 fn get_ancestors_on_two_deep_tree(){
-    let tree = Tree::new(None);
-    let child_node = Node::new(None);
-    let childs_child_node = Node::new(None);
+    let tree = Tree::new(None).unwrap();
+    let child_node = Node::<i32>::new(None, None);
+    let childs_child_node = Node::<i32>::new(None, None);
 
-    tree.lock().unwrap().add_child(child_node.clone(), None);
-    tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone()));
+    tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+    tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
 
-    let ancestors = tree.lock().unwrap().get_ancestors(&childs_child_node);
+    black_box(tree.lock().unwrap().get_ancestors(&childs_child_node).unwrap());
 }
 
 fn move_node_from_two_deep_to_one_deep(){
-    let tree = Tree::new(None);
-    let child_node = Node::new(None);
-    let childs_child_node = Node::new(None);
+    let tree = Tree::new(None).unwrap();
+    let child_node = Node::<i32>::new(None, None);
+    let childs_child_node = Node::<i32>::new(None, None);
 
     {
         let tree_guard = tree.lock().unwrap();
-        tree_guard.add_child(child_node.clone(), None);
-        tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone()));
+        tree_guard.add_child(child_node.clone(), None).unwrap();
+        tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
 
         let root_clone = tree_guard.root.clone();
-        tree_guard.move_node(&childs_child_node, &root_clone);
+        tree_guard.move_node(&childs_child_node, &root_clone).unwrap();
     }
 }
 
@@ -81,15 +81,15 @@ fn move_node_from_two_deep_to_one_deep(){
 // add_node_two_deep_mt, find_by_id_mt, get_ancestors_on_two_deep_tree_mt
 // move_node_from_two_deep_to_one_deep_m
 fn single_functionality_benches(c: &mut Criterion) {
-    c.bench_function("add node to empty tree", |b| b.iter(|| add_node_to_empty_tree()));
-    c.bench_function("add node two deep", |b| b.iter(|| add_node_two_deep()));
-    c.bench_function("find by id", |b| b.iter(|| find_by_id()));
-    c.bench_function("get ancestors on two deep tree", |b| b.iter(|| get_ancestors_on_two_deep_tree()));
-    c.bench_function("move node from two deep to one deep", |b| b.iter(|| move_node_from_two_deep_to_one_deep()));
+    c.bench_function("add node to empty tree", |b| b.iter(add_node_to_empty_tree));
+    c.bench_function("add node two deep", |b| b.iter(add_node_two_deep));
+    c.bench_function("find by id", |b| b.iter(find_by_id));
+    c.bench_function("get ancestors on two deep tree", |b| b.iter(get_ancestors_on_two_deep_tree));
+    c.bench_function("move node from two deep to one deep", |b| b.iter(move_node_from_two_deep_to_one_deep));
 }
 
 fn bulk_runs_benches(c: &mut Criterion) {
-    c.bench_function("Create many children", |b| b.iter(|| add_many_children()));
+    c.bench_function("Create many children", |b| b.iter(add_many_children));
 }
 
 criterion_group!{
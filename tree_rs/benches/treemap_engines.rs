@@ -0,0 +1,211 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pprof::criterion::{Output, PProfProfiler};
+
+use std::sync::{Arc, RwLock};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tree_rs::{NodeMap, TreeMap};
+
+// Compares TreeMap on its arena against the HashMap storage it had before, on the same 1M node tree
+const NODES: usize = 1_000_000;
+const SEED: u64 = 7;
+
+// The storage TreeMap used before the arena: every node behind its own lock in an id map, linked to
+// its relatives by String ids. Only what the benchmarks run is kept, with the same lookups, locking
+// and cloning as the old methods.
+mod hashmap_storage {
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    pub struct HashNode {
+        pub id: String,
+        pub children: Vec<String>,
+        pub parent: Option<String>,
+    }
+
+    pub type Handle = Arc<RwLock<HashNode>>;
+
+    pub fn node(id: String) -> Handle {
+        Arc::new(RwLock::new(HashNode {id, children: Vec::with_capacity(5), parent: None}))
+    }
+
+    pub struct HashTreeMap {
+        nodes: Arc<RwLock<HashMap<String, Handle>>>,
+    }
+
+    impl HashTreeMap {
+        pub fn new(root: &Handle) -> Self {
+            let mut nodes = HashMap::with_capacity(100);
+            nodes.insert(root.read().unwrap().id.clone(), root.clone());
+            nodes.insert("root".to_string(), root.clone());
+            HashTreeMap {nodes: Arc::new(RwLock::new(nodes))}
+        }
+
+        pub fn add_child(&self, child: &Handle, parent: &Handle) -> Result<(), String> {
+            let mut nodes_guard = self.nodes.write().unwrap();
+            let child_id = child.read().unwrap().id.clone();
+            if nodes_guard.contains_key(&child_id) {
+                return Err(child_id);
+            }
+            if !contains(&nodes_guard, parent) {
+                return Err(parent.read().unwrap().id.clone());
+            }
+            nodes_guard.insert(child_id.clone(), child.clone());
+            let mut parent_guard = parent.write().unwrap();
+            parent_guard.children.push(child_id);
+            child.write().unwrap().parent = Some(parent_guard.id.clone());
+            Ok(())
+        }
+
+        pub fn find_by_id(&self, id: &str) -> Option<Handle> {
+            self.nodes.read().unwrap().get(id).cloned()
+        }
+
+        pub fn get_ancestors(&self, node: &Handle) -> Option<Vec<Handle>> {
+            if !contains(&self.nodes.read().unwrap(), node) {
+                return None;
+            }
+            let mut collection = Vec::with_capacity(50);
+            let mut current = node.clone();
+            loop {
+                let Some(parent_id) = current.read().unwrap().parent.clone() else {
+                    break;
+                };
+                let parent = self.nodes.read().unwrap().get(&parent_id).unwrap().clone();
+                collection.push(parent.clone());
+                current = parent;
+            }
+            collection.shrink_to_fit();
+            Some(collection)
+        }
+
+        pub fn move_node(&self, tgt_node: &Handle, new_parent: &Handle) -> Result<(), String> {
+            if !contains(&self.nodes.read().unwrap(), tgt_node) {
+                return Err(tgt_node.read().unwrap().id.clone());
+            }
+            let ancestors = self.get_ancestors(new_parent).ok_or_else(|| new_parent.read().unwrap().id.clone())?;
+            if Arc::ptr_eq(tgt_node, new_parent) || ancestors.iter().any(|node| Arc::ptr_eq(node, tgt_node)) {
+                return Err(tgt_node.read().unwrap().id.clone());
+            }
+
+            let mut tgt_node_guard = tgt_node.write().unwrap();
+            let tgt_node_id = tgt_node_guard.id.clone();
+            let Some(old_parent_id) = tgt_node_guard.parent.clone() else {
+                return Err(tgt_node_id);
+            };
+            {
+                let old_parent = self.nodes.read().unwrap().get(&old_parent_id).unwrap().clone();
+                let mut old_parent_guard = old_parent.write().unwrap();
+                if let Some(index) = old_parent_guard.children.iter().position(|id| id == &tgt_node_id) {
+                    old_parent_guard.children.remove(index);
+                }
+            }
+            let mut new_parent_guard = new_parent.write().unwrap();
+            new_parent_guard.children.push(tgt_node_id);
+            tgt_node_guard.parent = Some(new_parent_guard.id.clone());
+            Ok(())
+        }
+    }
+
+    fn contains(nodes: &HashMap<String, Handle>, node: &Handle) -> bool {
+        nodes.get(&node.read().unwrap().id).is_some_and(|found| Arc::ptr_eq(found, node))
+    }
+}
+
+use hashmap_storage::HashTreeMap;
+
+// Node i hangs under a random earlier node, index 0 is the root
+struct Shape {
+    parents: Vec<usize>,
+    ids: Vec<String>,
+}
+
+fn random_shape() -> Shape {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let parents = (0..=NODES).map(|i| if i == 0 {0} else {rng.gen_range(0..i)}).collect();
+    let ids = (0..=NODES).map(|i| format!("node-{}", i)).collect();
+    Shape {parents, ids}
+}
+
+#[allow(clippy::type_complexity)]
+fn build_treemap(shape: &Shape) -> (Arc<RwLock<TreeMap<i32>>>, Vec<Arc<RwLock<NodeMap<i32>>>>) {
+    let root = NodeMap::new();
+    root.write().unwrap().id = shape.ids[0].clone();
+    let tree = TreeMap::new(Some(root.clone()));
+    let mut members = Vec::with_capacity(NODES + 1);
+    members.push(root);
+    {
+        let tree_guard = tree.read().unwrap();
+        for i in 1..=NODES {
+            let node = NodeMap::new();
+            node.write().unwrap().id = shape.ids[i].clone();
+            tree_guard.add_child(&node, Some(&members[shape.parents[i]])).unwrap();
+            members.push(node);
+        }
+    }
+    (tree, members)
+}
+
+fn build_hashmap(shape: &Shape) -> (HashTreeMap, Vec<hashmap_storage::Handle>) {
+    let root = hashmap_storage::node(shape.ids[0].clone());
+    let tree = HashTreeMap::new(&root);
+    let mut members = Vec::with_capacity(NODES + 1);
+    members.push(root);
+    for i in 1..=NODES {
+        let node = hashmap_storage::node(shape.ids[i].clone());
+        tree.add_child(&node, &members[shape.parents[i]]).unwrap();
+        members.push(node);
+    }
+    (tree, members)
+}
+
+fn add_benches(c: &mut Criterion) {
+    let shape = random_shape();
+    let mut group = c.benchmark_group("add 1M nodes");
+    group.sample_size(10);
+    group.bench_function("arena", |b| b.iter_with_large_drop(|| build_treemap(&shape)));
+    group.bench_function("hashmap", |b| b.iter_with_large_drop(|| build_hashmap(&shape)));
+    group.finish();
+}
+
+fn query_benches(c: &mut Criterion) {
+    let shape = random_shape();
+    let (tree_map, tree_map_members) = build_treemap(&shape);
+    let (hash_map, hash_map_members) = build_hashmap(&shape);
+    let tree_map_guard = tree_map.read().unwrap();
+
+    let mut group = c.benchmark_group("find by id in 1M nodes");
+    let mut rng = StdRng::seed_from_u64(SEED);
+    group.bench_function("arena", |b| b.iter(|| black_box(tree_map_guard.find_by_id(&shape.ids[rng.gen_range(0..=NODES)]).unwrap())));
+    let mut rng = StdRng::seed_from_u64(SEED);
+    group.bench_function("hashmap", |b| b.iter(|| black_box(hash_map.find_by_id(&shape.ids[rng.gen_range(0..=NODES)]).unwrap())));
+    group.finish();
+
+    let mut group = c.benchmark_group("get ancestors in 1M nodes");
+    let mut rng = StdRng::seed_from_u64(SEED);
+    group.bench_function("arena", |b| b.iter(|| black_box(tree_map_guard.get_ancestors(&tree_map_members[rng.gen_range(0..=NODES)]).unwrap())));
+    let mut rng = StdRng::seed_from_u64(SEED);
+    group.bench_function("hashmap", |b| b.iter(|| black_box(hash_map.get_ancestors(&hash_map_members[rng.gen_range(0..=NODES)]).unwrap())));
+    group.finish();
+
+    // Moves into the node's own subtree fail the cycle check, which is part of the cost being measured
+    let mut group = c.benchmark_group("move node in 1M nodes");
+    let mut rng = StdRng::seed_from_u64(SEED);
+    group.bench_function("arena", |b| b.iter(|| {
+        let (tgt, parent) = (rng.gen_range(1..=NODES), rng.gen_range(0..=NODES));
+        black_box(tree_map_guard.move_node(&tree_map_members[tgt], &tree_map_members[parent]).is_ok())
+    }));
+    let mut rng = StdRng::seed_from_u64(SEED);
+    group.bench_function("hashmap", |b| b.iter(|| {
+        let (tgt, parent) = (rng.gen_range(1..=NODES), rng.gen_range(0..=NODES));
+        black_box(hash_map.move_node(&hash_map_members[tgt], &hash_map_members[parent]).is_ok())
+    }));
+    group.finish();
+}
+
+criterion_group!{
+    name = benches;
+    config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
+    targets = add_benches, query_benches
+}
+criterion_main!(benches);
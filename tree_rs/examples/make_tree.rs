@@ -8,16 +8,16 @@ use rand::seq::SliceRandom;
 
 fn main(){
     let n_children = 5000;
-    let mut members: Vec<Arc<Mutex<Node>>> = Vec::with_capacity(n_children);
+    let mut members: Vec<Arc<Mutex<Node<i32>>>> = Vec::with_capacity(n_children);
     let mut rng = thread_rng();
 
     let guard = pprof::ProfilerGuard::new(1000).unwrap();
 
-    let tree = Tree::new(None);
+    let tree = Tree::new(None).unwrap();
 
     for _i in 2..n_children {
-        let child_node = Node::new(None);
-        tree.lock().unwrap().add_child(child_node.clone(), members.choose(&mut rng).map(|n| n.clone()));
+        let child_node = Node::<i32>::new(None, None);
+        tree.lock().unwrap().add_child(child_node.clone(), members.choose(&mut rng).cloned()).unwrap();
         members.push(child_node.clone());
     }
 
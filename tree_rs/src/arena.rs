@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use crate::{insert_at, NodeMap, Result, TreeError};
+
+// Slot of a node in the arena of a TreeMap. The generation tells a live node apart from whatever
+// was later put in the same slot, so an index kept past a removal is rejected instead of aliasing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct NodeIndex {
+    index: u32,
+    generation: u32,
+}
+
+impl fmt::Display for NodeIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+// A node on the tree: the handle handed out for it and its relatives as slots of the same arena
+pub(crate) struct ArenaNode<T> {
+    pub(crate) id: String,
+    pub(crate) handle: Arc<RwLock<NodeMap<T>>>,
+    pub(crate) parent: Option<NodeIndex>,
+    pub(crate) children: Vec<NodeIndex>,
+}
+
+struct Slot<T> {
+    generation: u32,
+    node: Option<ArenaNode<T>>,
+}
+
+// Where a TreeMap keeps its nodes, all of them in one contiguous Vec. Parent and children are
+// indices rather than String ids, so walking the tree costs no hashing, locking or cloning, the id
+// map is only consulted by find_by_id and to turn away duplicate ids. Freed slots are reused by
+// later adds.
+pub(crate) struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    // The root is also found under "root"
+    ids: HashMap<String, NodeIndex>,
+    root: NodeIndex,
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new(root_id: String, root: Arc<RwLock<NodeMap<T>>>) -> Self {
+        let mut arena = Self {slots: Vec::with_capacity(100), free: Vec::new(), ids: HashMap::with_capacity(100), root: NodeIndex {index: 0, generation: 0}};
+        arena.root = arena.allocate(ArenaNode {id: root_id.clone(), handle: root, parent: None, children: Vec::new()});
+        arena.ids.insert(root_id, arena.root);
+        arena.ids.insert("root".to_string(), arena.root);
+        arena
+    }
+
+    pub(crate) fn root(&self) -> NodeIndex {
+        self.root
+    }
+
+    // Number of nodes on the tree, counting the root
+    pub(crate) fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub(crate) fn find(&self, id: &str) -> Option<NodeIndex> {
+        self.ids.get(id).copied()
+    }
+
+    pub(crate) fn contains_id(&self, id: &str) -> bool {
+        self.ids.contains_key(id)
+    }
+
+    pub(crate) fn get(&self, index: NodeIndex) -> Option<&ArenaNode<T>> {
+        self.slots.get(index.index as usize)
+            .filter(|slot| slot.generation == index.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    // For indices taken from the links of live nodes, which always resolve
+    pub(crate) fn node(&self, index: NodeIndex) -> &ArenaNode<T> {
+        self.get(index).unwrap()
+    }
+
+    pub(crate) fn node_mut(&mut self, index: NodeIndex) -> &mut ArenaNode<T> {
+        self.slots.get_mut(index.index as usize)
+            .filter(|slot| slot.generation == index.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .unwrap()
+    }
+
+    // The slot of the handle, matching on the Arc as well so an unrelated NodeMap is never on the tree
+    pub(crate) fn slot_of(&self, handle: &Arc<RwLock<NodeMap<T>>>) -> Option<NodeIndex> {
+        let index = handle.read().unwrap().index?;
+        self.get(index).filter(|node| Arc::ptr_eq(&node.handle, handle)).map(|_| index)
+    }
+
+    pub(crate) fn index_of(&self, handle: &Arc<RwLock<NodeMap<T>>>) -> Result<NodeIndex> {
+        self.slot_of(handle).ok_or_else(|| TreeError::NotInTree(handle.read().unwrap().id.clone()))
+    }
+
+    pub(crate) fn handles(&self, indices: &[NodeIndex]) -> Vec<Arc<RwLock<NodeMap<T>>>> {
+        indices.iter().map(|index| self.node(*index).handle.clone()).collect()
+    }
+
+    // Puts a node whose id is not taken under parent at position, an index past the end appends
+    pub(crate) fn insert(&mut self, id: String, handle: Arc<RwLock<NodeMap<T>>>, parent: NodeIndex, position: Option<usize>) -> NodeIndex {
+        let index = self.allocate(ArenaNode {id: id.clone(), handle, parent: Some(parent), children: Vec::new()});
+        self.ids.insert(id, index);
+        insert_at(&mut self.node_mut(parent).children, index, position);
+        index
+    }
+
+    // The parent of the node and the position it holds among the parent's children, None for the root
+    pub(crate) fn position(&self, index: NodeIndex) -> Option<(NodeIndex, usize)> {
+        let parent = self.node(index).parent?;
+        Some((parent, self.node(parent).children.iter().position(|child| *child == index).unwrap()))
+    }
+
+    // Takes the node out of its parent's children, its own parent link is left to the caller
+    pub(crate) fn unlink(&mut self, index: NodeIndex) -> Result<(NodeIndex, usize)> {
+        let Some((parent, position)) = self.position(index) else {
+            return Err(TreeError::RootNode(self.node(index).id.clone()));
+        };
+        self.node_mut(parent).children.remove(position);
+        Ok((parent, position))
+    }
+
+    // Parent first, ending with the root
+    pub(crate) fn ancestors(&self, index: NodeIndex) -> Vec<NodeIndex> {
+        let mut collection = Vec::new();
+        let mut current = self.node(index).parent;
+        while let Some(parent) = current {
+            collection.push(parent);
+            current = self.node(parent).parent;
+        }
+        collection
+    }
+
+    // The node and everything below it in pre-order
+    pub(crate) fn subtree(&self, index: NodeIndex) -> Vec<NodeIndex> {
+        let mut collection = Vec::new();
+        let mut stack = vec![index];
+        while let Some(current) = stack.pop() {
+            stack.extend(self.node(current).children.iter().rev());
+            collection.push(current);
+        }
+        collection
+    }
+
+    fn allocate(&mut self, node: ArenaNode<T>) -> NodeIndex {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeIndex {index, generation: slot.generation}
+            },
+            None => {
+                self.slots.push(Slot {generation: 0, node: Some(node)});
+                NodeIndex {index: (self.slots.len() - 1) as u32, generation: 0}
+            }
+        }
+    }
+
+    // Empties the slot and bumps its generation so outstanding indices no longer resolve. The
+    // generation wraps around, an index would have to outlive 2^32 reuses of its slot to alias.
+    // The node's children are not released with it.
+    pub(crate) fn release(&mut self, index: NodeIndex) -> ArenaNode<T> {
+        let slot = &mut self.slots[index.index as usize];
+        let removed = slot.node.take().unwrap();
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index.index);
+        if self.ids.get(&removed.id) == Some(&index) {
+            self.ids.remove(&removed.id);
+        }
+        removed
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{Result, TreeError};
+
+// Little endian, length prefixed building blocks for the binary formats of the crate
+pub(crate) struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new(magic: &[u8]) -> Self {
+        Self {bytes: magic.to_vec()}
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub(crate) fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub(crate) fn opt_str(&mut self, value: Option<&str>) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            self.str(value);
+        }
+    }
+
+    pub(crate) fn opt_index(&mut self, value: Option<usize>) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            self.u64(value as u64);
+        }
+    }
+
+    // data goes through encode, the crate does not know how to turn a T into bytes
+    pub(crate) fn opt_data<T>(&mut self, value: Option<&T>, encode: &mut impl FnMut(&T) -> Result<Vec<u8>>) -> Result<()> {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            self.bytes(&encode(value)?);
+        }
+        Ok(())
+    }
+
+    // A run of u64 without a length, the reader has to know how many there are
+    pub(crate) fn u64s(&mut self, values: &[u64]) {
+        self.bytes.reserve(values.len() * 8);
+        for value in values {
+            self.bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    // finish with the checksum of everything written appended, for Reader::checked
+    pub(crate) fn finish_checked(mut self) -> Vec<u8> {
+        let sum = checksum(&self.bytes);
+        self.u64(sum);
+        self.bytes
+    }
+}
+
+// 64 bit FNV-1a, catches truncated and corrupted files rather than tampering
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3))
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    // Fails unless bytes starts with magic
+    pub(crate) fn new(bytes: &'a [u8], magic: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(magic) {
+            return Err(TreeError::MalformedInput(format!("expected data starting with {:?}", String::from_utf8_lossy(magic))));
+        }
+        Ok(Self {bytes, position: magic.len()})
+    }
+
+    // Like new for what Writer::finish_checked wrote, fails if the checksum does not match
+    pub(crate) fn checked(bytes: &'a [u8], magic: &[u8]) -> Result<Self> {
+        let reader = Self::new(bytes, magic)?;
+        let Some(split) = bytes.len().checked_sub(8).filter(|split| *split >= magic.len()) else {
+            return Err(TreeError::MalformedInput("unexpected end of data".to_string()));
+        };
+        let (body, sum) = bytes.split_at(split);
+        if checksum(body) != u64::from_le_bytes(sum.try_into().unwrap()) {
+            return Err(TreeError::MalformedInput("checksum mismatch, the data is corrupted".to_string()));
+        }
+        Ok(Self {bytes: body, ..reader})
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| TreeError::MalformedInput("unexpected end of data".to_string()))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64s(&mut self, count: usize) -> Result<Vec<u64>> {
+        let len = count.checked_mul(8).ok_or_else(|| TreeError::MalformedInput("length out of range".to_string()))?;
+        Ok(self.take(len)?.chunks_exact(8).map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap())).collect())
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(TreeError::MalformedInput(format!("invalid flag {}", other)))
+        }
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u64()?;
+        self.take(usize::try_from(len).map_err(|_| TreeError::MalformedInput("length out of range".to_string()))?)
+    }
+
+    pub(crate) fn str(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| TreeError::MalformedInput("invalid utf-8 in string".to_string()))
+    }
+
+    pub(crate) fn opt_str(&mut self) -> Result<Option<String>> {
+        if self.bool()? {Ok(Some(self.str()?))} else {Ok(None)}
+    }
+
+    pub(crate) fn opt_index(&mut self) -> Result<Option<usize>> {
+        if self.bool()? {Ok(Some(self.u64()? as usize))} else {Ok(None)}
+    }
+
+    pub(crate) fn opt_data<T>(&mut self, decode: &mut impl FnMut(&[u8]) -> Result<T>) -> Result<Option<T>> {
+        if self.bool()? {Ok(Some(decode(self.bytes()?)?))} else {Ok(None)}
+    }
+
+    // Fails if anything is left over
+    pub(crate) fn finish(self) -> Result<()> {
+        if self.position == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(TreeError::MalformedInput("trailing bytes after the data".to_string()))
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::render::Subtree;
+use crate::{Node, NodeMap, Result, Tree, TreeError, TreeMap};
+
+// Fill of the highlighted nodes in both formats
+const HIGHLIGHT: &str = "#ffd54f";
+
+// Which way the edges point, as Graphviz rankdir and Mermaid flowchart directions
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Direction {
+    #[default]
+    TopBottom,
+    LeftRight,
+    BottomTop,
+    RightLeft,
+}
+
+impl Direction {
+    // "TB" (or "TD"), "LR", "BT" or "RL", in any case
+    pub fn parse(direction: &str) -> Result<Self> {
+        match direction.to_ascii_uppercase().as_str() {
+            "TB" | "TD" => Ok(Direction::TopBottom),
+            "LR" => Ok(Direction::LeftRight),
+            "BT" => Ok(Direction::BottomTop),
+            "RL" => Ok(Direction::RightLeft),
+            _ => Err(TreeError::MalformedInput(format!("unknown direction '{}', expected 'TB', 'LR', 'BT' or 'RL'", direction))),
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Direction::TopBottom => "TB",
+            Direction::LeftRight => "LR",
+            Direction::BottomTop => "BT",
+            Direction::RightLeft => "RL",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DiagramOptions {
+    // Levels below the root to draw, all of them when None
+    pub max_depth: Option<usize>,
+    pub direction: Direction,
+    // Ids of the nodes to draw highlighted, ids that are not drawn are ignored
+    pub highlight: HashSet<String>,
+}
+
+// A drawn node, its parent is the position of an earlier row
+struct Row {
+    id: String,
+    parent: Option<usize>,
+    label: String,
+}
+
+// Rows in the subtree's pre-order, labelled once all of them are known
+fn rows<M>(subtree: &Subtree<M>, mut label: impl FnMut(&Arc<M>) -> Result<String>) -> Result<Vec<Row>> {
+    let mut parents = vec![None; subtree.nodes.len()];
+    for (position, children) in subtree.children.iter().enumerate() {
+        for &child in children {
+            parents[child] = Some(position);
+        }
+    }
+    subtree.nodes.iter().zip(parents).map(|((id, node), parent)| Ok(Row {id: id.clone(), parent, label: label(node)?})).collect()
+}
+
+fn dot_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+fn dot(rows: &[Row], options: &DiagramOptions) -> String {
+    let mut dot = String::new();
+    dot.push_str("digraph tree {\n");
+    writeln!(dot, "    rankdir={};", options.direction.code()).unwrap();
+    dot.push_str("    node [shape=box];\n");
+    for row in rows {
+        write!(dot, "    {} [label={}", dot_string(&row.id), dot_string(&row.label)).unwrap();
+        if options.highlight.contains(&row.id) {
+            write!(dot, ", style=filled, fillcolor=\"{}\"", HIGHLIGHT).unwrap();
+        }
+        dot.push_str("];\n");
+    }
+    for row in rows {
+        if let Some(parent) = row.parent {
+            writeln!(dot, "    {} -> {};", dot_string(&rows[parent].id), dot_string(&row.id)).unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// Mermaid ids cannot hold arbitrary text, nodes are n0, n1, .. in pre-order and the id only shows
+// through the label
+fn mermaid(rows: &[Row], options: &DiagramOptions) -> String {
+    let mut mermaid = String::new();
+    writeln!(mermaid, "flowchart {}", options.direction.code()).unwrap();
+    for (position, row) in rows.iter().enumerate() {
+        writeln!(mermaid, "    n{}[\"{}\"]", position, row.label.replace('"', "#quot;").replace('\n', "<br>")).unwrap();
+    }
+    for (position, row) in rows.iter().enumerate() {
+        if let Some(parent) = row.parent {
+            writeln!(mermaid, "    n{} --> n{}", parent, position).unwrap();
+        }
+    }
+    let highlighted: Vec<String> = rows.iter().enumerate().filter(|(_, row)| options.highlight.contains(&row.id)).map(|(position, _)| format!("n{}", position)).collect();
+    if !highlighted.is_empty() {
+        writeln!(mermaid, "    classDef highlight fill:{}", HIGHLIGHT).unwrap();
+        writeln!(mermaid, "    class {} highlight", highlighted.join(",")).unwrap();
+    }
+    mermaid
+}
+
+impl<M> Subtree<M> {
+    // The subtree as a Graphviz digraph, options.max_depth was applied when it was taken
+    pub fn to_dot(&self, options: &DiagramOptions, label: impl FnMut(&Arc<M>) -> Result<String>) -> Result<String> {
+        Ok(dot(&rows(self, label)?, options))
+    }
+
+    // The subtree as a Mermaid flowchart, options.max_depth was applied when it was taken
+    pub fn to_mermaid(&self, options: &DiagramOptions, label: impl FnMut(&Arc<M>) -> Result<String>) -> Result<String> {
+        Ok(mermaid(&rows(self, label)?, options))
+    }
+}
+
+impl<T> Tree<T> {
+    // The tree as a Graphviz digraph, label gives the text shown for a node
+    pub fn to_dot(&self, options: &DiagramOptions, label: impl FnMut(&Arc<Mutex<Node<T>>>) -> Result<String>) -> Result<String> {
+        self.subtree(None, options.max_depth)?.to_dot(options, label)
+    }
+
+    // The tree as a Mermaid flowchart, label gives the text shown for a node
+    pub fn to_mermaid(&self, options: &DiagramOptions, label: impl FnMut(&Arc<Mutex<Node<T>>>) -> Result<String>) -> Result<String> {
+        self.subtree(None, options.max_depth)?.to_mermaid(options, label)
+    }
+}
+
+impl<T> TreeMap<T> {
+    // The tree as a Graphviz digraph, label gives the text shown for a node
+    pub fn to_dot(&self, options: &DiagramOptions, label: impl FnMut(&Arc<RwLock<NodeMap<T>>>) -> Result<String>) -> Result<String> {
+        self.subtree(None, options.max_depth)?.to_dot(options, label)
+    }
+
+    // The tree as a Mermaid flowchart, label gives the text shown for a node
+    pub fn to_mermaid(&self, options: &DiagramOptions, label: impl FnMut(&Arc<RwLock<NodeMap<T>>>) -> Result<String>) -> Result<String> {
+        self.subtree(None, options.max_depth)?.to_mermaid(options, label)
+    }
+}
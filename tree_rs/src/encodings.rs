@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::{Record, Result, Tree, TreeError, TreeMap};
+
+// The hierarchy encodings relational databases use, as rows a table can be filled from:
+//   nested sets       (id, lft, rgt), a node's lft and rgt enclose those of everything below it
+//   materialized path "/root/a/b", the ids from the root down to the node
+//   closure table     (ancestor, descendant, depth), one row per pair including (node, node, 0)
+// The importers check the rows describe a single tree and build it through from_records.
+
+// (id, depth) of every node in pre-order, what nested sets and paths are computed from
+type Outline = Vec<(String, usize)>;
+
+fn nested_sets(outline: Outline) -> Vec<(String, usize, usize)> {
+    let mut rows: Vec<(String, usize, usize)> = Vec::with_capacity(outline.len());
+    // Rows of the nodes on the path from the root to the current node, waiting for their rgt
+    let mut open: Vec<usize> = Vec::new();
+    let mut counter = 1;
+    for (id, depth) in outline {
+        while open.len() > depth {
+            rows[open.pop().unwrap()].2 = counter;
+            counter += 1;
+        }
+        rows.push((id, counter, 0));
+        counter += 1;
+        open.push(rows.len() - 1);
+    }
+    while let Some(position) = open.pop() {
+        rows[position].2 = counter;
+        counter += 1;
+    }
+    rows
+}
+
+fn materialized_paths(outline: Outline, separator: &str) -> Result<Vec<String>> {
+    if separator.is_empty() {
+        return Err(TreeError::MalformedInput("the separator cannot be empty".to_string()));
+    }
+    let mut paths: Vec<String> = Vec::with_capacity(outline.len());
+    let mut stack: Vec<usize> = Vec::new();
+    for (id, depth) in outline {
+        if id.contains(separator) {
+            return Err(TreeError::MalformedInput(format!("node '{}' contains the separator '{}'", id, separator)));
+        }
+        stack.truncate(depth);
+        let prefix = stack.last().map_or("", |position| paths[*position].as_str());
+        paths.push(format!("{}{}{}", prefix, separator, id));
+        stack.push(paths.len() - 1);
+    }
+    Ok(paths)
+}
+
+fn nested_set_records<T>(mut rows: Vec<(String, usize, usize, Option<T>)>) -> Result<Vec<Record<T>>> {
+    rows.sort_by_key(|(_, lft, _, _)| *lft);
+    let mut records = Vec::with_capacity(rows.len());
+    // (id, lft, rgt) of the nodes enclosing the current one
+    let mut open: Vec<(String, usize, usize)> = Vec::new();
+    for (id, lft, rgt, data) in rows {
+        if rgt <= lft {
+            return Err(TreeError::MalformedInput(format!("node '{}' has a rgt that is not greater than its lft", id)));
+        }
+        while open.last().is_some_and(|(_, _, open_rgt)| *open_rgt < lft) {
+            open.pop();
+        }
+        if let Some((parent_id, parent_lft, parent_rgt)) = open.last() {
+            if lft == *parent_lft || rgt >= *parent_rgt {
+                return Err(TreeError::MalformedInput(format!("the numbers of node '{}' overlap those of '{}'", id, parent_id)));
+            }
+        }
+        records.push((id.clone(), open.last().map(|(parent_id, _, _)| parent_id.clone()), data));
+        open.push((id, lft, rgt));
+    }
+    Ok(records)
+}
+
+fn path_records<T>(rows: Vec<(String, Option<T>)>, separator: &str) -> Result<Vec<Record<T>>> {
+    if separator.is_empty() {
+        return Err(TreeError::MalformedInput("the separator cannot be empty".to_string()));
+    }
+    let mut parent_paths: Vec<(String, String)> = Vec::with_capacity(rows.len());
+    let mut records = Vec::with_capacity(rows.len());
+    for (path, data) in rows {
+        let segments: Vec<&str> = path.strip_prefix(separator).unwrap_or(&path).split(separator).collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(TreeError::MalformedInput(format!("path '{}' has an empty segment", path)));
+        }
+        let id = segments[segments.len() - 1].to_string();
+        let parent_id = (segments.len() > 1).then(|| segments[segments.len() - 2].to_string());
+        parent_paths.push((path[..path.len() - id.len()].to_string(), path));
+        records.push((id, parent_id, data));
+    }
+    // The parent named by a path has to sit at the rest of that path
+    let paths: HashMap<&str, &str> = records.iter().zip(&parent_paths).map(|((id, _, _), (_, path))| (id.as_str(), path.as_str())).collect();
+    for ((_, parent_id, _), (prefix, path)) in records.iter().zip(&parent_paths) {
+        let Some(parent_id) = parent_id else { continue };
+        if paths.get(parent_id.as_str()).is_some_and(|parent_path| prefix.strip_suffix(separator) != Some(parent_path)) {
+            return Err(TreeError::MalformedInput(format!("path '{}' does not continue the path of '{}'", path, parent_id)));
+        }
+    }
+    Ok(records)
+}
+
+fn closure_records<T>(rows: &[(String, String, usize)]) -> Result<Vec<Record<T>>> {
+    let mut order: Vec<&str> = Vec::new();
+    let mut parents: HashMap<&str, Option<&str>> = HashMap::new();
+    for (ancestor, descendant, depth) in rows {
+        let parent = parents.entry(descendant.as_str()).or_insert_with(|| {
+            order.push(descendant);
+            None
+        });
+        if *depth == 1 {
+            if parent.is_some_and(|parent| parent != ancestor) {
+                return Err(TreeError::MalformedInput(format!("node '{}' has more than one parent", descendant)));
+            }
+            *parent = Some(ancestor);
+        }
+    }
+    for (ancestor, _, _) in rows {
+        if !parents.contains_key(ancestor.as_str()) {
+            return Err(TreeError::MalformedInput(format!("node '{}' is an orphan, it never appears as a descendant", ancestor)));
+        }
+    }
+    Ok(order.into_iter().map(|id| (id.to_string(), parents[id].map(str::to_string), None)).collect())
+}
+
+// The rows of a closure table have to be exactly the ones of the tree built from its depth 1 rows
+fn check_closure(rows: &[(String, String, usize)], built: Vec<(String, String, usize)>) -> Result<()> {
+    let expected: HashSet<&(String, String, usize)> = built.iter().collect();
+    let given: HashSet<&(String, String, usize)> = rows.iter().collect();
+    if expected == given {
+        Ok(())
+    } else {
+        Err(TreeError::MalformedInput("the closure table rows do not match the tree their depth 1 rows describe".to_string()))
+    }
+}
+
+impl<T> Tree<T> {
+    // (id, lft, rgt) for every node in pre-order, numbered from 1
+    pub fn nested_sets(&self) -> Vec<(String, usize, usize)> {
+        nested_sets(self.outline())
+    }
+
+    // "/root/a/b" for every node in pre-order, fails when an id contains the separator
+    pub fn materialized_paths(&self, separator: &str) -> Result<Vec<String>> {
+        materialized_paths(self.outline(), separator)
+    }
+
+    // (ancestor, descendant, depth) rows, for every node in pre-order the node itself at depth 0
+    // followed by its ancestors nearest first
+    pub fn closure_table(&self) -> Vec<(String, String, usize)> {
+        let mut rows = Vec::new();
+        for node in self.pre_order(None, None).unwrap() {
+            let id = node.lock().unwrap().id.clone();
+            rows.push((id.clone(), id.clone(), 0));
+            for (depth, ancestor) in self.get_ancestors(&node).unwrap().into_iter().enumerate() {
+                rows.push((ancestor.lock().unwrap().id.clone(), id.clone(), depth + 1));
+            }
+        }
+        rows
+    }
+
+    // Builds a tree from (id, lft, rgt, data) rows in any order, siblings are ordered by lft
+    pub fn from_nested_sets(rows: Vec<(String, usize, usize, Option<T>)>) -> Result<Arc<Mutex<Tree<T>>>> {
+        Tree::from_records(nested_set_records(rows)?)
+    }
+
+    // Builds a tree from (path, data) rows, the last id of a path is the node's. Siblings keep the order of their rows.
+    pub fn from_materialized_paths(rows: Vec<(String, Option<T>)>, separator: &str) -> Result<Arc<Mutex<Tree<T>>>> {
+        Tree::from_records(path_records(rows, separator)?)
+    }
+
+    // Builds a tree without payloads from closure table rows, siblings come in the order of their rows
+    pub fn from_closure_table(rows: &[(String, String, usize)]) -> Result<Arc<Mutex<Tree<T>>>> {
+        let tree = Tree::from_records(closure_records(rows)?)?;
+        check_closure(rows, tree.lock().unwrap().closure_table())?;
+        Ok(tree)
+    }
+
+    fn outline(&self) -> Outline {
+        let mut outline = Vec::new();
+        let mut stack = vec![(self.root.clone(), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let node_guard = node.lock().unwrap();
+            stack.extend(node_guard.children.lock().unwrap().iter().rev().map(|child| (child.clone(), depth + 1)));
+            outline.push((node_guard.id.clone(), depth));
+        }
+        outline
+    }
+}
+
+impl<T> TreeMap<T> {
+    // (id, lft, rgt) for every node in pre-order, numbered from 1
+    pub fn nested_sets(&self) -> Vec<(String, usize, usize)> {
+        nested_sets(self.outline())
+    }
+
+    // "/root/a/b" for every node in pre-order, fails when an id contains the separator
+    pub fn materialized_paths(&self, separator: &str) -> Result<Vec<String>> {
+        materialized_paths(self.outline(), separator)
+    }
+
+    // (ancestor, descendant, depth) rows, for every node in pre-order the node itself at depth 0
+    // followed by its ancestors nearest first
+    pub fn closure_table(&self) -> Vec<(String, String, usize)> {
+        let mut rows = Vec::new();
+        for node in self.pre_order(None, None).unwrap() {
+            let id = node.read().unwrap().id.clone();
+            rows.push((id.clone(), id.clone(), 0));
+            for (depth, ancestor) in self.get_ancestors(&node).unwrap().into_iter().enumerate() {
+                rows.push((ancestor.read().unwrap().id.clone(), id.clone(), depth + 1));
+            }
+        }
+        rows
+    }
+
+    // Builds a tree from (id, lft, rgt, data) rows in any order, siblings are ordered by lft
+    pub fn from_nested_sets(rows: Vec<(String, usize, usize, Option<T>)>) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        TreeMap::from_records(nested_set_records(rows)?)
+    }
+
+    // Builds a tree from (path, data) rows, the last id of a path is the node's. Siblings keep the order of their rows.
+    pub fn from_materialized_paths(rows: Vec<(String, Option<T>)>, separator: &str) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        TreeMap::from_records(path_records(rows, separator)?)
+    }
+
+    // Builds a tree without payloads from closure table rows, siblings come in the order of their rows
+    pub fn from_closure_table(rows: &[(String, String, usize)]) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        let tree = TreeMap::from_records(closure_records(rows)?)?;
+        check_closure(rows, tree.read().unwrap().closure_table())?;
+        Ok(tree)
+    }
+
+    fn outline(&self) -> Outline {
+        let arena = self.arena.read().unwrap();
+        let mut outline = Vec::with_capacity(arena.len());
+        let mut stack = vec![(arena.root(), 0)];
+        while let Some((slot, depth)) = stack.pop() {
+            let node = arena.node(slot);
+            stack.extend(node.children.iter().rev().map(|child| (*child, depth + 1)));
+            outline.push((node.id.clone(), depth));
+        }
+        outline
+    }
+}
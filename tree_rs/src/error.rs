@@ -0,0 +1,28 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, TreeError>;
+
+// Every fallible Tree and TreeMap operation reports one of these, the String is the id of the offending node
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TreeError {
+    #[error("node '{0}' was not found on the tree")]
+    NodeNotFound(String),
+    #[error("node '{0}' is an ancestor of the new parent, moving it would create a cycle")]
+    CycleDetected(String),
+    #[error("a node with id '{0}' is already on the tree")]
+    DuplicateId(String),
+    #[error("malformed input: {0}")]
+    MalformedInput(String),
+    #[error("node '{0}' is not on this tree")]
+    NotInTree(String),
+    #[error("node '{0}' belongs to another tree, detach it before adding it here")]
+    AttachedElsewhere(String),
+    #[error("node '{0}' is the root, the root cannot be removed or moved")]
+    RootNode(String),
+    #[error("checkpoint '{0}' is not in the history")]
+    CheckpointNotFound(String),
+    #[error("the tree has no history, call enable_history first")]
+    HistoryDisabled,
+    #[error("detach_subtree cleared the history, the changes made before it cannot be undone")]
+    HistoryCleared,
+}
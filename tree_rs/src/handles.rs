@@ -0,0 +1,235 @@
+use std::sync::{Arc, Mutex, RwLock, Weak as AWeak};
+
+use crate::history::History;
+use crate::{Change, Hooks, Node, NodeMap, Op, Result, Tree, TreeError, TreeMap};
+
+// What the history, hooks and transactions shared by Tree and TreeMap need to know about a tree and
+// its node handles, the only part of them that is written once per tree. The module is private, so
+// the trait cannot be implemented outside the crate.
+pub trait Handles: Sized {
+    type Node: Clone;
+    type Data;
+
+    // A Node carries its own data and children, so they can be changed while it is off the tree
+    const EDITS_DETACHED: bool;
+    // A removed Node keeps its subtree, a removed NodeMap leaves its descendants behind
+    const KEEPS_SUBTREE: bool;
+
+    fn id_of(node: &Self::Node) -> String;
+    // Identity of the handle, two handles to the same node have the same key
+    fn key(node: &Self::Node) -> usize;
+    fn root(&self) -> Self::Node;
+    fn contains(&self, node: &Self::Node) -> bool;
+    fn id_taken(&self, id: &str) -> bool;
+    fn parent_of(&self, node: &Self::Node) -> Option<Self::Node>;
+    fn children_of(&self, node: &Self::Node) -> Vec<Self::Node>;
+    // Whether adding the node has to fail with AttachedElsewhere, has_parent as the batch left it
+    fn attached_elsewhere(&self, node: &Self::Node, has_parent: bool) -> bool;
+
+    fn history_slot(&self) -> &Mutex<Option<History<Self::Node, Self::Data>>>;
+    fn hooks_slot(&self) -> &RwLock<Hooks>;
+
+    fn apply_op(&self, op: Op<Self::Node, Self::Data>) -> Result<Change<Self::Node, Self::Data>>;
+    fn revert(&self, change: Change<Self::Node, Self::Data>) -> Result<Change<Self::Node, Self::Data>>;
+
+    // Where the journal stands before a commit and dropping what came after, when it is rolled back
+    fn journal_mark(&self) -> usize {
+        0
+    }
+
+    fn journal_rewind(&self, _mark: usize) {}
+
+    fn record(&self, changes: Vec<Change<Self::Node, Self::Data>>) {
+        if let Some(history) = self.history_slot().lock().unwrap().as_mut() {
+            history.record(changes);
+        }
+    }
+
+    // Runs recorded and keeps its change while the history is on, otherwise runs the cheaper plain
+    fn record_with(&self, recorded: impl FnOnce() -> Result<Change<Self::Node, Self::Data>>, plain: impl FnOnce() -> Result<()>) -> Result<()> {
+        if self.history_slot().lock().unwrap().is_none() {
+            return plain();
+        }
+        let change = recorded()?;
+        self.record(vec![change]);
+        Ok(())
+    }
+
+    // The history is locked throughout, reverting only uses methods that do not record
+    fn with_history<R>(&self, f: impl FnOnce(&mut History<Self::Node, Self::Data>) -> Result<R>) -> Result<R> {
+        match self.history_slot().lock().unwrap().as_mut() {
+            Some(history) => f(history),
+            None => Err(TreeError::HistoryDisabled)
+        }
+    }
+}
+
+impl<T> Handles for TreeMap<T> {
+    type Node = Arc<RwLock<NodeMap<T>>>;
+    type Data = T;
+
+    const EDITS_DETACHED: bool = false;
+    const KEEPS_SUBTREE: bool = false;
+
+    fn id_of(node: &Self::Node) -> String {
+        node.read().unwrap().id.clone()
+    }
+
+    fn key(node: &Self::Node) -> usize {
+        Arc::as_ptr(node) as usize
+    }
+
+    fn root(&self) -> Self::Node {
+        TreeMap::root(self)
+    }
+
+    fn contains(&self, node: &Self::Node) -> bool {
+        self.check_on_tree(node).is_ok()
+    }
+
+    fn id_taken(&self, id: &str) -> bool {
+        self.arena.read().unwrap().contains_id(id)
+    }
+
+    // A node that is off the tree has no relatives
+    fn parent_of(&self, node: &Self::Node) -> Option<Self::Node> {
+        self.parent(node).ok().flatten()
+    }
+
+    fn children_of(&self, node: &Self::Node) -> Vec<Self::Node> {
+        self.children(node).unwrap_or_default()
+    }
+
+    fn attached_elsewhere(&self, node: &Self::Node, _has_parent: bool) -> bool {
+        node.read().unwrap().get_tree().is_some_and(|tree| !AWeak::ptr_eq(&Arc::downgrade(&tree), &self.this))
+    }
+
+    fn history_slot(&self) -> &Mutex<Option<History<Self::Node, T>>> {
+        &self.history
+    }
+
+    fn hooks_slot(&self) -> &RwLock<Hooks> {
+        &self.hooks
+    }
+
+    fn apply_op(&self, op: Op<Self::Node, T>) -> Result<Change<Self::Node, T>> {
+        match op {
+            Op::Add {child, parent, index} => {
+                self.attach(&child, parent.as_ref(), index)?;
+                Ok(Change::Added {node: child, keep_children: false})
+            },
+            Op::Move {node, parent, index} => self.move_recorded(&node, &parent, index),
+            Op::Remove {node, keep_children} => self.remove_recorded(&node, keep_children),
+            Op::SetData {node, data} => self.set_data_recorded(&node, data),
+            Op::Reorder {parent, ids} => self.reorder_recorded(&parent, &ids),
+        }
+    }
+
+    fn revert(&self, change: Change<Self::Node, T>) -> Result<Change<Self::Node, T>> {
+        match change {
+            Change::Added {node, keep_children} => self.remove_recorded(&node, keep_children),
+            Change::Moved {node, parent, index} => self.move_recorded(&node, &parent, Some(index)),
+            Change::Removed {node, parent, index, adopted, subtree} => {
+                self.attach(&node, Some(&parent), Some(index))?;
+                let adopted_children: Vec<Self::Node> = self.children(&parent)?[index + 1..index + 1 + adopted].to_vec();
+                for child in adopted_children {
+                    self.relocate(&child, &node, None)?;
+                }
+                for (descendant, descendant_parent) in subtree {
+                    self.attach(&descendant, Some(&descendant_parent), None)?;
+                }
+                Ok(Change::Added {node, keep_children: adopted > 0})
+            },
+            Change::DataSet {node, data} => self.set_data_recorded(&node, data),
+            Change::Reordered {parent, ids} => self.reorder_recorded(&parent, &ids),
+        }
+    }
+
+    fn journal_mark(&self) -> usize {
+        self.journal_len()
+    }
+
+    fn journal_rewind(&self, mark: usize) {
+        self.journal_truncate(mark);
+    }
+}
+
+impl<T> Handles for Tree<T> {
+    type Node = Arc<Mutex<Node<T>>>;
+    type Data = T;
+
+    const EDITS_DETACHED: bool = true;
+    const KEEPS_SUBTREE: bool = true;
+
+    fn id_of(node: &Self::Node) -> String {
+        node.lock().unwrap().id.clone()
+    }
+
+    fn key(node: &Self::Node) -> usize {
+        Arc::as_ptr(node) as usize
+    }
+
+    fn root(&self) -> Self::Node {
+        self.root.clone()
+    }
+
+    fn contains(&self, node: &Self::Node) -> bool {
+        Tree::contains(self, node)
+    }
+
+    fn id_taken(&self, id: &str) -> bool {
+        self.find_by_id(id).is_some()
+    }
+
+    fn parent_of(&self, node: &Self::Node) -> Option<Self::Node> {
+        node.lock().unwrap().parent.as_ref().and_then(|parent_weak| parent_weak.upgrade())
+    }
+
+    fn children_of(&self, node: &Self::Node) -> Vec<Self::Node> {
+        node.lock().unwrap().children.lock().unwrap().clone()
+    }
+
+    // A node with a live parent is on a tree, this one included
+    fn attached_elsewhere(&self, _node: &Self::Node, has_parent: bool) -> bool {
+        has_parent
+    }
+
+    fn history_slot(&self) -> &Mutex<Option<History<Self::Node, T>>> {
+        &self.history
+    }
+
+    fn hooks_slot(&self) -> &RwLock<Hooks> {
+        &self.hooks
+    }
+
+    fn apply_op(&self, op: Op<Self::Node, T>) -> Result<Change<Self::Node, T>> {
+        match op {
+            Op::Add {child, parent, index} => {
+                self.attach(child.clone(), parent, index)?;
+                Ok(Change::Added {node: child, keep_children: false})
+            },
+            Op::Move {node, parent, index} => self.move_recorded(&node, &parent, index),
+            Op::Remove {node, keep_children} => self.remove_recorded(&node, keep_children),
+            Op::SetData {node, data} => Ok(self.set_data_recorded(&node, data)),
+            Op::Reorder {parent, ids} => self.reorder_recorded(&parent, &ids),
+        }
+    }
+
+    fn revert(&self, change: Change<Self::Node, T>) -> Result<Change<Self::Node, T>> {
+        match change {
+            Change::Added {node, keep_children} => self.remove_recorded(&node, keep_children),
+            Change::Moved {node, parent, index} => self.move_recorded(&node, &parent, Some(index)),
+            // A removed Node keeps its subtree, only children it handed to its parent need collecting
+            Change::Removed {node, parent, index, adopted, ..} => {
+                self.attach(node.clone(), Some(parent.clone()), Some(index))?;
+                let adopted_children: Vec<Self::Node> = parent.lock().unwrap().children.lock().unwrap()[index + 1..index + 1 + adopted].to_vec();
+                for child in adopted_children {
+                    self.relocate(&child, &node, None)?;
+                }
+                Ok(Change::Added {node, keep_children: adopted > 0})
+            },
+            Change::DataSet {node, data} => Ok(self.set_data_recorded(&node, data)),
+            Change::Reordered {parent, ids} => self.reorder_recorded(&parent, &ids),
+        }
+    }
+}
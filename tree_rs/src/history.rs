@@ -0,0 +1,207 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::handles::Handles;
+use crate::transaction::roll_back;
+use crate::{Change, Op, Result, Tree, TreeError, TreeMap};
+
+// One undo step, the changes of a single mutation or of a whole committed transaction
+struct Entry<N, T> {
+    // Entries are numbered in the order they were recorded, checkpoints refer to these numbers
+    seq: u64,
+    changes: Vec<Change<N, T>>,
+}
+
+// Bounded undo/redo stacks of the mutations applied to a tree. Undoing an entry reverts its changes
+// newest first and keeps the inverse changes as the redo entry, recording anything new drops redo.
+pub struct History<N, T> {
+    limit: usize,
+    undo: VecDeque<Entry<N, T>>,
+    redo: Vec<Entry<N, T>>,
+    // seq of the newest entry that fell off the bottom of the undo stack, 0 while none did
+    floor: u64,
+    next_seq: u64,
+    checkpoints: HashMap<String, u64>,
+    // Set once detach_subtree has dropped the entries, undo raises instead of stopping there
+    cleared: bool,
+}
+
+impl<N: Clone, T> History<N, T> {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {limit, undo: VecDeque::new(), redo: Vec::new(), floor: 0, next_seq: 1, checkpoints: HashMap::new(), cleared: false}
+    }
+
+    // Drops every entry and checkpoint after a change that cannot be recorded
+    pub(crate) fn clear(&mut self) {
+        *self = Self {cleared: true, ..Self::new(self.limit)};
+    }
+
+    pub(crate) fn record(&mut self, changes: Vec<Change<N, T>>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(Entry {seq: self.next_seq, changes});
+        self.next_seq += 1;
+        while self.undo.len() > self.limit {
+            self.floor = self.undo.pop_front().unwrap().seq;
+        }
+    }
+
+    // seq of the last entry applied to the tree
+    fn position(&self) -> u64 {
+        self.undo.back().map_or(self.floor, |entry| entry.seq)
+    }
+
+    fn undo(&mut self, revert: impl FnMut(Change<N, T>) -> Result<Change<N, T>>) -> Result<bool> {
+        let Some(entry) = self.undo.pop_back() else {
+            return if self.cleared { Err(TreeError::HistoryCleared) } else { Ok(false) };
+        };
+        let changes = self.revert_entry(entry.changes, revert)?;
+        self.redo.push(Entry {seq: entry.seq, changes});
+        Ok(true)
+    }
+
+    fn redo(&mut self, revert: impl FnMut(Change<N, T>) -> Result<Change<N, T>>) -> Result<bool> {
+        let Some(entry) = self.redo.pop() else {
+            return Ok(false);
+        };
+        let changes = self.revert_entry(entry.changes, revert)?;
+        self.undo.push_back(Entry {seq: entry.seq, changes});
+        Ok(true)
+    }
+
+    // Reverting newest first gives the inverse changes newest last, so reverting those the same way
+    // replays the entry. If a change cannot be reverted the tree no longer matches the history: the
+    // changes already reverted are applied again so the tree is back where it was, the history is
+    // cleared and the error is returned.
+    fn revert_entry(&mut self, changes: Vec<Change<N, T>>, mut revert: impl FnMut(Change<N, T>) -> Result<Change<N, T>>) -> Result<Vec<Change<N, T>>> {
+        let mut inverse = Vec::with_capacity(changes.len());
+        for change in changes.into_iter().rev() {
+            match revert(change) {
+                Ok(change) => inverse.push(change),
+                Err(error) => {
+                    *self = Self::new(self.limit);
+                    roll_back(inverse, &mut revert)?;
+                    return Err(error);
+                }
+            }
+        }
+        Ok(inverse)
+    }
+
+    fn checkpoint(&mut self, name: &str) {
+        self.checkpoints.insert(name.to_string(), self.position());
+    }
+
+    // seq of the entry the checkpoint was taken at, as long as undo and redo can still get there
+    fn target(&self, name: &str) -> Result<u64> {
+        let target = *self.checkpoints.get(name).ok_or_else(|| TreeError::CheckpointNotFound(name.to_string()))?;
+        let reachable = target == self.floor
+            || self.undo.iter().any(|entry| entry.seq == target)
+            || self.redo.iter().any(|entry| entry.seq == target);
+        if !reachable {
+            return Err(TreeError::CheckpointNotFound(name.to_string()));
+        }
+        Ok(target)
+    }
+
+    // Undoes or redoes entries until the tree is back where it was when the checkpoint was taken
+    fn restore(&mut self, name: &str, mut revert: impl FnMut(Change<N, T>) -> Result<Change<N, T>>) -> Result<()> {
+        let target = self.target(name)?;
+        while self.position() > target {
+            self.undo(&mut revert)?;
+        }
+        while self.position() < target {
+            self.redo(&mut revert)?;
+        }
+        Ok(())
+    }
+
+    // What undoing or redoing the entries would do, in the order it is done
+    fn revert_ops<'a>(entries: impl Iterator<Item = &'a Entry<N, T>>) -> Vec<Op<N, T>> where N: 'a, T: 'a {
+        entries.flat_map(|entry| entry.changes.iter().rev().map(Change::revert_op)).collect()
+    }
+
+    fn undo_ops(&self) -> Vec<Op<N, T>> {
+        Self::revert_ops(self.undo.back().into_iter())
+    }
+
+    fn redo_ops(&self) -> Vec<Op<N, T>> {
+        Self::revert_ops(self.redo.last().into_iter())
+    }
+
+    fn restore_ops(&self, name: &str) -> Result<Vec<Op<N, T>>> {
+        let target = self.target(name)?;
+        let undone = self.undo.iter().rev().take_while(|entry| entry.seq > target);
+        let redone = self.redo.iter().rev().take_while(|entry| entry.seq <= target);
+        Ok(Self::revert_ops(undone.chain(redone)))
+    }
+
+    // (kind, node) for the changes of every entry that can be undone, oldest entry first
+    fn entries(&self) -> Vec<Vec<(&'static str, N)>> {
+        self.undo.iter()
+            .map(|entry| entry.changes.iter().map(|change| (change.kind(), change.node().clone())).collect())
+            .collect()
+    }
+}
+
+// Undo and redo for Tree and TreeMap, the history is off until enable_history is called
+pub trait Undo: Handles {
+    // Starts recording add_child, insert_child, add_many, move_node, remove_node, set_data,
+    // reorder_children and committed transactions, keeping the last limit entries for undo.
+    // A history that was already running is replaced.
+    fn enable_history(&self, limit: usize) {
+        *self.history_slot().lock().unwrap() = Some(History::new(limit));
+    }
+
+    fn disable_history(&self) {
+        *self.history_slot().lock().unwrap() = None;
+    }
+
+    // Reverts the last recorded entry, false when there is nothing to undo. Once detach_subtree has
+    // cleared the history, running out of entries is a HistoryCleared error instead.
+    fn undo(&self) -> Result<bool> {
+        self.with_history(|history| history.undo(|change| self.revert(change)))
+    }
+
+    // Applies the last undone entry again, false when there is nothing to redo
+    fn redo(&self) -> Result<bool> {
+        self.with_history(|history| history.redo(|change| self.revert(change)))
+    }
+
+    // Names the tree's current state, restore_checkpoint returns to it for as long as it is in reach
+    // of undo and redo
+    fn checkpoint(&self, name: &str) -> Result<()> {
+        self.with_history(|history| {
+            history.checkpoint(name);
+            Ok(())
+        })
+    }
+
+    fn restore_checkpoint(&self, name: &str) -> Result<()> {
+        self.with_history(|history| history.restore(name, |change| self.revert(change)))
+    }
+
+    // The entries that can be undone, oldest first, each as the (kind, node) of its changes
+    #[allow(clippy::type_complexity)]
+    fn history(&self) -> Result<Vec<Vec<(&'static str, Self::Node)>>> {
+        self.with_history(|history| Ok(history.entries()))
+    }
+
+    // The operations undo, redo and restore_checkpoint would apply, for events_for. Their payloads
+    // are left out.
+    fn undo_ops(&self) -> Result<Vec<Op<Self::Node, Self::Data>>> {
+        self.with_history(|history| Ok(history.undo_ops()))
+    }
+
+    fn redo_ops(&self) -> Result<Vec<Op<Self::Node, Self::Data>>> {
+        self.with_history(|history| Ok(history.redo_ops()))
+    }
+
+    fn restore_ops(&self, name: &str) -> Result<Vec<Op<Self::Node, Self::Data>>> {
+        self.with_history(|history| history.restore_ops(name))
+    }
+}
+
+impl<T> Undo for TreeMap<T> {}
+impl<T> Undo for Tree<T> {}
@@ -0,0 +1,106 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use crate::handles::Handles;
+use crate::transaction::Scratch;
+use crate::{Op, Result, Tree, TreeMap};
+
+// What a mutation does to one node. Indices are positions among the parent's children, old_* is
+// where the node was and new_* where it ends up, a data change reports the node's current place in
+// both. kind is one of "add", "move", "remove" and "set_data", reordering children moves each child
+// whose index changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: &'static str,
+    pub node_id: String,
+    pub old_parent: Option<String>,
+    pub new_parent: Option<String>,
+    pub old_index: Option<usize>,
+    pub new_index: Option<usize>,
+}
+
+pub type HookError = Box<dyn Error + Send + Sync>;
+pub type Hook = Arc<dyn Fn(&Event) -> std::result::Result<(), HookError> + Send + Sync>;
+
+// Callbacks registered on a tree. The tree only keeps them, running them is up to whoever applies the
+// mutation: take a snapshot with hooks() and the events with events_for, release every lock of the
+// tree, call before (an error vetoes the mutation), apply it and call after.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    next_id: u64,
+    before: Vec<(u64, Hook)>,
+    after: Vec<(u64, Hook)>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    // Runs the before hooks in the order they were added, stopping at the first error
+    pub fn before(&self, events: &[Event]) -> std::result::Result<(), HookError> {
+        run(&self.before, events)
+    }
+
+    pub fn after(&self, events: &[Event]) -> std::result::Result<(), HookError> {
+        run(&self.after, events)
+    }
+
+    fn add(&mut self, hook: Hook, before: bool) -> u64 {
+        self.next_id += 1;
+        let hooks = if before {&mut self.before} else {&mut self.after};
+        hooks.push((self.next_id, hook));
+        self.next_id
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        let count = self.before.len() + self.after.len();
+        self.before.retain(|(hook_id, _)| *hook_id != id);
+        self.after.retain(|(hook_id, _)| *hook_id != id);
+        count != self.before.len() + self.after.len()
+    }
+}
+
+fn run(hooks: &[(u64, Hook)], events: &[Event]) -> std::result::Result<(), HookError> {
+    for event in events {
+        for (_, hook) in hooks {
+            hook(event)?;
+        }
+    }
+    Ok(())
+}
+
+// Hooks for Tree and TreeMap
+pub trait Hooked: Handles {
+    // Returns an id for remove_hook
+    fn add_before_hook(&self, hook: Hook) -> u64 {
+        self.hooks_slot().write().unwrap().add(hook, true)
+    }
+
+    fn add_after_hook(&self, hook: Hook) -> u64 {
+        self.hooks_slot().write().unwrap().add(hook, false)
+    }
+
+    // False when no hook has that id
+    fn remove_hook(&self, id: u64) -> bool {
+        self.hooks_slot().write().unwrap().remove(id)
+    }
+
+    fn hooks(&self) -> Hooks {
+        self.hooks_slot().read().unwrap().clone()
+    }
+
+    // The events applying ops in order would cause, each op taken against the tree as the ones
+    // before it leave it. Fails with the error applying the first invalid op would give.
+    fn events_for(&self, ops: &[Op<Self::Node, Self::Data>]) -> Result<Vec<Event>> {
+        let mut scratch = Scratch::new(self);
+        let mut events = Vec::with_capacity(ops.len());
+        for op in ops {
+            events.extend(scratch.apply(op)?);
+        }
+        Ok(events)
+    }
+}
+
+impl<T> Hooked for TreeMap<T> {}
+impl<T> Hooked for Tree<T> {}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::codec::{Reader, Writer};
+use crate::{Commit, NodeMap, Result, TreeError, TreeMap, TreeMapTransaction};
+
+// A mutation as it reaches the tree, nodes are referred to by id so it can be applied to another
+// copy of the tree, in another process or after a restart
+#[derive(Clone, Debug, PartialEq)]
+pub enum JournalOp<T> {
+    // A parent of None means the root, an index of None appends
+    Add {id: String, parent: Option<String>, index: Option<usize>, data: Option<T>},
+    Move {id: String, parent: String, index: Option<usize>},
+    Remove {id: String, keep_children: bool},
+    SetData {id: String, data: Option<T>},
+    Reorder {parent: String, ids: Vec<String>},
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry<T> {
+    // Numbered from 1 in the order the mutations were made
+    pub seq: u64,
+    pub op: JournalOp<T>,
+}
+
+// Append-only log of the mutations made to a TreeMap. root_id is the id of the tree's root, which the
+// first entries refer to when they add to the top of the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Journal<T> {
+    pub root_id: String,
+    pub entries: Vec<JournalEntry<T>>,
+}
+
+const MAGIC: &[u8] = b"TRJ1";
+
+impl<T> Journal<T> {
+    // The seq of the last entry, 0 when empty
+    pub fn last_seq(&self) -> u64 {
+        self.entries.last().map_or(0, |entry| entry.seq)
+    }
+
+    // Only the entries after seq, to ship what a copy has not seen yet
+    pub fn since(&self, seq: u64) -> Journal<T> where T: Clone {
+        let entries = self.entries.iter().filter(|entry| entry.seq > seq).cloned().collect();
+        Journal {root_id: self.root_id.clone(), entries}
+    }
+
+    // Binary form of the journal, encode turns a payload into bytes
+    pub fn to_bytes(&self, mut encode: impl FnMut(&T) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        let mut writer = Writer::new(MAGIC);
+        writer.str(&self.root_id);
+        writer.u64(self.entries.len() as u64);
+        for entry in &self.entries {
+            writer.u64(entry.seq);
+            match &entry.op {
+                JournalOp::Add {id, parent, index, data} => {
+                    writer.u8(0);
+                    writer.str(id);
+                    writer.opt_str(parent.as_deref());
+                    writer.opt_index(*index);
+                    writer.opt_data(data.as_ref(), &mut encode)?;
+                },
+                JournalOp::Move {id, parent, index} => {
+                    writer.u8(1);
+                    writer.str(id);
+                    writer.str(parent);
+                    writer.opt_index(*index);
+                },
+                JournalOp::Remove {id, keep_children} => {
+                    writer.u8(2);
+                    writer.str(id);
+                    writer.bool(*keep_children);
+                },
+                JournalOp::SetData {id, data} => {
+                    writer.u8(3);
+                    writer.str(id);
+                    writer.opt_data(data.as_ref(), &mut encode)?;
+                },
+                JournalOp::Reorder {parent, ids} => {
+                    writer.u8(4);
+                    writer.str(parent);
+                    writer.u64(ids.len() as u64);
+                    for id in ids {
+                        writer.str(id);
+                    }
+                }
+            }
+        }
+        Ok(writer.finish())
+    }
+
+    // Reads what to_bytes wrote, decode turns the bytes of a payload back into a T
+    pub fn from_bytes(bytes: &[u8], mut decode: impl FnMut(&[u8]) -> Result<T>) -> Result<Journal<T>> {
+        let mut reader = Reader::new(bytes, MAGIC)?;
+        let root_id = reader.str()?;
+        let count = reader.u64()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let seq = reader.u64()?;
+            let op = match reader.u8()? {
+                0 => JournalOp::Add {id: reader.str()?, parent: reader.opt_str()?, index: reader.opt_index()?, data: reader.opt_data(&mut decode)?},
+                1 => JournalOp::Move {id: reader.str()?, parent: reader.str()?, index: reader.opt_index()?},
+                2 => JournalOp::Remove {id: reader.str()?, keep_children: reader.bool()?},
+                3 => JournalOp::SetData {id: reader.str()?, data: reader.opt_data(&mut decode)?},
+                4 => {
+                    let parent = reader.str()?;
+                    let len = reader.u64()?;
+                    let ids = (0..len).map(|_| reader.str()).collect::<Result<Vec<_>>>()?;
+                    JournalOp::Reorder {parent, ids}
+                },
+                tag => return Err(TreeError::MalformedInput(format!("unknown journal operation {}", tag)))
+            };
+            entries.push(JournalEntry {seq, op});
+        }
+        reader.finish()?;
+        Ok(Journal {root_id, entries})
+    }
+}
+
+// The journal a TreeMap is writing to. Payloads are copied into it with clone_data, which is taken
+// from T: Clone when the journal is started so that recording works for any T.
+pub(crate) struct Recorder<T> {
+    journal: Journal<T>,
+    clone_data: fn(&T) -> T,
+}
+
+impl<T> TreeMap<T> {
+    // Starts an empty journal of every mutation made to the tree from now on, including the ones
+    // made by transactions, undo and redo. Replaying it rebuilds the tree when it is started on a
+    // new tree, otherwise it advances a copy taken at this point.
+    pub fn enable_journal(&self) -> Result<()> where T: Clone {
+        let root_id = self.find_by_id("root")?.read().unwrap().id.clone();
+        *self.journal.lock().unwrap() = Some(Recorder {journal: Journal {root_id, entries: Vec::new()}, clone_data: T::clone});
+        Ok(())
+    }
+
+    pub fn disable_journal(&self) {
+        *self.journal.lock().unwrap() = None;
+    }
+
+    // A copy of the entries after seq, None without a journal
+    pub fn journal(&self, since: u64) -> Option<Journal<T>> where T: Clone {
+        self.journal.lock().unwrap().as_ref().map(|recorder| recorder.journal.since(since))
+    }
+
+    // seq of the last entry, None without a journal
+    pub fn journal_seq(&self) -> Option<u64> {
+        self.journal.lock().unwrap().as_ref().map(|recorder| recorder.journal.last_seq())
+    }
+
+    // Rebuilds a tree from a journal that was started on a new tree
+    pub fn replay(journal: &Journal<T>) -> Result<Arc<RwLock<TreeMap<T>>>> where T: Clone {
+        let root = NodeMap::new();
+        root.write().unwrap().id = journal.root_id.clone();
+        let tree = TreeMap::new(Some(root));
+        tree.read().unwrap().apply(journal)?;
+        Ok(tree)
+    }
+
+    // Applies the entries of a journal in order as one transaction, so on an error the tree is left
+    // as it was. Ids resolve against the tree and the nodes added earlier in the same journal.
+    pub fn apply(&self, journal: &Journal<T>) -> Result<()> where T: Clone {
+        let mut added: HashMap<String, Arc<RwLock<NodeMap<T>>>> = HashMap::new();
+        let resolve = |added: &HashMap<String, Arc<RwLock<NodeMap<T>>>>, id: &str| match added.get(id) {
+            Some(node) => Ok(node.clone()),
+            None => self.find_by_id(id)
+        };
+        let mut transaction = TreeMapTransaction::new();
+        for entry in &journal.entries {
+            match &entry.op {
+                JournalOp::Add {id, parent, index, data} => {
+                    let node = NodeMap::new();
+                    {
+                        let mut node_guard = node.write().unwrap();
+                        node_guard.id = id.clone();
+                        node_guard.data = data.clone();
+                    }
+                    added.insert(id.clone(), node.clone());
+                    let parent = match parent {
+                        Some(parent) => resolve(&added, parent)?,
+                        None => self.find_by_id("root")?
+                    };
+                    match index {
+                        Some(index) => transaction.insert_child(parent, node, *index),
+                        None => transaction.add_child(node, Some(parent))
+                    };
+                },
+                JournalOp::Move {id, parent, index} => {
+                    let (node, parent) = (resolve(&added, id)?, resolve(&added, parent)?);
+                    match index {
+                        Some(index) => transaction.move_node_at(node, parent, *index),
+                        None => transaction.move_node(node, parent)
+                    };
+                },
+                JournalOp::Remove {id, keep_children} => {
+                    transaction.remove_node(resolve(&added, id)?, *keep_children);
+                },
+                JournalOp::SetData {id, data} => {
+                    transaction.set_data(resolve(&added, id)?, data.clone());
+                },
+                JournalOp::Reorder {parent, ids} => {
+                    transaction.reorder_children(resolve(&added, parent)?, ids.clone());
+                }
+            }
+        }
+        self.commit(transaction)
+    }
+
+    // Appends an entry when a journal is running, make builds the op given the clone of T
+    pub(crate) fn journal_op(&self, make: impl FnOnce(fn(&T) -> T) -> JournalOp<T>) {
+        if let Some(recorder) = self.journal.lock().unwrap().as_mut() {
+            let seq = recorder.journal.last_seq() + 1;
+            let op = make(recorder.clone_data);
+            recorder.journal.entries.push(JournalEntry {seq, op});
+        }
+    }
+
+    // Number of entries in the journal, for journal_truncate
+    pub(crate) fn journal_len(&self) -> usize {
+        self.journal.lock().unwrap().as_ref().map_or(0, |recorder| recorder.journal.entries.len())
+    }
+
+    // Drops the entries after the first len, used when a transaction has been rolled back
+    pub(crate) fn journal_truncate(&self, len: usize) {
+        if let Some(recorder) = self.journal.lock().unwrap().as_mut() {
+            recorder.journal.entries.truncate(len);
+        }
+    }
+}
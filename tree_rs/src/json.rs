@@ -0,0 +1,321 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::arena::NodeIndex;
+use crate::{Node, NodeMap, Result, Tree, TreeError, TreeMap};
+
+// The nested {"id": .., "data": .., "children": [..]} layout export and load use in python. data
+// and children are left out when there is nothing in them. The nesting is read and written with an
+// explicit stack, so a tree can be as deep as it likes. Only ids and payloads go through serde_json.
+struct JsonNode {
+    id: Option<String>,
+    // Position of the parent, nodes are numbered in the order their objects open
+    parent: Option<usize>,
+    data: Option<Value>,
+}
+
+// What the parser is inside of, the flag is set until the first member or element has been read
+enum Open {
+    Object(usize, bool),
+    Children(usize, bool),
+}
+
+struct Parser<'a> {
+    json: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> TreeError {
+        TreeError::MalformedInput(format!("{} at position {}", message, self.position))
+    }
+
+    fn skip(&mut self) {
+        let rest = &self.json[self.position..];
+        self.position += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip();
+        self.json.as_bytes().get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<()> {
+        match self.peek() {
+            Some(next) if next == expected => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(next) => Err(self.error(&format!("expected '{}', found '{}'", expected as char, next as char))),
+            None => Err(self.error(&format!("expected '{}', found the end of the data", expected as char))),
+        }
+    }
+
+    // A single JSON value through serde_json, whatever follows it is left for the parser
+    fn value<V: DeserializeOwned>(&mut self) -> Result<V> {
+        self.skip();
+        let mut values = serde_json::Deserializer::from_str(&self.json[self.position..]).into_iter::<V>();
+        match values.next() {
+            Some(Ok(value)) => {
+                self.position += values.byte_offset();
+                Ok(value)
+            },
+            Some(Err(err)) => Err(self.error(&err.to_string())),
+            None => Err(self.error("unexpected end of the data")),
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<JsonNode>> {
+        let mut nodes = vec![JsonNode {id: None, parent: None, data: None}];
+        self.expect(b'{')?;
+        let mut stack = vec![Open::Object(0, true)];
+        while let Some(open) = stack.last_mut() {
+            match open {
+                Open::Object(index, first) => {
+                    let index = *index;
+                    if self.peek() == Some(b'}') {
+                        self.position += 1;
+                        stack.pop();
+                        continue;
+                    }
+                    if !std::mem::replace(first, false) {
+                        self.expect(b',')?;
+                    }
+                    let key: String = self.value()?;
+                    self.expect(b':')?;
+                    let node = &mut nodes[index];
+                    match key.as_str() {
+                        "id" if node.id.is_none() => node.id = Some(self.value().map_err(|_| self.error("'id' must be a string"))?),
+                        "data" if node.data.is_none() => node.data = self.value::<Option<Value>>()?,
+                        "children" => {
+                            self.expect(b'[')?;
+                            stack.push(Open::Children(index, true));
+                        },
+                        "id" | "data" => return Err(self.error(&format!("duplicate field '{}'", key))),
+                        _ => {
+                            self.value::<IgnoredAny>()?;
+                        },
+                    }
+                },
+                Open::Children(parent, first) => {
+                    let parent = *parent;
+                    if self.peek() == Some(b']') {
+                        self.position += 1;
+                        stack.pop();
+                        continue;
+                    }
+                    if !std::mem::replace(first, false) {
+                        self.expect(b',')?;
+                    }
+                    self.expect(b'{')?;
+                    nodes.push(JsonNode {id: None, parent: Some(parent), data: None});
+                    stack.push(Open::Object(nodes.len() - 1, true));
+                },
+            }
+        }
+        if self.peek().is_some() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(nodes)
+    }
+}
+
+// The root's id and data, then the (id, parent id, data) of every other node with parents before
+// their children
+#[allow(clippy::type_complexity)]
+fn parse<T>(json: &str, mut data: impl FnMut(Value) -> Result<T>) -> Result<((String, Option<T>), Vec<(String, Option<String>, Option<T>)>)> {
+    let nodes = Parser {json, position: 0}.parse()?;
+    let ids = nodes.iter().map(|node| node.id.clone().ok_or_else(|| TreeError::MalformedInput("missing field 'id'".to_string()))).collect::<Result<Vec<String>>>()?;
+    let mut records = Vec::with_capacity(nodes.len() - 1);
+    let mut root_data = None;
+    for (node, id) in nodes.into_iter().zip(&ids) {
+        let node_data = node.data.map(&mut data).transpose()?;
+        match node.parent {
+            Some(parent) => records.push((id.clone(), Some(ids[parent].clone()), node_data)),
+            None => root_data = node_data,
+        }
+    }
+    Ok(((ids[0].clone(), root_data), records))
+}
+
+// Compact without an indent, otherwise one entry per line indented by that many spaces, the same
+// text serde_json's pretty printer gives
+struct Writer {
+    json: String,
+    indent: Option<String>,
+}
+
+impl Writer {
+    fn newline(&mut self, level: usize) {
+        if let Some(indent) = &self.indent {
+            self.json.push('\n');
+            for _ in 0..level {
+                self.json.push_str(indent);
+            }
+        }
+    }
+
+    fn key(&mut self, key: &str, level: usize) {
+        self.newline(level);
+        self.json.push('"');
+        self.json.push_str(key);
+        self.json.push_str(if self.indent.is_some() { "\": " } else { "\":" });
+    }
+
+    fn value(&mut self, value: &impl Serialize, level: usize) -> Result<()> {
+        let written = match &self.indent {
+            None => serde_json::to_string(value),
+            Some(indent) => {
+                let mut bytes = Vec::new();
+                let mut serializer = serde_json::Serializer::with_formatter(&mut bytes, serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes()));
+                value.serialize(&mut serializer).map(|_| String::from_utf8(bytes).unwrap())
+            }
+        };
+        let written = written.map_err(|err| TreeError::MalformedInput(err.to_string()))?;
+        // Strings are escaped, the only line breaks are the printer's and they move in to this level
+        match &self.indent {
+            Some(indent) if written.contains('\n') => self.json.push_str(&written.replace('\n', &format!("\n{}", indent.repeat(level)))),
+            _ => self.json.push_str(&written),
+        }
+        Ok(())
+    }
+
+    // visit gives the id, converted data and children of a node
+    fn write<N>(mut self, root: N, mut visit: impl FnMut(&N) -> Result<(String, Option<Value>, Vec<N>)>) -> Result<String> {
+        enum Step<N> {
+            // The node, the level of its braces and whether a sibling comes before it
+            Open(N, usize, bool),
+            // Ends the children of the node whose braces are at that level
+            Close(usize),
+        }
+        let mut steps = vec![Step::Open(root, 0, false)];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Open(node, level, after_sibling) => {
+                    if after_sibling {
+                        self.json.push(',');
+                    }
+                    if level > 0 {
+                        self.newline(level);
+                    }
+                    let (id, data, children) = visit(&node)?;
+                    self.json.push('{');
+                    self.key("id", level + 1);
+                    self.value(&id, level + 1)?;
+                    if let Some(data) = data {
+                        self.json.push(',');
+                        self.key("data", level + 1);
+                        self.value(&data, level + 1)?;
+                    }
+                    if children.is_empty() {
+                        self.newline(level);
+                        self.json.push('}');
+                        continue;
+                    }
+                    self.json.push(',');
+                    self.key("children", level + 1);
+                    self.json.push('[');
+                    steps.push(Step::Close(level));
+                    steps.extend(children.into_iter().enumerate().rev().map(|(position, child)| Step::Open(child, level + 2, position > 0)));
+                },
+                Step::Close(level) => {
+                    self.newline(level + 1);
+                    self.json.push(']');
+                    self.newline(level);
+                    self.json.push('}');
+                },
+            }
+        }
+        Ok(self.json)
+    }
+}
+
+fn data_error(id: &str, err: TreeError) -> TreeError {
+    let message = match err {
+        TreeError::MalformedInput(message) => message,
+        other => other.to_string()
+    };
+    TreeError::MalformedInput(format!("data of node '{}': {}", id, message))
+}
+
+fn writer(indent: Option<usize>) -> Writer {
+    Writer {json: String::new(), indent: indent.map(|width| " ".repeat(width))}
+}
+
+fn to_value<T: Serialize>(data: &T) -> Result<Value> {
+    serde_json::to_value(data).map_err(|err| TreeError::MalformedInput(err.to_string()))
+}
+
+fn from_value<T: DeserializeOwned>(data: Value) -> Result<T> {
+    serde_json::from_value(data).map_err(|err| TreeError::MalformedInput(err.to_string()))
+}
+
+impl<T> Tree<T> {
+    // The tree as nested {"id", "data", "children"} objects, the same layout export gives in python
+    pub fn to_json(&self, indent: Option<usize>) -> Result<String> where T: Serialize {
+        self.to_json_with(indent, &to_value)
+    }
+
+    // to_json for payloads that are not Serialize, data converts each one
+    pub fn to_json_with(&self, indent: Option<usize>, data: &dyn Fn(&T) -> Result<Value>) -> Result<String> {
+        writer(indent).write(self.root.clone(), |node: &Arc<Mutex<Node<T>>>| {
+            let node_guard = node.lock().unwrap();
+            let value = node_guard.data.as_ref().map(data).transpose().map_err(|err| data_error(&node_guard.id, err))?;
+            let children = node_guard.children.lock().unwrap().clone();
+            Ok((node_guard.id.clone(), value, children))
+        })
+    }
+
+    // Builds a tree from what to_json wrote, the outermost object becomes the root
+    pub fn from_json(json: &str) -> Result<Arc<Mutex<Tree<T>>>> where T: DeserializeOwned {
+        Self::from_json_with(json, from_value)
+    }
+
+    pub fn from_json_with(json: &str, data: impl FnMut(Value) -> Result<T>) -> Result<Arc<Mutex<Tree<T>>>> {
+        let ((id, root_data), records) = parse(json, data)?;
+        let root = Node::new(root_data, None);
+        root.lock().unwrap().id = id;
+        let tree = Tree::new(Some(root))?;
+        tree.lock().unwrap().extend(records)?;
+        Ok(tree)
+    }
+}
+
+impl<T> TreeMap<T> {
+    // The tree as nested {"id", "data", "children"} objects, the same layout export gives in python
+    pub fn to_json(&self, indent: Option<usize>) -> Result<String> where T: Serialize {
+        self.to_json_with(indent, &to_value)
+    }
+
+    // to_json for payloads that are not Serialize, data converts each one
+    pub fn to_json_with(&self, indent: Option<usize>, data: &dyn Fn(&T) -> Result<Value>) -> Result<String> {
+        let arena = self.arena.read().unwrap();
+        let store_guard = self.data.read().unwrap();
+        writer(indent).write(arena.root(), |slot: &NodeIndex| {
+            let node = arena.node(*slot);
+            let value = store_guard.get(&node.id).map(data).transpose().map_err(|err| data_error(&node.id, err))?;
+            Ok((node.id.clone(), value, node.children.clone()))
+        })
+    }
+
+    // Builds a tree from what to_json wrote, the outermost object becomes the root
+    pub fn from_json(json: &str) -> Result<Arc<RwLock<TreeMap<T>>>> where T: DeserializeOwned {
+        Self::from_json_with(json, from_value)
+    }
+
+    pub fn from_json_with(json: &str, data: impl FnMut(Value) -> Result<T>) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        let ((id, root_data), records) = parse(json, data)?;
+        let root = NodeMap::new();
+        {
+            let mut root_guard = root.write().unwrap();
+            root_guard.id = id;
+            root_guard.data = root_data;
+        }
+        let tree = TreeMap::new(Some(root));
+        tree.read().unwrap().extend(records)?;
+        Ok(tree)
+    }
+}
@@ -1,323 +1,2688 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex, RwLock, Weak as AWeak};
 use uuid::Uuid;
-use anyhow::{Result, anyhow};
-use pyo3::{PyObject, Python, ToPyObject};
 
-pub struct Tree {
-    pub root: Arc<Mutex<Node>>,
+mod error;
+pub use error::{Result, TreeError};
+
+pub mod traversal;
+use traversal::{BreadthFirstIter, LevelOrderIter, NodeChildren, NodeMapChildren, PostOrderIter, PreOrderIter};
+
+mod arena;
+use arena::{Arena, NodeIndex};
+
+mod handles;
+use handles::Handles;
+mod transaction;
+pub use transaction::{Change, Commit, Op, Transaction, TreeMapTransaction, TreeTransaction};
+mod history;
+use history::History;
+pub use history::Undo;
+mod hooks;
+pub use hooks::{Event, Hook, HookError, Hooked, Hooks};
+mod codec;
+mod journal;
+use journal::Recorder;
+pub use journal::{Journal, JournalEntry, JournalOp};
+mod json;
+mod snapshot;
+mod records;
+pub use records::Record;
+mod encodings;
+mod newick;
+mod diagram;
+pub use diagram::{DiagramOptions, Direction};
+mod render;
+pub use render::{RenderOptions, RenderStyle, SortChildren, Subtree};
+
+#[cfg(feature = "pyo3")]
+pub mod py;
+
+#[allow(clippy::type_complexity)]
+pub struct Tree<T> {
+    pub root: Arc<Mutex<Node<T>>>,
+    // id -> node for every node on the tree, kept up to date by the methods that attach or remove
+    // nodes. Weak so that a subtree dropped by its last owner is still freed.
+    index: RwLock<HashMap<String, AWeak<Mutex<Node<T>>>>>,
+    // None until enable_history is called
+    history: Mutex<Option<History<Arc<Mutex<Node<T>>>, T>>>,
+    hooks: RwLock<Hooks>,
 }
 
-pub struct TreeMap {
-    pub nodes: Arc<RwLock<HashMap<String,Arc<RwLock<NodeMap>>>>>,
+#[allow(clippy::type_complexity)]
+pub struct TreeMap<T> {
+    // Every node on the tree and how they hang together, the handles are the NodeMaps in the arena
+    arena: Arc<RwLock<Arena<T>>>,
+    // Payloads of the nodes on this tree keyed by node id, kept apart from the relationships
+    pub data: Arc<RwLock<HashMap<String, T>>>,
+    // Handed to every NodeMap on the tree so it can resolve its relatives against this instance
+    this: AWeak<RwLock<TreeMap<T>>>,
+    // None until enable_history is called
+    history: Mutex<Option<History<Arc<RwLock<NodeMap<T>>>, T>>>,
+    hooks: RwLock<Hooks>,
+    // None until enable_journal is called
+    journal: Mutex<Option<Recorder<T>>>,
 }
 
-impl TreeMap {
-    pub fn new(root: Option<Arc<RwLock<NodeMap>>>) -> Self {
-        let nodes = Arc::new(RwLock::new(HashMap::with_capacity(100)));
-        match root {
-            Some(node) => {
-                let node_id = node.read().unwrap().id.clone();
-                let mut nodes_guard = nodes.write().unwrap();
-                nodes_guard.insert(node_id, node.clone());
-                nodes_guard.insert("root".to_string(),node)
-            },
-            None => {
-                let node = NodeMap::new(None);
-                let node_id = node.read().unwrap().id.clone();
-                let mut nodes_guard = nodes.write().unwrap();
-                nodes_guard.insert(node_id, node.clone());
-                nodes_guard.insert("root".to_string(),node)
+impl<T> TreeMap<T> {
+    pub fn new(root: Option<Arc<RwLock<NodeMap<T>>>>) -> Arc<RwLock<Self>> {
+        let data = Arc::new(RwLock::new(HashMap::with_capacity(100)));
+        let node = root.unwrap_or_else(NodeMap::new);
+        Arc::new_cyclic(|this: &AWeak<RwLock<TreeMap<T>>>| {
+            let mut node_guard = node.write().unwrap();
+            node_guard.tree = Some(this.clone());
+            if let Some(value) = node_guard.data.take() {
+                data.write().unwrap().insert(node_guard.id.clone(), value);
             }
-        };
-        Self {nodes}
+            let arena = Arena::new(node_guard.id.clone(), node.clone());
+            node_guard.index = Some(arena.root());
+            drop(node_guard);
+            RwLock::new(Self {arena: Arc::new(RwLock::new(arena)), data, this: this.clone(), history: Mutex::new(None), hooks: RwLock::default(), journal: Mutex::new(None)})
+        })
+    }
+
+    pub fn root(&self) -> Arc<RwLock<NodeMap<T>>> {
+        let arena = self.arena.read().unwrap();
+        arena.node(arena.root()).handle.clone()
+    }
+
+    // Number of nodes on the tree, counting the root
+    pub fn len(&self) -> usize {
+        self.arena.read().unwrap().len()
+    }
+
+    // A tree always holds its root
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn add_child(&self, child: &Arc<RwLock<NodeMap<T>>>, parent: Option<&Arc<RwLock<NodeMap<T>>>>) -> Result<()> {
+        self.attach(child, parent, None)?;
+        self.record(vec![Change::Added {node: child.clone(), keep_children: false}]);
+        Ok(())
     }
 
-    pub fn add_child(&self, child: &Arc<RwLock<NodeMap>>, parent: Option<&Arc<RwLock<NodeMap>>>) -> Result<()> {
-        let mut nodes_guard = self.nodes.write().unwrap();
-        nodes_guard.insert(child.read().unwrap().id.clone(), child.clone());
-        // TODO decide how to handle a parent not being on the tree
-        // nodes_guard.insert(parent.unwrap().as_ref().read().unwrap().id.clone(), parent.unwrap().clone());
+    // Adds child under parent at index, an index past the end appends
+    pub fn insert_child(&self, parent: &Arc<RwLock<NodeMap<T>>>, child: &Arc<RwLock<NodeMap<T>>>, index: usize) -> Result<()> {
+        self.attach(child, Some(parent), Some(index))?;
+        self.record(vec![Change::Added {node: child.clone(), keep_children: false}]);
+        Ok(())
+    }
 
+    fn attach(&self, child: &Arc<RwLock<NodeMap<T>>>, parent: Option<&Arc<RwLock<NodeMap<T>>>>, index: Option<usize>) -> Result<()> {
+        let mut arena = self.arena.write().unwrap();
+        let child_id = self.check_joinable(&arena, child)?;
         // Checks for parent inside option, if no parent, make parent 'root node'
-        let (parent_id, mut parent_guard) = match parent {
-            Some(node) => {
-                let parent_guard = node.write().unwrap();
-                (parent_guard.id.clone(), parent_guard)
-            }
-            None => {
-                ("root".to_string(), nodes_guard.get("root").unwrap().write().unwrap())
-            }
+        let parent = match parent {
+            Some(node) => arena.index_of(node)?,
+            None => arena.root()
         };
-        let mut child_guard = child.write().unwrap();
-        parent_guard.children.push(child_guard.id.clone());
-        child_guard.parent = Some(parent_id);
+        self.link(&mut arena, child, child_id, parent, index);
+        Ok(())
+    }
+
+    // Adds every (child, parent) pair in one go under a single lock of the tree. A parent of None
+    // means the root, otherwise it has to be on the tree already or be one of the children in the
+    // batch, in any order. Everything is validated first, on an error nothing has been added.
+    #[allow(clippy::type_complexity)]
+    pub fn add_many(&self, pairs: &[(Arc<RwLock<NodeMap<T>>>, Option<Arc<RwLock<NodeMap<T>>>>)]) -> Result<()> {
+        let mut arena = self.arena.write().unwrap();
+        let mut batch_ids: HashSet<String> = HashSet::with_capacity(pairs.len());
+        for (child, _) in pairs {
+            let child_id = self.check_joinable(&arena, child)?;
+            if !batch_ids.insert(child_id.clone()) {
+                return Err(TreeError::DuplicateId(child_id));
+            }
+        }
+        let order = parents_first(pairs, |parent| arena.slot_of(parent).is_some(), |node| node.read().unwrap().id.clone())?;
 
+        let mut changes = Vec::with_capacity(pairs.len());
+        for position in order {
+            let (child, parent) = &pairs[position];
+            // Parents come first, so one from the batch is already on the tree
+            let parent = match parent {
+                Some(parent) => arena.index_of(parent)?,
+                None => arena.root()
+            };
+            let child_id = child.read().unwrap().id.clone();
+            self.link(&mut arena, child, child_id, parent, None);
+            changes.push(Change::Added {node: child.clone(), keep_children: false});
+        }
+        drop(arena);
+        // The whole batch is one entry in the history
+        self.record(changes);
         Ok(())
     }
 
-    pub fn find_by_id(&self, id: &str) -> Result<Arc<RwLock<NodeMap>>> {
-        Ok(self.nodes.read().unwrap().get(id).unwrap().clone())
+    // Builds a node for every (id, parent_id, data) record and adds them with add_many, a parent_id
+    // of None means the root. Returns the new nodes in the order of the records.
+    pub fn extend(&self, records: Vec<(String, Option<String>, Option<T>)>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let pairs = self.pairs_from_records(records)?;
+        self.add_many(&pairs)?;
+        Ok(pairs.into_iter().map(|(node, _)| node).collect())
     }
 
-    pub fn get_ancestors(&self, node: &Arc<RwLock<NodeMap>>) -> Result<Vec<Arc<RwLock<NodeMap>>>> {
-        let mut collection: Vec<Arc<RwLock<NodeMap>>> = Vec::with_capacity(50);
-        get_nodemap_ancestors_recursive(self, &node, &mut collection);
-        collection.shrink_to_fit();
-        Ok(collection)
+    // The (node, parent) pairs extend adds, a parent is looked up among the records first and then on the tree
+    #[allow(clippy::type_complexity)]
+    pub fn pairs_from_records(&self, records: Vec<(String, Option<String>, Option<T>)>) -> Result<Vec<(Arc<RwLock<NodeMap<T>>>, Option<Arc<RwLock<NodeMap<T>>>>)>> {
+        let mut batch: HashMap<String, Arc<RwLock<NodeMap<T>>>> = HashMap::with_capacity(records.len());
+        let mut nodes = Vec::with_capacity(records.len());
+        let mut parent_ids = Vec::with_capacity(records.len());
+        for (id, parent_id, data) in records {
+            let node = NodeMap::new();
+            {
+                let mut node_guard = node.write().unwrap();
+                node_guard.id = id.clone();
+                node_guard.data = data;
+            }
+            if batch.insert(id.clone(), node.clone()).is_some() {
+                return Err(TreeError::DuplicateId(id));
+            }
+            nodes.push(node);
+            parent_ids.push(parent_id);
+        }
+
+        let arena = self.arena.read().unwrap();
+        let pairs = nodes.iter().zip(parent_ids).map(|(node, parent_id)| {
+            let parent = match parent_id {
+                Some(parent_id) => {
+                    let on_tree = || arena.find(&parent_id).map(|index| arena.node(index).handle.clone());
+                    Some(batch.get(&parent_id).cloned().or_else(on_tree).ok_or(TreeError::NodeNotFound(parent_id))?)
+                },
+                None => None
+            };
+            Ok((node.clone(), parent))
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(pairs)
     }
 
-// TODO - need this for when we start dropping NodeMaps
-    // pub fn remove_from_parent(&self, node: &Arc<RwLock<NodeMap>>) {
-    // }
-
-    pub fn move_node(&self, tgt_node: &Arc<RwLock<NodeMap>>, new_parent: &Arc<RwLock<NodeMap>>) -> Result<()> {
-        // If child is an ancestor of new_parent Error out
-        if self.get_ancestors(new_parent).unwrap().iter().any(|node| Arc::ptr_eq(node, &tgt_node)) {
-            Err(anyhow!("Input node is ancestor of parent, cannot move."))?
-        }
-        
-        // obtain tgt_node write lock to pull out data and set new parent
-        let mut tgt_node_guard = tgt_node.write().unwrap();
-        let tgt_node_id = tgt_node_guard.id.clone();
-        
-        {  
-            // obtain old_parent_write_guard, find the tgt_node id and remove it.
-            let old_parent = self.nodes.read().unwrap().get(tgt_node_guard.parent.as_ref().unwrap()).unwrap().clone();
-            let mut old_parent_write_guard = old_parent.write().unwrap();
-            if let Some(index) = old_parent_write_guard.children.iter().position(|x| x == &tgt_node_id){
-                old_parent_write_guard.children.remove(index);
+    // Checks child may be added to this tree and returns its id
+    fn check_joinable(&self, arena: &Arena<T>, child: &Arc<RwLock<NodeMap<T>>>) -> Result<String> {
+        let child_guard = child.read().unwrap();
+        // A node can only live on one tree, it has to be detached before it can join another
+        if let Some(tree) = child_guard.get_tree() {
+            if !AWeak::ptr_eq(&Arc::downgrade(&tree), &self.this) {
+                return Err(TreeError::AttachedElsewhere(child_guard.id.clone()));
             }
         }
+        if arena.contains_id(&child_guard.id) {
+            return Err(TreeError::DuplicateId(child_guard.id.clone()));
+        }
+        Ok(child_guard.id.clone())
+    }
 
-        let mut new_parent_guard = new_parent.write().unwrap();
-        new_parent_guard.children.push(tgt_node_id);
-        tgt_node_guard.parent = Some(new_parent_guard.id.clone());
-        drop(new_parent_guard);
-        drop(tgt_node_guard);    
+    // Hooks an already validated child under the node in the parent slot
+    fn link(&self, arena: &mut Arena<T>, child: &Arc<RwLock<NodeMap<T>>>, child_id: String, parent: NodeIndex, index: Option<usize>) {
+        let slot = arena.insert(child_id, child.clone(), parent, index);
+        let mut child_guard = child.write().unwrap();
+        child_guard.index = Some(slot);
+        child_guard.tree = Some(self.this.clone());
+        // The payload moves off the detached node and into this tree's store
+        if let Some(value) = child_guard.data.take() {
+            self.data.write().unwrap().insert(child_guard.id.clone(), value);
+        }
+        self.journal_op(|clone_data| JournalOp::Add {
+            id: child_guard.id.clone(),
+            parent: Some(arena.node(parent).id.clone()),
+            index,
+            data: self.data.read().unwrap().get(&child_guard.id).map(clone_data)
+        });
+    }
 
-        Ok(())
+    pub fn find_by_id(&self, id: &str) -> Result<Arc<RwLock<NodeMap<T>>>> {
+        let arena = self.arena.read().unwrap();
+        arena.find(id).map(|index| arena.node(index).handle.clone()).ok_or_else(|| TreeError::NodeNotFound(id.to_string()))
     }
-}
 
-pub fn get_nodemap_ancestors_recursive(tree: &TreeMap, node: &Arc<RwLock<NodeMap>>, collection: &mut Vec<Arc<RwLock<NodeMap>>>) {
-    if let Some(parent) = node.read().unwrap().parent.clone() {
-        let parent_node = tree.nodes.read().unwrap().get(&parent).unwrap().clone();
-        collection.push(parent_node.clone());
-        get_nodemap_ancestors_recursive(tree, &parent_node, collection);
+    pub fn get_data(&self, id: &str) -> Option<T> where T: Clone {
+        self.data.read().unwrap().get(id).cloned()
     }
-}
 
-impl Tree {
-    pub fn new(root: Option<Arc<Mutex<Node>>>) -> Arc<Mutex<Self>> {
-        match root {
-            Some(node) => Arc::new(Mutex::new(Self {root: node})),
-            None => Arc::new(Mutex::new(Self {root: Node::new(py_none(), None)}))
+    pub fn set_data(&self, id: &str, data: Option<T>) {
+        let previous = self.store_data(id, data);
+        if let Ok(node) = self.find_by_id(id) {
+            self.record(vec![Change::DataSet {node, data: previous}]);
         }
     }
 
-    pub fn add_child(&self, child: Arc<Mutex<Node>>, parent_node: Option<Arc<Mutex<Node>>>) {
-        let parent: Arc<Mutex<Node>> = parent_node.unwrap_or_else(|| self.root.clone());
-        {
-            parent.lock().unwrap()
-                .children.lock().unwrap()
-                .push(Arc::clone(&child));
+    // Puts data in the store under id and returns what was there before
+    fn store_data(&self, id: &str, data: Option<T>) -> Option<T> {
+        let mut data_guard = self.data.write().unwrap();
+        let previous = match data {
+            Some(value) => data_guard.insert(id.to_string(), value),
+            None => data_guard.remove(id)
+        };
+        drop(data_guard);
+        if self.arena.read().unwrap().contains_id(id) {
+            self.journal_op(|clone_data| JournalOp::SetData {id: id.to_string(), data: self.data.read().unwrap().get(id).map(clone_data)});
         }
-        let self_weak: AWeak<Mutex<Node>> = Arc::downgrade(&parent);
-        child.lock().unwrap().parent = Some(self_weak);
+        previous
     }
 
-    pub fn find_by_id(&self, id: &str) -> Option<Arc<Mutex<Node>>> {
-        let mut stack = VecDeque::new();
-        stack.push_back(self.root.clone());
+    // The node's parent, None for the root
+    pub fn parent(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.node(index).parent.map(|parent| arena.node(parent).handle.clone()))
+    }
 
-        while let Some(node_arc) = stack.pop_front() {
-            let node = node_arc.lock().unwrap();
+    pub fn children(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.handles(&arena.node(index).children))
+    }
 
-            if node.id == id {
-                return Some(node_arc.clone());
-            }
+    pub fn get_ancestors(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.handles(&arena.ancestors(index)))
+    }
 
-            for child in node.children.lock().unwrap().iter(){
-                stack.push_back(child.clone());
-            }
+    // Everything below the node in pre-order, the node itself is not included
+    pub fn get_descendants(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.handles(&arena.subtree(index)[1..]))
+    }
+
+    // The childless nodes of the subtree in pre-order, a leaf is its own only leaf
+    pub fn get_leaves(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        let leaves: Vec<NodeIndex> = arena.subtree(index).into_iter().filter(|descendant| arena.node(*descendant).children.is_empty()).collect();
+        Ok(arena.handles(&leaves))
+    }
+
+    // Number of nodes in the subtree, counting the node itself
+    pub fn subtree_size(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<usize> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.subtree(index).len())
+    }
+
+    pub fn is_leaf(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<bool> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.node(index).children.is_empty())
+    }
+
+    pub fn is_root(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<bool> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.node(index).parent.is_none())
+    }
+
+    // Number of edges between the node and the root, the root is at depth 0
+    pub fn depth(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<usize> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.ancestors(index).len())
+    }
+
+    // Number of edges on the longest path from the node down to a leaf, a leaf has height 0
+    pub fn height(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<usize> {
+        Ok(self.level_order(Some(node), None)?.count() - 1)
+    }
+
+    // Every node at depth level, left to right
+    pub fn nodes_at_level(&self, level: usize) -> Vec<Arc<RwLock<NodeMap<T>>>> {
+        self.level_order(None, Some(level)).ok().and_then(|mut levels| levels.nth(level)).unwrap_or_default()
+    }
+
+    // The node followed by its ancestors, ending with the root
+    pub fn path_to_root(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let mut path = vec![node.clone()];
+        path.extend(self.get_ancestors(node)?);
+        Ok(path)
+    }
+
+    // The deepest node that has both a and b in its subtree, a node counts as its own ancestor
+    pub fn lowest_common_ancestor(&self, a: &Arc<RwLock<NodeMap<T>>>, b: &Arc<RwLock<NodeMap<T>>>) -> Result<Arc<RwLock<NodeMap<T>>>> {
+        let a_path = self.path_to_root(a)?;
+        let shared = shared_tail_len(&a_path, &self.path_to_root(b)?);
+        Ok(a_path[a_path.len() - shared].clone())
+    }
+
+    // The nodes from a up to the lowest common ancestor and down to b, both ends included
+    pub fn path_between(&self, a: &Arc<RwLock<NodeMap<T>>>, b: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        Ok(join_paths(self.path_to_root(a)?, self.path_to_root(b)?))
+    }
+
+    // The other children of the node's parent in order, the root has none
+    pub fn siblings(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Vec<Arc<RwLock<NodeMap<T>>>>> {
+        let Some((siblings, index)) = self.parent_children(node)? else {
+            return Ok(Vec::new());
+        };
+        Ok(siblings.into_iter().enumerate()
+            .filter(|(position, _)| *position != index)
+            .map(|(_, sibling)| sibling)
+            .collect())
+    }
+
+    // Position of the node among its parent's children, None for the root
+    pub fn index_in_parent(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<usize>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.position(index).map(|(_, position)| position))
+    }
+
+    pub fn next_sibling(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<Arc<RwLock<NodeMap<T>>>>> {
+        Ok(self.parent_children(node)?.and_then(|(siblings, index)| siblings.get(index + 1).cloned()))
+    }
+
+    pub fn prev_sibling(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<Arc<RwLock<NodeMap<T>>>>> {
+        Ok(self.parent_children(node)?.and_then(|(siblings, index)| index.checked_sub(1).map(|prev| siblings[prev].clone())))
+    }
+
+    pub fn first_child(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.node(index).children.first().map(|child| arena.node(*child).handle.clone()))
+    }
+
+    pub fn last_child(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<Arc<RwLock<NodeMap<T>>>>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.node(index).children.last().map(|child| arena.node(*child).handle.clone()))
+    }
+
+    // Puts parent's children in the order of ids, which has to name every child exactly once
+    pub fn reorder_children(&self, parent: &Arc<RwLock<NodeMap<T>>>, ids: &[String]) -> Result<()> {
+        self.record_with(|| self.reorder_recorded(parent, ids), || self.reorder(parent, ids))
+    }
+
+    fn reorder(&self, parent: &Arc<RwLock<NodeMap<T>>>, ids: &[String]) -> Result<()> {
+        let mut arena = self.arena.write().unwrap();
+        let index = arena.index_of(parent)?;
+        let parent_id = arena.node(index).id.clone();
+        match reordered(&arena.node(index).children, ids, |child| arena.node(*child).id.clone()) {
+            Some(children) => {
+                arena.node_mut(index).children = children;
+                self.journal_op(|_| JournalOp::Reorder {parent: parent_id, ids: ids.to_vec()});
+                Ok(())
+            },
+            None => Err(TreeError::MalformedInput(format!("ids must name every child of '{}' exactly once", parent_id)))
         }
+    }
 
-        None
+    // The children of the node's parent and the node's position among them, None for the root
+    #[allow(clippy::type_complexity)]
+    fn parent_children(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Option<(Vec<Arc<RwLock<NodeMap<T>>>>, usize)>> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        Ok(arena.position(index).map(|(parent, position)| (arena.handles(&arena.node(parent).children), position)))
     }
 
-    pub fn get_ancestors(&self, node: &Arc<Mutex<Node>>) -> Vec<Arc<Mutex<Node>>> {
-        let mut collection: Vec<Arc<Mutex<Node>>> = Vec::new();
-        get_ancestors_recursive(node, &mut collection);
-        collection
+    // Removes the node from the tree. With keep_children its children take its place in the
+    // parent's children, otherwise the whole subtree leaves the tree with it.
+    pub fn remove_node(&self, node: &Arc<RwLock<NodeMap<T>>>, keep_children: bool) -> Result<()> {
+        self.record_with(|| self.remove_recorded(node, keep_children), || self.remove_from_tree(node, keep_children))
     }
 
-    pub fn move_node(&self, tgt_node: &Arc<Mutex<Node>>, new_parent_node: &Arc<Mutex<Node>>) -> (){
-        if self.get_ancestors(new_parent_node).iter().any(|ancestor| Arc::ptr_eq(ancestor, tgt_node)) {
-            println!("Operation not allowed: Cannot move a node into one of its descendants.");
-            return;
+    fn remove_from_tree(&self, node: &Arc<RwLock<NodeMap<T>>>, keep_children: bool) -> Result<()> {
+        let mut arena = self.arena.write().unwrap();
+        let index = arena.index_of(node)?;
+        let (parent, position) = arena.unlink(index)?;
+        let removed = arena.release(index);
+
+        if keep_children {
+            for child in &removed.children {
+                arena.node_mut(*child).parent = Some(parent);
+            }
+            arena.node_mut(parent).children.splice(position..position, removed.children);
+        } else {
+            let mut stack = removed.children;
+            while let Some(descendant) = stack.pop() {
+                let descendant = arena.release(descendant);
+                stack.extend(descendant.children);
+                self.release_node(&mut descendant.handle.write().unwrap());
+            }
         }
 
-        // Remove the node from its current parent's children list, if it has one
-        if let Some(ref parent_weak) = tgt_node.lock().unwrap().parent {
-            if let Some(parent) = parent_weak.upgrade() {
-                {
-                    let parent_borrowed = parent.lock().unwrap();
-                    let mut parent_children = parent_borrowed.children.lock().unwrap();
-                    if let Some(index) = parent_children.iter().position(|child| Arc::ptr_eq(child, tgt_node)) {
-                        parent_children.remove(index);
-                    }
+        let mut node_guard = node.write().unwrap();
+        self.release_node(&mut node_guard);
+        self.journal_op(|_| JournalOp::Remove {id: node_guard.id.clone(), keep_children});
+        Ok(())
+    }
+
+    // Cuts the subtree below (and including) node out of this tree and returns it as its own TreeMap,
+    // the payloads move across to the new tree's data store. This cannot be undone, the history
+    // is cleared as its entries may refer to the nodes that left, and a later undo that reaches
+    // back past it fails with HistoryCleared.
+    pub fn detach_subtree(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        let mut arena = self.arena.write().unwrap();
+        let index = arena.index_of(node)?;
+        arena.unlink(index)?;
+        if let Some(history) = self.history.lock().unwrap().as_mut() {
+            history.clear();
+        }
+
+        // Pre-order, each descendant with the position of its parent in the list and the subtree root at 0
+        let subtree = arena.subtree(index);
+        let positions: HashMap<NodeIndex, usize> = subtree.iter().enumerate().map(|(position, slot)| (*slot, position)).collect();
+        let mut descendants = Vec::with_capacity(subtree.len() - 1);
+        for slot in &subtree[1..] {
+            let parent_position = positions[&arena.node(*slot).parent.unwrap()];
+            descendants.push((arena.release(*slot), parent_position));
+        }
+        arena.release(index);
+        {
+            let mut node_guard = node.write().unwrap();
+            self.release_node(&mut node_guard);
+            self.journal_op(|_| JournalOp::Remove {id: node_guard.id.clone(), keep_children: false});
+        }
+        drop(arena);
+
+        let subtree = TreeMap::new(Some(node.clone()));
+        {
+            let subtree_guard = subtree.read().unwrap();
+            let mut subtree_arena = subtree_guard.arena.write().unwrap();
+            let mut subtree_data_guard = subtree_guard.data.write().unwrap();
+            let mut data_guard = self.data.write().unwrap();
+            let mut slots = vec![subtree_arena.root()];
+            for (descendant, parent_position) in descendants {
+                let slot = subtree_arena.insert(descendant.id.clone(), descendant.handle.clone(), slots[parent_position], None);
+                let mut descendant_guard = descendant.handle.write().unwrap();
+                descendant_guard.index = Some(slot);
+                descendant_guard.tree = Some(subtree_guard.this.clone());
+                if let Some(value) = data_guard.remove(&descendant.id) {
+                    subtree_data_guard.insert(descendant.id, value);
                 }
+                slots.push(slot);
             }
         }
+        Ok(subtree)
+    }
 
-        // Add the node to the new parent's children list
-        new_parent_node.lock().unwrap().children.lock().unwrap().push(Arc::clone(tgt_node));
+    // The walks start at start (the root when None) and stop max_depth levels below it
+    pub fn pre_order(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>, max_depth: Option<usize>) -> Result<PreOrderIter<NodeMapChildren<T>>> {
+        Ok(PreOrderIter::new(NodeMapChildren(self.arena.clone()), self.walk_start(start)?, max_depth))
+    }
 
-        // Update the parent reference in the target node
-        let new_parent_weak = Arc::downgrade(new_parent_node);
-        tgt_node.lock().unwrap().parent = Some(new_parent_weak);
+    pub fn post_order(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>, max_depth: Option<usize>) -> Result<PostOrderIter<NodeMapChildren<T>>> {
+        Ok(PostOrderIter::new(NodeMapChildren(self.arena.clone()), self.walk_start(start)?, max_depth))
     }
-}
 
-fn get_ancestors_recursive(node: &Arc<Mutex<Node>>, collection: &mut Vec<Arc<Mutex<Node>>>) {
-    if let Some(parent_weak) = node.lock().unwrap().parent.as_ref() {
-        if let Some(parent) = parent_weak.upgrade() {
-            collection.push(parent.clone());
-            get_ancestors_recursive(&parent, collection);
+    pub fn breadth_first(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>, max_depth: Option<usize>) -> Result<BreadthFirstIter<NodeMapChildren<T>>> {
+        Ok(BreadthFirstIter::new(NodeMapChildren(self.arena.clone()), self.walk_start(start)?, max_depth))
+    }
+
+    pub fn level_order(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>, max_depth: Option<usize>) -> Result<LevelOrderIter<NodeMapChildren<T>>> {
+        Ok(LevelOrderIter::new(NodeMapChildren(self.arena.clone()), self.walk_start(start)?, max_depth))
+    }
+
+    fn walk_start(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>) -> Result<Arc<RwLock<NodeMap<T>>>> {
+        match start {
+            Some(node) => {
+                self.check_on_tree(node)?;
+                Ok(node.clone())
+            },
+            None => Ok(self.root())
         }
     }
-}
 
-pub struct Node {
-    pub id: String,
-    pub data: PyObject,
-    pub children: Arc<Mutex<Vec<Arc<Mutex<Node>>>>>,
-    // Option only to cater for 'root'
-    pub parent: Option<AWeak<Mutex<Node>>>,
+    fn check_on_tree(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<()> {
+        self.arena.read().unwrap().index_of(node).map(|_| ())
+    }
+
+    // Detaches a node whose slot has already been released, its payload moves back onto it
+    fn release_node(&self, node_guard: &mut NodeMap<T>) {
+        node_guard.index = None;
+        node_guard.tree = None;
+        node_guard.data = self.data.write().unwrap().remove(&node_guard.id);
+    }
+
+    pub fn move_node(&self, tgt_node: &Arc<RwLock<NodeMap<T>>>, new_parent: &Arc<RwLock<NodeMap<T>>>) -> Result<()> {
+        self.record_with(|| self.move_recorded(tgt_node, new_parent, None), || self.relocate(tgt_node, new_parent, None))
+    }
+
+    // Moves the node to index among new_parent's children, counted after it has left its old place
+    pub fn move_node_at(&self, tgt_node: &Arc<RwLock<NodeMap<T>>>, new_parent: &Arc<RwLock<NodeMap<T>>>, index: usize) -> Result<()> {
+        self.record_with(|| self.move_recorded(tgt_node, new_parent, Some(index)), || self.relocate(tgt_node, new_parent, Some(index)))
+    }
+
+    fn relocate(&self, tgt_node: &Arc<RwLock<NodeMap<T>>>, new_parent: &Arc<RwLock<NodeMap<T>>>, index: Option<usize>) -> Result<()> {
+        let mut arena = self.arena.write().unwrap();
+        let tgt = arena.index_of(tgt_node)?;
+        let parent = arena.index_of(new_parent)?;
+        // If child is an ancestor of new_parent (or is new_parent) Error out
+        if tgt == parent || arena.ancestors(parent).contains(&tgt) {
+            return Err(TreeError::CycleDetected(arena.node(tgt).id.clone()));
+        }
+
+        arena.unlink(tgt)?;
+        insert_at(&mut arena.node_mut(parent).children, tgt, index);
+        arena.node_mut(tgt).parent = Some(parent);
+        self.journal_op(|_| JournalOp::Move {id: arena.node(tgt).id.clone(), parent: arena.node(parent).id.clone(), index});
+        Ok(())
+    }
 }
 
-impl Node {
-    pub fn new(data: PyObject, parent: Option<AWeak<Mutex<Node>>>) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            id: Uuid::new_v4().to_string(),
-            data,
-            children: Arc::new(Mutex::new(Vec::new())),
-            parent,
-        }))
+// A NodeMap on a tree keeps its payload in that tree's store, a detached NodeMap carries it itself
+pub fn get_nodemap_data<T: Clone>(node: &Arc<RwLock<NodeMap<T>>>) -> Option<T> {
+    let node_guard = node.read().unwrap();
+    match node_guard.get_tree() {
+        Some(tree) => {
+            let id = node_guard.id.clone();
+            drop(node_guard);
+            let data = tree.read().unwrap().get_data(&id);
+            data
+        },
+        None => node_guard.data.clone()
     }
 }
 
-pub struct NodeMap {
-    pub id: String,
-    pub children: Vec<String>,
-    pub parent: Option<String>,
+pub fn set_nodemap_data<T>(node: &Arc<RwLock<NodeMap<T>>>, data: Option<T>) {
+    let mut node_guard = node.write().unwrap();
+    match node_guard.get_tree() {
+        Some(tree) => {
+            let id = node_guard.id.clone();
+            drop(node_guard);
+            tree.read().unwrap().set_data(&id, data);
+        },
+        None => node_guard.data = data
+    }
 }
 
-impl NodeMap {
-    pub fn new(parent: Option<String>) -> Arc<RwLock<Self>> {
-        Arc::new(RwLock::new( Self {
-            id: Uuid::new_v4().to_string(),
-            children: Vec::with_capacity(5),
-            parent
+impl<T> Tree<T> {
+    pub fn new(root: Option<Arc<Mutex<Node<T>>>>) -> Result<Arc<Mutex<Self>>> {
+        let root = root.unwrap_or_else(|| Node::new(None, None));
+        // A root that arrives with a subtree (e.g. from load) is indexed in one pass, ids have to be
+        // unique within it as they are for every node added later
+        let mut index = HashMap::new();
+        for (id, node) in subtree_entries(&root) {
+            if index.insert(id.clone(), node).is_some() {
+                return Err(TreeError::DuplicateId(id));
+            }
+        }
+        Ok(Arc::new_cyclic(|this| {
+            root.lock().unwrap().tree = Some(this.clone());
+            Mutex::new(Self {root, index: RwLock::new(index), history: Mutex::new(None), hooks: RwLock::default()})
         }))
     }
-}
 
-pub fn py_none() -> PyObject {
-    Python::with_gil(|py| {
-        py.None().to_object(py)
-    })
-}
+    pub fn add_child(&self, child: Arc<Mutex<Node<T>>>, parent_node: Option<Arc<Mutex<Node<T>>>>) -> Result<()> {
+        self.attach(child.clone(), parent_node, None)?;
+        self.record(vec![Change::Added {node: child, keep_children: false}]);
+        Ok(())
+    }
 
-#[cfg(test)]
-mod tests {
-    // Note this useful idiom: importing names from outer (for mod tests) scope.
-    use super::*;
+    // Adds child under parent at index, an index past the end appends
+    pub fn insert_child(&self, parent: &Arc<Mutex<Node<T>>>, child: &Arc<Mutex<Node<T>>>, index: usize) -> Result<()> {
+        self.attach(child.clone(), Some(parent.clone()), Some(index))?;
+        self.record(vec![Change::Added {node: child.clone(), keep_children: false}]);
+        Ok(())
+    }
 
-    #[test]
-    fn test_add_node_to_empty_tree_mt(){
-        let tree = Tree::new(None);
-        let child_node = Node::new(py_none(), None);
+    fn attach(&self, child: Arc<Mutex<Node<T>>>, parent_node: Option<Arc<Mutex<Node<T>>>>, index: Option<usize>) -> Result<()> {
+        // Checked in the same order as TreeMap's attach, which transactions validate against
+        check_detached(&child)?;
+        let entries = subtree_entries(&child);
+        if let Some((id, _)) = entries.iter().find(|(id, _)| self.find_by_id(id).is_some()) {
+            return Err(TreeError::DuplicateId(id.clone()));
+        }
+        let parent: Arc<Mutex<Node<T>>> = parent_node.unwrap_or_else(|| self.root.clone());
+        self.check_on_tree(&parent)?;
+        self.link(&child, &parent, index, entries);
+        Ok(())
+    }
 
-        tree.lock().unwrap().add_child(child_node.clone(), None);
+    // Adds every (child, parent) pair in one call. A parent of None means the root, otherwise it has
+    // to be on the tree already or be one of the children in the batch, in any order. Everything is
+    // validated first, on an error nothing has been added.
+    #[allow(clippy::type_complexity)]
+    pub fn add_many(&self, pairs: &[(Arc<Mutex<Node<T>>>, Option<Arc<Mutex<Node<T>>>>)]) -> Result<()> {
+        let mut batch_ids: HashSet<String> = HashSet::with_capacity(pairs.len());
+        let mut batch_entries = Vec::with_capacity(pairs.len());
+        for (child, _) in pairs {
+            check_detached(child)?;
+            let entries = subtree_entries(child);
+            for (id, _) in &entries {
+                if self.find_by_id(id).is_some() || !batch_ids.insert(id.clone()) {
+                    return Err(TreeError::DuplicateId(id.clone()));
+                }
+            }
+            batch_entries.push(Some(entries));
+        }
+        let order = parents_first(pairs, |parent| self.contains(parent), |node| node.lock().unwrap().id.clone())?;
 
-        let child_parent_id = child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id.clone();
+        let mut changes = Vec::with_capacity(pairs.len());
+        for position in order {
+            let (child, parent) = &pairs[position];
+            let entries = batch_entries[position].take().unwrap();
+            self.link(child, parent.as_ref().unwrap_or(&self.root), None, entries);
+            changes.push(Change::Added {node: child.clone(), keep_children: false});
+        }
+        // The whole batch is one entry in the history
+        self.record(changes);
+        Ok(())
+    }
 
-        let root_id = tree.lock().unwrap().root.lock().unwrap().id.clone();
+    // Builds a node for every (id, parent_id, data) record and adds them with add_many, a parent_id
+    // of None means the root. Returns the new nodes in the order of the records.
+    pub fn extend(&self, records: Vec<(String, Option<String>, Option<T>)>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        let pairs = self.pairs_from_records(records)?;
+        self.add_many(&pairs)?;
+        Ok(pairs.into_iter().map(|(node, _)| node).collect())
+    }
 
-        assert_eq!(child_parent_id, root_id);
+    // The (node, parent) pairs extend adds, a parent is looked up among the records first and then on the tree
+    #[allow(clippy::type_complexity)]
+    pub fn pairs_from_records(&self, records: Vec<(String, Option<String>, Option<T>)>) -> Result<Vec<(Arc<Mutex<Node<T>>>, Option<Arc<Mutex<Node<T>>>>)>> {
+        let mut batch: HashMap<String, Arc<Mutex<Node<T>>>> = HashMap::with_capacity(records.len());
+        let mut nodes = Vec::with_capacity(records.len());
+        let mut parent_ids = Vec::with_capacity(records.len());
+        for (id, parent_id, data) in records {
+            let node = Node::new(data, None);
+            node.lock().unwrap().id = id.clone();
+            if batch.insert(id.clone(), node.clone()).is_some() {
+                return Err(TreeError::DuplicateId(id));
+            }
+            nodes.push(node);
+            parent_ids.push(parent_id);
+        }
 
-        assert!(tree.lock().unwrap().root.lock().unwrap().children.lock().unwrap().iter().any(|child| Arc::ptr_eq(&child, &child_node)));
+        let pairs = nodes.iter().zip(parent_ids).map(|(node, parent_id)| {
+            let parent = match parent_id {
+                Some(parent_id) => Some(batch.get(&parent_id).cloned().or_else(|| self.find_by_id(&parent_id)).ok_or(TreeError::NodeNotFound(parent_id))?),
+                None => None
+            };
+            Ok((node.clone(), parent))
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(pairs)
     }
 
-    #[test]
-    fn test_add_node_two_deep_mt(){
-        let tree = Tree::new(None);
-        let child_node = Node::new(py_none(), None);
-        let childs_child_node = Node::new(py_none(), None);
+    // Hooks an already validated child under parent, entries are the child's subtree for the index
+    #[allow(clippy::type_complexity)]
+    fn link(&self, child: &Arc<Mutex<Node<T>>>, parent: &Arc<Mutex<Node<T>>>, index: Option<usize>, entries: Vec<(String, AWeak<Mutex<Node<T>>>)>) {
+        {
+            let parent_guard = parent.lock().unwrap();
+            insert_at(&mut parent_guard.children.lock().unwrap(), Arc::clone(child), index);
+        }
+        let self_weak: AWeak<Mutex<Node<T>>> = Arc::downgrade(parent);
+        child.lock().unwrap().parent = Some(self_weak);
+        self.index.write().unwrap().extend(entries);
+    }
 
-        tree.lock().unwrap().add_child(child_node.clone(), None);
-        tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone()));
+    pub fn find_by_id(&self, id: &str) -> Option<Arc<Mutex<Node<T>>>> {
+        self.index.read().unwrap().get(id).and_then(|node| node.upgrade())
+    }
 
-        let childs_child_parent_id = childs_child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id.clone();
+    // Whether the node itself is on this tree, not just a node with the same id
+    fn contains(&self, node: &Arc<Mutex<Node<T>>>) -> bool {
+        let id = node.lock().unwrap().id.clone();
+        self.find_by_id(&id).is_some_and(|indexed| Arc::ptr_eq(&indexed, node))
+    }
 
-        let child_id = child_node.lock().unwrap().id.clone();
+    fn check_on_tree(&self, node: &Arc<Mutex<Node<T>>>) -> Result<()> {
+        match self.contains(node) {
+            true => Ok(()),
+            false => Err(TreeError::NotInTree(node.lock().unwrap().id.clone()))
+        }
+    }
 
-        assert_eq!(childs_child_parent_id, child_id);
+    // Replaces the node's data, unlike writing node.data directly this is recorded in the history
+    pub fn set_data(&self, node: &Arc<Mutex<Node<T>>>, data: Option<T>) {
+        let change = self.set_data_recorded(node, data);
+        self.record(vec![change]);
+    }
 
-        assert!(child_node.lock().unwrap().children.lock().unwrap().iter().any(|child| Arc::ptr_eq(&child, &childs_child_node)));
+    pub fn get_ancestors(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        self.check_on_tree(node)?;
+        let mut collection: Vec<Arc<Mutex<Node<T>>>> = Vec::new();
+        get_ancestors_recursive(node, &mut collection);
+        Ok(collection)
     }
 
-    #[test]
-    fn test_find_by_id_mt(){
-        let tree = Tree::new(None);
-        let child_node = Node::new(py_none(), None);
-        let childs_child_node = Node::new(py_none(), None);
+    // Everything below the node in pre-order, the node itself is not included
+    pub fn get_descendants(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        Ok(self.pre_order(Some(node), None)?.skip(1).collect())
+    }
 
-        {
-            tree.lock().unwrap().add_child(child_node.clone(), None);
-            tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone()));
-        }
+    // The childless nodes of the subtree in pre-order, a leaf is its own only leaf
+    pub fn get_leaves(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        Ok(self.pre_order(Some(node), None)?.filter(|descendant| descendant.lock().unwrap().children.lock().unwrap().is_empty()).collect())
+    }
 
-        let childs_child_id =  {childs_child_node.lock().unwrap().id.clone()};
-        let found_child = {tree.lock().unwrap().find_by_id(&childs_child_id).unwrap().clone()};
-        assert!(Arc::ptr_eq(&found_child,&childs_child_node));
+    // Number of nodes in the subtree, counting the node itself
+    pub fn subtree_size(&self, node: &Arc<Mutex<Node<T>>>) -> Result<usize> {
+        Ok(self.pre_order(Some(node), None)?.count())
     }
 
-    #[test]
-    fn test_get_ancestors_on_two_deep_tree_mt(){
-        let tree = Tree::new(None);
-        let child_node = Node::new(py_none(), None);
-        let childs_child_node = Node::new(py_none(), None);
+    pub fn is_leaf(&self, node: &Arc<Mutex<Node<T>>>) -> Result<bool> {
+        self.check_on_tree(node)?;
+        Ok(node.lock().unwrap().children.lock().unwrap().is_empty())
+    }
 
-        tree.lock().unwrap().add_child(child_node.clone(), None);
-        tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone()));
+    pub fn is_root(&self, node: &Arc<Mutex<Node<T>>>) -> Result<bool> {
+        self.check_on_tree(node)?;
+        Ok(Arc::ptr_eq(node, &self.root))
+    }
 
-        let ancestors = tree.lock().unwrap().get_ancestors(&childs_child_node);
+    // Number of edges between the node and the root, the root is at depth 0
+    pub fn depth(&self, node: &Arc<Mutex<Node<T>>>) -> Result<usize> {
+        Ok(self.get_ancestors(node)?.len())
+    }
 
-        assert!(ancestors.iter().any(|ancestor| Arc::ptr_eq(ancestor, &child_node)));
-        assert!(ancestors.iter().any(|ancestor| Arc::ptr_eq(ancestor, &tree.lock().unwrap().root)));
+    // Number of edges on the longest path from the node down to a leaf, a leaf has height 0
+    pub fn height(&self, node: &Arc<Mutex<Node<T>>>) -> Result<usize> {
+        Ok(self.level_order(Some(node), None)?.count() - 1)
     }
 
-    #[test]
-    fn test_move_node_from_two_deep_to_one_deep_mt(){
-        let tree = Tree::new(None);
-        let child_node = Node::new(py_none(), None);
-        let childs_child_node = Node::new(py_none(), None);
+    // Every node at depth level, left to right
+    pub fn nodes_at_level(&self, level: usize) -> Vec<Arc<Mutex<Node<T>>>> {
+        LevelOrderIter::new(NodeChildren::new(), self.root.clone(), Some(level)).nth(level).unwrap_or_default()
+    }
 
-        {
-            let tree_guard = tree.lock().unwrap();
-            tree_guard.add_child(child_node.clone(), None);
-            tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone()));
+    // The node followed by its ancestors, ending with the root
+    pub fn path_to_root(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        let mut path = vec![node.clone()];
+        path.extend(self.get_ancestors(node)?);
+        Ok(path)
+    }
 
-            let root_clone = tree_guard.root.clone();
-            tree_guard.move_node(&childs_child_node, &root_clone);
+    // The deepest node that has both a and b in its subtree, a node counts as its own ancestor
+    pub fn lowest_common_ancestor(&self, a: &Arc<Mutex<Node<T>>>, b: &Arc<Mutex<Node<T>>>) -> Result<Arc<Mutex<Node<T>>>> {
+        let a_path = self.path_to_root(a)?;
+        let shared = shared_tail_len(&a_path, &self.path_to_root(b)?);
+        Ok(a_path[a_path.len() - shared].clone())
+    }
+
+    // The nodes from a up to the lowest common ancestor and down to b, both ends included
+    pub fn path_between(&self, a: &Arc<Mutex<Node<T>>>, b: &Arc<Mutex<Node<T>>>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        Ok(join_paths(self.path_to_root(a)?, self.path_to_root(b)?))
+    }
+
+    // The other children of the node's parent in order, the root has none
+    pub fn siblings(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Vec<Arc<Mutex<Node<T>>>>> {
+        match self.parent_children(node)? {
+            Some((mut siblings, index)) => {
+                siblings.remove(index);
+                Ok(siblings)
+            },
+            None => Ok(Vec::new())
         }
+    }
 
-        let children = tree.lock().unwrap().root.lock().unwrap().children.lock().unwrap().clone();
+    // Position of the node among its parent's children, None for the root
+    pub fn index_in_parent(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Option<usize>> {
+        Ok(self.parent_children(node)?.map(|(_, index)| index))
+    }
+
+    pub fn next_sibling(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Option<Arc<Mutex<Node<T>>>>> {
+        Ok(self.parent_children(node)?.and_then(|(siblings, index)| siblings.get(index + 1).cloned()))
+    }
+
+    pub fn prev_sibling(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Option<Arc<Mutex<Node<T>>>>> {
+        Ok(self.parent_children(node)?.and_then(|(siblings, index)| index.checked_sub(1).map(|prev| siblings[prev].clone())))
+    }
+
+    pub fn first_child(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Option<Arc<Mutex<Node<T>>>>> {
+        self.check_on_tree(node)?;
+        Ok(node.lock().unwrap().children.lock().unwrap().first().cloned())
+    }
+
+    pub fn last_child(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Option<Arc<Mutex<Node<T>>>>> {
+        self.check_on_tree(node)?;
+        Ok(node.lock().unwrap().children.lock().unwrap().last().cloned())
+    }
+
+    // Puts parent's children in the order of ids, which has to name every child exactly once
+    pub fn reorder_children(&self, parent: &Arc<Mutex<Node<T>>>, ids: &[String]) -> Result<()> {
+        self.record_with(|| self.reorder_recorded(parent, ids), || self.reorder(parent, ids))
+    }
+
+    fn reorder(&self, parent: &Arc<Mutex<Node<T>>>, ids: &[String]) -> Result<()> {
+        let parent_guard = parent.lock().unwrap();
+        let mut children = parent_guard.children.lock().unwrap();
+        match reordered(&children, ids, |child| child.lock().unwrap().id.clone()) {
+            Some(reordered_children) => {
+                *children = reordered_children;
+                Ok(())
+            },
+            None => Err(TreeError::MalformedInput(format!("ids must name every child of '{}' exactly once", parent_guard.id)))
+        }
+    }
+
+    // The children of the node's parent and the node's position among them, None for the root
+    #[allow(clippy::type_complexity)]
+    fn parent_children(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Option<(Vec<Arc<Mutex<Node<T>>>>, usize)>> {
+        self.check_on_tree(node)?;
+        let Some(parent) = node.lock().unwrap().parent.as_ref().and_then(|parent_weak| parent_weak.upgrade()) else {
+            return Ok(None);
+        };
+        let siblings = parent.lock().unwrap().children.lock().unwrap().clone();
+        Ok(siblings.iter().position(|sibling| Arc::ptr_eq(sibling, node)).map(|index| (siblings, index)))
+    }
 
-        assert!(children.iter().any(|child| Arc::ptr_eq(&child, &child_node)));
-        assert!(children.iter().any(|child| Arc::ptr_eq(&child, &childs_child_node)));
+    pub fn move_node(&self, tgt_node: &Arc<Mutex<Node<T>>>, new_parent_node: &Arc<Mutex<Node<T>>>) -> Result<()> {
+        self.record_with(|| self.move_recorded(tgt_node, new_parent_node, None), || self.relocate(tgt_node, new_parent_node, None))
     }
-}
\ No newline at end of file
+
+    // Moves the node to index among new_parent's children, counted after it has left its old place
+    pub fn move_node_at(&self, tgt_node: &Arc<Mutex<Node<T>>>, new_parent_node: &Arc<Mutex<Node<T>>>, index: usize) -> Result<()> {
+        self.record_with(|| self.move_recorded(tgt_node, new_parent_node, Some(index)), || self.relocate(tgt_node, new_parent_node, Some(index)))
+    }
+
+    fn relocate(&self, tgt_node: &Arc<Mutex<Node<T>>>, new_parent_node: &Arc<Mutex<Node<T>>>, index: Option<usize>) -> Result<()> {
+        // Both have to be on this tree, a node of another tree is added through add_child once detached
+        if !self.contains(tgt_node) {
+            check_detached(tgt_node)?;
+            return Err(TreeError::NotInTree(tgt_node.lock().unwrap().id.clone()));
+        }
+        if Arc::ptr_eq(tgt_node, new_parent_node) || self.get_ancestors(new_parent_node)?.iter().any(|ancestor| Arc::ptr_eq(ancestor, tgt_node)) {
+            return Err(TreeError::CycleDetected(tgt_node.lock().unwrap().id.clone()));
+        }
+
+        // Remove the node from its current parent's children list, if it has one
+        if let Some(ref parent_weak) = tgt_node.lock().unwrap().parent {
+            if let Some(parent) = parent_weak.upgrade() {
+                {
+                    let parent_borrowed = parent.lock().unwrap();
+                    let mut parent_children = parent_borrowed.children.lock().unwrap();
+                    if let Some(index) = parent_children.iter().position(|child| Arc::ptr_eq(child, tgt_node)) {
+                        parent_children.remove(index);
+                    }
+                }
+            }
+        }
+
+        // Add the node to the new parent's children list
+        {
+            let new_parent_guard = new_parent_node.lock().unwrap();
+            insert_at(&mut new_parent_guard.children.lock().unwrap(), Arc::clone(tgt_node), index);
+        }
+
+        // Update the parent reference in the target node, the index stays as it is within the tree
+        tgt_node.lock().unwrap().parent = Some(Arc::downgrade(new_parent_node));
+        Ok(())
+    }
+
+    // Takes the node out of its parent's children and returns it. With keep_children its children
+    // take its place under the parent, otherwise the node comes back as the root of a detached subtree.
+    pub fn remove_node(&self, node: &Arc<Mutex<Node<T>>>, keep_children: bool) -> Result<Arc<Mutex<Node<T>>>> {
+        self.record_with(|| self.remove_recorded(node, keep_children), || self.remove_from_tree(node, keep_children))?;
+        Ok(node.clone())
+    }
+
+    fn remove_from_tree(&self, node: &Arc<Mutex<Node<T>>>, keep_children: bool) -> Result<()> {
+        if Arc::ptr_eq(node, &self.root) {
+            return Err(TreeError::RootNode(node.lock().unwrap().id.clone()));
+        }
+        self.check_on_tree(node)?;
+        let removed = if keep_children {
+            vec![(node.lock().unwrap().id.clone(), Arc::downgrade(node))]
+        } else {
+            subtree_entries(node)
+        };
+
+        let parent = node.lock().unwrap().parent.as_ref().and_then(|parent_weak| parent_weak.upgrade()).unwrap();
+        let parent_guard = parent.lock().unwrap();
+        let mut parent_children = parent_guard.children.lock().unwrap();
+        let index = parent_children.iter().position(|child| Arc::ptr_eq(child, node)).unwrap();
+        parent_children.remove(index);
+
+        let mut node_guard = node.lock().unwrap();
+        if keep_children {
+            let children = std::mem::take(&mut *node_guard.children.lock().unwrap());
+            for (offset, child) in children.into_iter().enumerate() {
+                child.lock().unwrap().parent = Some(Arc::downgrade(&parent));
+                parent_children.insert(index + offset, child);
+            }
+        }
+        node_guard.parent = None;
+
+        // Only entries that point at a removed node go, whatever else holds the id stays findable
+        let mut index = self.index.write().unwrap();
+        for (id, removed_node) in &removed {
+            if index.get(id).is_some_and(|indexed| indexed.ptr_eq(removed_node)) {
+                index.remove(id);
+            }
+        }
+        Ok(())
+    }
+
+    // Cuts the node and everything below it off the tree, the returned node can be added elsewhere
+    pub fn prune(&self, node: &Arc<Mutex<Node<T>>>) -> Result<Arc<Mutex<Node<T>>>> {
+        self.remove_node(node, false)
+    }
+
+    // The walks start at start (the root when None) and stop max_depth levels below it
+    pub fn pre_order(&self, start: Option<&Arc<Mutex<Node<T>>>>, max_depth: Option<usize>) -> Result<PreOrderIter<NodeChildren<T>>> {
+        Ok(PreOrderIter::new(NodeChildren::new(), self.walk_start(start)?, max_depth))
+    }
+
+    pub fn post_order(&self, start: Option<&Arc<Mutex<Node<T>>>>, max_depth: Option<usize>) -> Result<PostOrderIter<NodeChildren<T>>> {
+        Ok(PostOrderIter::new(NodeChildren::new(), self.walk_start(start)?, max_depth))
+    }
+
+    pub fn breadth_first(&self, start: Option<&Arc<Mutex<Node<T>>>>, max_depth: Option<usize>) -> Result<BreadthFirstIter<NodeChildren<T>>> {
+        Ok(BreadthFirstIter::new(NodeChildren::new(), self.walk_start(start)?, max_depth))
+    }
+
+    pub fn level_order(&self, start: Option<&Arc<Mutex<Node<T>>>>, max_depth: Option<usize>) -> Result<LevelOrderIter<NodeChildren<T>>> {
+        Ok(LevelOrderIter::new(NodeChildren::new(), self.walk_start(start)?, max_depth))
+    }
+
+    fn walk_start(&self, start: Option<&Arc<Mutex<Node<T>>>>) -> Result<Arc<Mutex<Node<T>>>> {
+        match start {
+            Some(node) => {
+                self.check_on_tree(node)?;
+                Ok(node.clone())
+            },
+            None => Ok(self.root.clone())
+        }
+    }
+}
+
+// A node with a live parent is already on a tree, it has to be removed before it can be added again.
+// Returns the node's id.
+fn check_detached<T>(node: &Arc<Mutex<Node<T>>>) -> Result<String> {
+    let node_guard = node.lock().unwrap();
+    if node_guard.parent.as_ref().is_some_and(|parent_weak| parent_weak.upgrade().is_some()) {
+        return Err(TreeError::AttachedElsewhere(node_guard.id.clone()));
+    }
+    Ok(node_guard.id.clone())
+}
+
+// Order in which a batch of (child, parent) pairs can be linked so every parent is on the tree or
+// linked before its children. Children of the same parent keep their order from the batch.
+#[allow(clippy::type_complexity)]
+fn parents_first<N>(pairs: &[(Arc<N>, Option<Arc<N>>)], on_tree: impl Fn(&Arc<N>) -> bool, id_of: impl Fn(&Arc<N>) -> String) -> Result<Vec<usize>> {
+    let batch: HashSet<*const N> = pairs.iter().map(|(child, _)| Arc::as_ptr(child)).collect();
+    let mut ready: VecDeque<usize> = VecDeque::with_capacity(pairs.len());
+    let mut waiting: HashMap<*const N, Vec<usize>> = HashMap::new();
+    for (position, (_, parent)) in pairs.iter().enumerate() {
+        match parent {
+            None => ready.push_back(position),
+            Some(parent) if batch.contains(&Arc::as_ptr(parent)) => waiting.entry(Arc::as_ptr(parent)).or_default().push(position),
+            Some(parent) if on_tree(parent) => ready.push_back(position),
+            Some(parent) => return Err(TreeError::NotInTree(id_of(parent)))
+        }
+    }
+
+    let mut order = Vec::with_capacity(pairs.len());
+    while let Some(position) = ready.pop_front() {
+        order.push(position);
+        if let Some(children) = waiting.remove(&Arc::as_ptr(&pairs[position].0)) {
+            ready.extend(children);
+        }
+    }
+    // Whatever is still waiting hangs off a loop of batch nodes that never reaches the tree
+    match waiting.into_values().flatten().min() {
+        Some(position) => Err(TreeError::CycleDetected(id_of(&pairs[position].0))),
+        None => Ok(order)
+    }
+}
+
+// (id, node) for the node and everything below it
+#[allow(clippy::type_complexity)]
+fn subtree_entries<T>(node: &Arc<Mutex<Node<T>>>) -> Vec<(String, AWeak<Mutex<Node<T>>>)> {
+    PreOrderIter::new(NodeChildren::new(), node.clone(), None)
+        .map(|descendant| (descendant.lock().unwrap().id.clone(), Arc::downgrade(&descendant)))
+        .collect()
+}
+
+// Inserts at index when given, past the end or without an index the item is appended
+fn insert_at<N>(children: &mut Vec<N>, item: N, index: Option<usize>) {
+    match index {
+        Some(index) if index < children.len() => children.insert(index, item),
+        _ => children.push(item)
+    }
+}
+
+// children rearranged into the order of ids, None unless ids names every child exactly once
+fn reordered<N>(children: &[N], ids: &[String], id_of: impl Fn(&N) -> String) -> Option<Vec<N>> where N: Clone {
+    if ids.len() != children.len() {
+        return None;
+    }
+    let mut remaining: HashMap<String, N> = children.iter().map(|child| (id_of(child), child.clone())).collect();
+    ids.iter().map(|id| remaining.remove(id)).collect()
+}
+
+// Number of nodes two root-ending paths have in common, counted from the root
+fn shared_tail_len<N: ?Sized>(a_path: &[Arc<N>], b_path: &[Arc<N>]) -> usize {
+    a_path.iter().rev().zip(b_path.iter().rev()).take_while(|(a, b)| Arc::ptr_eq(a, b)).count()
+}
+
+// Joins two root-ending paths at their lowest common node, a's side runs upwards and b's side downwards
+fn join_paths<N: ?Sized>(mut a_path: Vec<Arc<N>>, b_path: Vec<Arc<N>>) -> Vec<Arc<N>> {
+    let shared = shared_tail_len(&a_path, &b_path);
+    a_path.truncate(a_path.len() - shared + 1);
+    a_path.extend(b_path.into_iter().rev().skip(shared));
+    a_path
+}
+
+fn get_ancestors_recursive<T>(node: &Arc<Mutex<Node<T>>>, collection: &mut Vec<Arc<Mutex<Node<T>>>>) {
+    if let Some(parent_weak) = node.lock().unwrap().parent.as_ref() {
+        if let Some(parent) = parent_weak.upgrade() {
+            collection.push(parent.clone());
+            get_ancestors_recursive(&parent, collection);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub struct Node<T> {
+    pub id: String,
+    pub data: Option<T>,
+    pub children: Arc<Mutex<Vec<Arc<Mutex<Node<T>>>>>>,
+    // Option only to cater for 'root'
+    pub parent: Option<AWeak<Mutex<Node<T>>>>,
+    // Length of the edge to the parent, set from Newick and written back by to_newick
+    pub branch_length: Option<f64>,
+    // Only set on the root, the Tree it is the root of. Other nodes find their tree through the
+    // root at the top of their parents, so nothing has to be updated when a subtree moves.
+    pub tree: Option<AWeak<Mutex<Tree<T>>>>,
+}
+
+impl<T> Node<T> {
+    pub fn new(data: Option<T>, parent: Option<AWeak<Mutex<Node<T>>>>) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            id: Uuid::new_v4().to_string(),
+            data,
+            children: Arc::new(Mutex::new(Vec::new())),
+            parent,
+            branch_length: None,
+            tree: None,
+        }))
+    }
+
+    // The Tree the node is on, None for a node that is not on one. Locks the node and its
+    // ancestors one at a time, the caller must not hold any of them.
+    pub fn get_tree(node: &Arc<Mutex<Node<T>>>) -> Option<Arc<Mutex<Tree<T>>>> {
+        let mut top = node.clone();
+        loop {
+            let parent = top.lock().unwrap().parent.as_ref().and_then(|parent_weak| parent_weak.upgrade());
+            match parent {
+                Some(parent) => top = parent,
+                None => break
+            }
+        }
+        let tree = top.lock().unwrap().tree.as_ref().and_then(|tree| tree.upgrade());
+        tree
+    }
+}
+
+// Dropping a deep tree one nested Arc at a time would overflow the stack, the children this node is
+// the last owner of are taken apart in a loop instead
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        let Some(children) = Arc::get_mut(&mut self.children) else { return };
+        let mut stack = std::mem::take(children.get_mut().unwrap_or_else(|err| err.into_inner()));
+        while let Some(node) = stack.pop() {
+            if let Ok(node) = Arc::try_unwrap(node) {
+                let mut node = node.into_inner().unwrap_or_else(|err| err.into_inner());
+                if let Some(children) = Arc::get_mut(&mut node.children) {
+                    stack.append(children.get_mut().unwrap_or_else(|err| err.into_inner()));
+                }
+            }
+        }
+    }
+}
+
+pub struct NodeMap<T> {
+    pub id: String,
+    // The node's slot in the arena of its tree, parent and children are kept there
+    pub(crate) index: Option<NodeIndex>,
+    // None until the node is added to a TreeMap, the tree its relatives are looked up on
+    pub tree: Option<AWeak<RwLock<TreeMap<T>>>>,
+    // Only holds the payload while the node is detached, on a tree it lives in the tree's data store
+    pub data: Option<T>,
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new( Self {
+            id: Uuid::new_v4().to_string(),
+            index: None,
+            tree: None,
+            data: None,
+        }))
+    }
+
+    pub fn get_tree(&self) -> Option<Arc<RwLock<TreeMap<T>>>> {
+        self.tree.as_ref().and_then(|tree| tree.upgrade())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    #[test]
+    fn test_add_node_to_empty_tree_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+
+        tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+
+        let child_parent_id = child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id.clone();
+
+        let root_id = tree.lock().unwrap().root.lock().unwrap().id.clone();
+
+        assert_eq!(child_parent_id, root_id);
+
+        assert!(tree.lock().unwrap().root.lock().unwrap().children.lock().unwrap().iter().any(|child| Arc::ptr_eq(child, &child_node)));
+    }
+
+    #[test]
+    fn test_add_node_two_deep_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+        tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+
+        let childs_child_parent_id = childs_child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id.clone();
+
+        let child_id = child_node.lock().unwrap().id.clone();
+
+        assert_eq!(childs_child_parent_id, child_id);
+
+        assert!(child_node.lock().unwrap().children.lock().unwrap().iter().any(|child| Arc::ptr_eq(child, &childs_child_node)));
+    }
+
+    #[test]
+    fn test_find_by_id_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        {
+            tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+            tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+        }
+
+        let childs_child_id =  {childs_child_node.lock().unwrap().id.clone()};
+        let found_child = {tree.lock().unwrap().find_by_id(&childs_child_id).unwrap().clone()};
+        assert!(Arc::ptr_eq(&found_child,&childs_child_node));
+    }
+
+    #[test]
+    fn test_get_ancestors_on_two_deep_tree_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        tree.lock().unwrap().add_child(child_node.clone(), None).unwrap();
+        tree.lock().unwrap().add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+
+        let ancestors = tree.lock().unwrap().get_ancestors(&childs_child_node).unwrap();
+
+        assert!(ancestors.iter().any(|ancestor| Arc::ptr_eq(ancestor, &child_node)));
+        assert!(ancestors.iter().any(|ancestor| Arc::ptr_eq(ancestor, &tree.lock().unwrap().root)));
+    }
+
+    #[test]
+    fn test_move_node_from_two_deep_to_one_deep_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        {
+            let tree_guard = tree.lock().unwrap();
+            tree_guard.add_child(child_node.clone(), None).unwrap();
+            tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+
+            let root_clone = tree_guard.root.clone();
+            tree_guard.move_node(&childs_child_node, &root_clone).unwrap();
+        }
+
+        let children = tree.lock().unwrap().root.lock().unwrap().children.lock().unwrap().clone();
+
+        assert!(children.iter().any(|child| Arc::ptr_eq(child, &child_node)));
+        assert!(children.iter().any(|child| Arc::ptr_eq(child, &childs_child_node)));
+    }
+
+    #[test]
+    fn test_treemaps_are_independent_mt(){
+        let first_tree = TreeMap::<i32>::new(None);
+        let second_tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+
+        first_tree.read().unwrap().add_child(&child_node, None).unwrap();
+
+        let child_id = child_node.read().unwrap().id.clone();
+        assert!(first_tree.read().unwrap().find_by_id(&child_id).is_ok());
+        assert!(second_tree.read().unwrap().find_by_id(&child_id).is_err());
+
+        let owning_tree = child_node.read().unwrap().get_tree().unwrap();
+        assert!(Arc::ptr_eq(&owning_tree, &first_tree));
+    }
+
+    #[test]
+    fn test_nodemap_data_moves_into_tree_store_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+        let child_id = child_node.read().unwrap().id.clone();
+
+        set_nodemap_data(&child_node, Some(1));
+        assert!(child_node.read().unwrap().data.is_some());
+
+        tree.read().unwrap().add_child(&child_node, None).unwrap();
+
+        assert!(child_node.read().unwrap().data.is_none());
+        assert!(tree.read().unwrap().get_data(&child_id).is_some());
+        assert!(get_nodemap_data(&child_node).is_some());
+    }
+
+    #[test]
+    fn test_add_child_from_another_treemap_fails_mt(){
+        let first_tree = TreeMap::<i32>::new(None);
+        let second_tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+
+        first_tree.read().unwrap().add_child(&child_node, None).unwrap();
+
+        let child_id = child_node.read().unwrap().id.clone();
+        assert_eq!(second_tree.read().unwrap().add_child(&child_node, None), Err(TreeError::AttachedElsewhere(child_id)));
+    }
+
+    #[test]
+    fn test_remove_node_keeping_children_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+        let childs_child_node = NodeMap::<i32>::new();
+        let sibling_node = NodeMap::<i32>::new();
+
+        {
+            let tree_guard = tree.read().unwrap();
+            tree_guard.add_child(&child_node, None).unwrap();
+            tree_guard.add_child(&sibling_node, None).unwrap();
+            tree_guard.add_child(&childs_child_node, Some(&child_node)).unwrap();
+            tree_guard.remove_node(&child_node, true).unwrap();
+        }
+
+        let tree_guard = tree.read().unwrap();
+        let root = tree_guard.find_by_id("root").unwrap();
+        let childs_child_id = childs_child_node.read().unwrap().id.clone();
+        let sibling_id = sibling_node.read().unwrap().id.clone();
+
+        assert_eq!(nodemap_ids(tree_guard.children(&root).unwrap()), vec![childs_child_id, sibling_id]);
+        assert!(Arc::ptr_eq(&tree_guard.parent(&childs_child_node).unwrap().unwrap(), &root));
+        assert!(child_node.read().unwrap().get_tree().is_none());
+        assert!(tree_guard.find_by_id(&child_node.read().unwrap().id).is_err());
+    }
+
+    #[test]
+    fn test_remove_node_with_subtree_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+        let childs_child_node = NodeMap::<i32>::new();
+
+        {
+            let tree_guard = tree.read().unwrap();
+            tree_guard.add_child(&child_node, None).unwrap();
+            tree_guard.add_child(&childs_child_node, Some(&child_node)).unwrap();
+            tree_guard.set_data(&childs_child_node.read().unwrap().id, Some(1));
+            tree_guard.remove_node(&child_node, false).unwrap();
+        }
+
+        let tree_guard = tree.read().unwrap();
+        // Only the root is left
+        assert_eq!(tree_guard.len(), 1);
+        assert!(tree_guard.data.read().unwrap().is_empty());
+        assert!(childs_child_node.read().unwrap().data.is_some());
+        assert!(tree_guard.remove_node(&tree_guard.find_by_id("root").unwrap(), false).is_err());
+    }
+
+    #[test]
+    fn test_detach_subtree_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+        let childs_child_node = NodeMap::<i32>::new();
+
+        {
+            let tree_guard = tree.read().unwrap();
+            tree_guard.add_child(&child_node, None).unwrap();
+            tree_guard.add_child(&childs_child_node, Some(&child_node)).unwrap();
+            tree_guard.enable_history(10);
+        }
+
+        let subtree = tree.read().unwrap().detach_subtree(&child_node).unwrap();
+        assert_eq!(tree.read().unwrap().undo(), Err(TreeError::HistoryCleared));
+        let childs_child_id = childs_child_node.read().unwrap().id.clone();
+
+        {
+            let tree_guard = tree.read().unwrap();
+            assert!(tree_guard.children(&tree_guard.root()).unwrap().is_empty());
+        }
+        assert!(Arc::ptr_eq(&subtree.read().unwrap().find_by_id("root").unwrap(), &child_node));
+        assert!(Arc::ptr_eq(&subtree.read().unwrap().find_by_id(&childs_child_id).unwrap(), &childs_child_node));
+        assert!(Arc::ptr_eq(&childs_child_node.read().unwrap().get_tree().unwrap(), &subtree));
+    }
+
+    #[test]
+    fn test_node_get_tree_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+        assert!(Node::get_tree(&child_node).is_none());
+
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(child_node.clone(), None).unwrap();
+        tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+        drop(tree_guard);
+        assert!(Arc::ptr_eq(&Node::get_tree(&childs_child_node).unwrap(), &tree));
+
+        tree.lock().unwrap().remove_node(&child_node, false).unwrap();
+        assert!(Node::get_tree(&childs_child_node).is_none());
+    }
+
+    #[test]
+    fn test_remove_node_keeping_children_from_tree_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        let removed = {
+            let tree_guard = tree.lock().unwrap();
+            tree_guard.add_child(child_node.clone(), None).unwrap();
+            tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+            tree_guard.remove_node(&child_node, true).unwrap()
+        };
+
+        let root = tree.lock().unwrap().root.clone();
+        let children = root.lock().unwrap().children.lock().unwrap().clone();
+
+        assert!(Arc::ptr_eq(&removed, &child_node));
+        assert!(child_node.lock().unwrap().parent.is_none());
+        assert_eq!(children.len(), 1);
+        assert!(Arc::ptr_eq(&children[0], &childs_child_node));
+        assert!(Arc::ptr_eq(&tree.lock().unwrap().get_ancestors(&childs_child_node).unwrap()[0], &root));
+    }
+
+    #[test]
+    fn test_prune_subtree_from_tree_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let other_tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        {
+            let tree_guard = tree.lock().unwrap();
+            tree_guard.add_child(child_node.clone(), None).unwrap();
+            tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+            let pruned = tree_guard.prune(&child_node).unwrap();
+            assert!(tree_guard.root.lock().unwrap().children.lock().unwrap().is_empty());
+            assert!(tree_guard.prune(&child_node).is_err());
+            other_tree.lock().unwrap().add_child(pruned, None).unwrap();
+        }
+
+        let other_root = other_tree.lock().unwrap().root.clone();
+        let ancestors = other_tree.lock().unwrap().get_ancestors(&childs_child_node).unwrap();
+        assert!(Arc::ptr_eq(&ancestors[1], &other_root));
+    }
+
+    #[test]
+    fn test_treemap_errors_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let child_node = NodeMap::<i32>::new();
+        let childs_child_node = NodeMap::<i32>::new();
+        let stray_node = NodeMap::<i32>::new();
+
+        let tree_guard = tree.read().unwrap();
+        tree_guard.add_child(&child_node, None).unwrap();
+        tree_guard.add_child(&childs_child_node, Some(&child_node)).unwrap();
+
+        let child_id = child_node.read().unwrap().id.clone();
+        let stray_id = stray_node.read().unwrap().id.clone();
+        let root = tree_guard.find_by_id("root").unwrap();
+        let root_id = root.read().unwrap().id.clone();
+
+        assert_eq!(tree_guard.find_by_id("missing").err(), Some(TreeError::NodeNotFound("missing".to_string())));
+        assert_eq!(tree_guard.move_node(&child_node, &childs_child_node), Err(TreeError::CycleDetected(child_id.clone())));
+        assert_eq!(tree_guard.add_child(&child_node, None), Err(TreeError::DuplicateId(child_id)));
+        assert_eq!(tree_guard.add_child(&NodeMap::<i32>::new(), Some(&stray_node)), Err(TreeError::NotInTree(stray_id)));
+        assert_eq!(tree_guard.move_node(&root, &child_node), Err(TreeError::CycleDetected(root_id.clone())));
+        assert_eq!(tree_guard.remove_node(&root, true), Err(TreeError::RootNode(root_id)));
+    }
+
+    #[test]
+    fn test_tree_move_into_descendant_errors_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let child_node = Node::<i32>::new(None, None);
+        let childs_child_node = Node::<i32>::new(None, None);
+
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(child_node.clone(), None).unwrap();
+        tree_guard.add_child(childs_child_node.clone(), Some(child_node.clone())).unwrap();
+
+        let child_id = child_node.lock().unwrap().id.clone();
+        assert_eq!(tree_guard.move_node(&child_node, &childs_child_node), Err(TreeError::CycleDetected(child_id.clone())));
+        assert_eq!(tree_guard.add_child(child_node.clone(), None), Err(TreeError::AttachedElsewhere(child_id)));
+        assert!(Arc::ptr_eq(&childs_child_node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap(), &child_node));
+    }
+
+    #[test]
+    fn test_tree_move_across_trees_errors_mt(){
+        let first = Tree::<i32>::new(None).unwrap();
+        let second = Tree::<i32>::new(None).unwrap();
+        let first_guard = first.lock().unwrap();
+        let second_guard = second.lock().unwrap();
+        let node = Node::<i32>::new(None, None);
+        let other_parent = Node::<i32>::new(None, None);
+        first_guard.add_child(node.clone(), None).unwrap();
+        second_guard.add_child(other_parent.clone(), None).unwrap();
+        let node_id = node.lock().unwrap().id.clone();
+        let other_parent_id = other_parent.lock().unwrap().id.clone();
+
+        // Neither tree changes when the node or the new parent is on the other one
+        assert_eq!(first_guard.move_node(&node, &other_parent), Err(TreeError::NotInTree(other_parent_id)));
+        assert_eq!(second_guard.move_node(&node, &other_parent), Err(TreeError::AttachedElsewhere(node_id.clone())));
+        assert!(Arc::ptr_eq(&first_guard.find_by_id(&node_id).unwrap(), &node));
+        assert_eq!(first_guard.root.lock().unwrap().children.lock().unwrap().len(), 1);
+        assert!(other_parent.lock().unwrap().children.lock().unwrap().is_empty());
+
+        let detached = Node::<i32>::new(None, None);
+        let detached_parent = Node::<i32>::new(None, None);
+        let detached_id = detached.lock().unwrap().id.clone();
+        assert_eq!(first_guard.move_node(&detached, &node), Err(TreeError::NotInTree(detached_id)));
+        assert_eq!(first_guard.move_node(&node, &detached_parent), Err(TreeError::NotInTree(detached_parent.lock().unwrap().id.clone())));
+        assert!(Arc::ptr_eq(&node.lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap(), &first_guard.root));
+    }
+
+    #[test]
+    fn test_treemap_with_string_payload_mt(){
+        let tree = TreeMap::new(None);
+        let child_node = NodeMap::new();
+
+        set_nodemap_data(&child_node, Some("detached".to_string()));
+        tree.read().unwrap().add_child(&child_node, None).unwrap();
+        assert_eq!(get_nodemap_data(&child_node), Some("detached".to_string()));
+
+        set_nodemap_data(&child_node, Some("attached".to_string()));
+        let child_id = child_node.read().unwrap().id.clone();
+        assert_eq!(tree.read().unwrap().get_data(&child_id), Some("attached".to_string()));
+    }
+
+    fn node_ids(nodes: Vec<Arc<Mutex<Node<i32>>>>) -> Vec<String> {
+        nodes.iter().map(|node| node.lock().unwrap().id.clone()).collect()
+    }
+
+    fn nodemap_ids(nodes: Vec<Arc<RwLock<NodeMap<i32>>>>) -> Vec<String> {
+        nodes.iter().map(|node| node.read().unwrap().id.clone()).collect()
+    }
+
+    #[test]
+    fn test_tree_traversal_orders_mt(){
+        // root -> (a -> (c, d), b)
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..4).map(|_| Node::new(None, None)).collect();
+        {
+            let tree_guard = tree.lock().unwrap();
+            tree_guard.add_child(nodes[0].clone(), None).unwrap();
+            tree_guard.add_child(nodes[1].clone(), None).unwrap();
+            tree_guard.add_child(nodes[2].clone(), Some(nodes[0].clone())).unwrap();
+            tree_guard.add_child(nodes[3].clone(), Some(nodes[0].clone())).unwrap();
+        }
+        let root = tree.lock().unwrap().root.clone();
+        let [a, b, c, d] = [0, 1, 2, 3].map(|index| nodes[index].lock().unwrap().id.clone());
+        let r = root.lock().unwrap().id.clone();
+
+        let tree_guard = tree.lock().unwrap();
+        assert_eq!(node_ids(tree_guard.pre_order(None, None).unwrap().collect()), vec![r.clone(), a.clone(), c.clone(), d.clone(), b.clone()]);
+        assert_eq!(node_ids(tree_guard.post_order(None, None).unwrap().collect()), vec![c.clone(), d.clone(), a.clone(), b.clone(), r.clone()]);
+        assert_eq!(node_ids(tree_guard.breadth_first(None, None).unwrap().collect()), vec![r.clone(), a.clone(), b.clone(), c.clone(), d.clone()]);
+        assert_eq!(tree_guard.level_order(None, None).unwrap().map(node_ids).collect::<Vec<_>>(), vec![vec![r.clone()], vec![a.clone(), b.clone()], vec![c.clone(), d.clone()]]);
+        assert_eq!(node_ids(tree_guard.pre_order(None, Some(1)).unwrap().collect()), vec![r.clone(), a.clone(), b.clone()]);
+        assert_eq!(node_ids(tree_guard.post_order(Some(&nodes[0]), None).unwrap().collect()), vec![c, d, a]);
+    }
+
+    #[test]
+    fn test_treemap_traversal_orders_mt(){
+        // root -> (a -> (c), b)
+        let tree = TreeMap::<i32>::new(None);
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..3).map(|_| NodeMap::new()).collect();
+        {
+            let tree_guard = tree.read().unwrap();
+            tree_guard.add_child(&nodes[0], None).unwrap();
+            tree_guard.add_child(&nodes[1], None).unwrap();
+            tree_guard.add_child(&nodes[2], Some(&nodes[0])).unwrap();
+        }
+        let [a, b, c] = [0, 1, 2].map(|index| nodes[index].read().unwrap().id.clone());
+
+        let tree_guard = tree.read().unwrap();
+        let r = tree_guard.find_by_id("root").unwrap().read().unwrap().id.clone();
+        assert_eq!(nodemap_ids(tree_guard.pre_order(None, None).unwrap().collect()), vec![r.clone(), a.clone(), c.clone(), b.clone()]);
+        assert_eq!(nodemap_ids(tree_guard.post_order(None, None).unwrap().collect()), vec![c.clone(), a.clone(), b.clone(), r.clone()]);
+        assert_eq!(nodemap_ids(tree_guard.breadth_first(None, Some(1)).unwrap().collect()), vec![r.clone(), a.clone(), b.clone()]);
+        assert_eq!(tree_guard.level_order(Some(&nodes[0]), None).unwrap().map(nodemap_ids).collect::<Vec<_>>(), vec![vec![a], vec![c]]);
+        assert!(tree_guard.pre_order(Some(&NodeMap::new()), None).is_err());
+    }
+
+    #[test]
+    fn test_tree_descendant_queries_mt(){
+        // root -> (a -> (c, d), b)
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..4).map(|_| Node::new(None, None)).collect();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(nodes[0].clone(), None).unwrap();
+        tree_guard.add_child(nodes[1].clone(), None).unwrap();
+        tree_guard.add_child(nodes[2].clone(), Some(nodes[0].clone())).unwrap();
+        tree_guard.add_child(nodes[3].clone(), Some(nodes[0].clone())).unwrap();
+        let [a, b, c, d] = [0, 1, 2, 3].map(|index| nodes[index].lock().unwrap().id.clone());
+        let root = tree_guard.root.clone();
+
+        assert_eq!(node_ids(tree_guard.get_descendants(&root).unwrap()), vec![a.clone(), c.clone(), d.clone(), b.clone()]);
+        assert_eq!(node_ids(tree_guard.get_leaves(&root).unwrap()), vec![c.clone(), d.clone(), b.clone()]);
+        assert_eq!(node_ids(tree_guard.get_leaves(&nodes[1]).unwrap()), vec![b]);
+        assert!(tree_guard.get_descendants(&nodes[2]).unwrap().is_empty());
+        assert_eq!(tree_guard.subtree_size(&root).unwrap(), 5);
+        assert_eq!(tree_guard.subtree_size(&nodes[0]).unwrap(), 3);
+        assert!(tree_guard.is_leaf(&nodes[3]).unwrap() && !tree_guard.is_leaf(&nodes[0]).unwrap());
+        assert!(tree_guard.is_root(&root).unwrap() && !tree_guard.is_root(&nodes[0]).unwrap());
+    }
+
+    #[test]
+    fn test_treemap_descendant_queries_mt(){
+        // root -> (a -> (c), b)
+        let tree = TreeMap::<i32>::new(None);
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..3).map(|_| NodeMap::new()).collect();
+        let tree_guard = tree.read().unwrap();
+        tree_guard.add_child(&nodes[0], None).unwrap();
+        tree_guard.add_child(&nodes[1], None).unwrap();
+        tree_guard.add_child(&nodes[2], Some(&nodes[0])).unwrap();
+        let [a, b, c] = [0, 1, 2].map(|index| nodes[index].read().unwrap().id.clone());
+        let root = tree_guard.find_by_id("root").unwrap();
+
+        assert_eq!(nodemap_ids(tree_guard.get_descendants(&root).unwrap()), vec![a.clone(), c.clone(), b.clone()]);
+        assert_eq!(nodemap_ids(tree_guard.get_leaves(&root).unwrap()), vec![c, b]);
+        assert_eq!(tree_guard.subtree_size(&root).unwrap(), 4);
+        assert_eq!(tree_guard.subtree_size(&nodes[0]).unwrap(), 2);
+        assert!(tree_guard.is_leaf(&nodes[1]).unwrap() && !tree_guard.is_leaf(&nodes[0]).unwrap());
+        assert!(tree_guard.is_root(&root).unwrap() && !tree_guard.is_root(&nodes[2]).unwrap());
+
+        let stray = NodeMap::<i32>::new();
+        let stray_id = stray.read().unwrap().id.clone();
+        assert_eq!(tree_guard.subtree_size(&stray), Err(TreeError::NotInTree(stray_id)));
+        assert!(tree_guard.is_leaf(&stray).is_err());
+    }
+
+    #[test]
+    fn test_tree_depth_height_and_levels_mt(){
+        // root -> (a -> (c -> (d)), b)
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..4).map(|_| Node::new(None, None)).collect();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(nodes[0].clone(), None).unwrap();
+        tree_guard.add_child(nodes[1].clone(), None).unwrap();
+        tree_guard.add_child(nodes[2].clone(), Some(nodes[0].clone())).unwrap();
+        tree_guard.add_child(nodes[3].clone(), Some(nodes[2].clone())).unwrap();
+        let [a, b] = [0, 1].map(|index| nodes[index].lock().unwrap().id.clone());
+        let root = tree_guard.root.clone();
+
+        assert_eq!(tree_guard.depth(&root).unwrap(), 0);
+        assert_eq!(tree_guard.depth(&nodes[3]).unwrap(), 3);
+        assert_eq!(tree_guard.height(&root).unwrap(), 3);
+        assert_eq!(tree_guard.height(&nodes[1]).unwrap(), 0);
+        assert_eq!(node_ids(tree_guard.nodes_at_level(1)), vec![a.clone(), b]);
+        assert!(tree_guard.nodes_at_level(4).is_empty());
+
+        tree_guard.move_node(&nodes[3], &root).unwrap();
+        assert_eq!(tree_guard.depth(&nodes[3]).unwrap(), 1);
+        assert_eq!(tree_guard.height(&root).unwrap(), 2);
+
+        // A pruned node still has its own subtree but is no longer on the tree
+        tree_guard.prune(&nodes[0]).unwrap();
+        assert_eq!(tree_guard.depth(&nodes[0]), Err(TreeError::NotInTree(a.clone())));
+        assert_eq!(tree_guard.is_leaf(&nodes[0]), Err(TreeError::NotInTree(a)));
+        assert!(matches!(tree_guard.siblings(&nodes[0]), Err(TreeError::NotInTree(_))));
+        assert!(matches!(tree_guard.pre_order(Some(&nodes[0]), None), Err(TreeError::NotInTree(_))));
+        assert!(matches!(tree_guard.subtree(Some(&nodes[2]), None), Err(TreeError::NotInTree(_))));
+    }
+
+    #[test]
+    fn test_treemap_depth_height_and_levels_mt(){
+        // root -> (a -> (c), b)
+        let tree = TreeMap::<i32>::new(None);
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..3).map(|_| NodeMap::new()).collect();
+        let tree_guard = tree.read().unwrap();
+        tree_guard.add_child(&nodes[0], None).unwrap();
+        tree_guard.add_child(&nodes[1], None).unwrap();
+        tree_guard.add_child(&nodes[2], Some(&nodes[0])).unwrap();
+        let c = nodes[2].read().unwrap().id.clone();
+        let root = tree_guard.find_by_id("root").unwrap();
+
+        assert_eq!(tree_guard.depth(&root).unwrap(), 0);
+        assert_eq!(tree_guard.depth(&nodes[2]).unwrap(), 2);
+        assert_eq!(tree_guard.height(&root).unwrap(), 2);
+        assert_eq!(tree_guard.height(&nodes[0]).unwrap(), 1);
+        assert_eq!(nodemap_ids(tree_guard.nodes_at_level(2)), vec![c]);
+        assert!(tree_guard.nodes_at_level(3).is_empty());
+        assert!(tree_guard.depth(&NodeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_tree_lowest_common_ancestor_and_paths_mt(){
+        // root -> (a -> (c, d -> (e)), b)
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..5).map(|_| Node::new(None, None)).collect();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(nodes[0].clone(), None).unwrap();
+        tree_guard.add_child(nodes[1].clone(), None).unwrap();
+        tree_guard.add_child(nodes[2].clone(), Some(nodes[0].clone())).unwrap();
+        tree_guard.add_child(nodes[3].clone(), Some(nodes[0].clone())).unwrap();
+        tree_guard.add_child(nodes[4].clone(), Some(nodes[3].clone())).unwrap();
+        let [a, b, c, d, e] = [0, 1, 2, 3, 4].map(|index| nodes[index].lock().unwrap().id.clone());
+        let r = tree_guard.root.lock().unwrap().id.clone();
+
+        assert_eq!(node_ids(tree_guard.path_to_root(&nodes[4]).unwrap()), vec![e.clone(), d.clone(), a.clone(), r.clone()]);
+        assert!(Arc::ptr_eq(&tree_guard.lowest_common_ancestor(&nodes[2], &nodes[4]).unwrap(), &nodes[0]));
+        assert!(Arc::ptr_eq(&tree_guard.lowest_common_ancestor(&nodes[0], &nodes[4]).unwrap(), &nodes[0]));
+        assert!(Arc::ptr_eq(&tree_guard.lowest_common_ancestor(&nodes[4], &nodes[1]).unwrap(), &tree_guard.root));
+        assert_eq!(node_ids(tree_guard.path_between(&nodes[2], &nodes[4]).unwrap()), vec![c.clone(), a.clone(), d.clone(), e.clone()]);
+        assert_eq!(node_ids(tree_guard.path_between(&nodes[4], &nodes[1]).unwrap()), vec![e.clone(), d.clone(), a.clone(), r, b]);
+        assert_eq!(node_ids(tree_guard.path_between(&nodes[3], &nodes[3]).unwrap()), vec![d.clone()]);
+        assert_eq!(node_ids(tree_guard.path_between(&nodes[0], &nodes[4]).unwrap()), vec![a, d, e]);
+
+        let stray = Node::<i32>::new(None, None);
+        let stray_id = stray.lock().unwrap().id.clone();
+        assert_eq!(tree_guard.lowest_common_ancestor(&nodes[0], &stray).err(), Some(TreeError::NotInTree(stray_id)));
+    }
+
+    #[test]
+    fn test_treemap_lowest_common_ancestor_and_paths_mt(){
+        // root -> (a -> (c), b)
+        let tree = TreeMap::<i32>::new(None);
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..3).map(|_| NodeMap::new()).collect();
+        let tree_guard = tree.read().unwrap();
+        tree_guard.add_child(&nodes[0], None).unwrap();
+        tree_guard.add_child(&nodes[1], None).unwrap();
+        tree_guard.add_child(&nodes[2], Some(&nodes[0])).unwrap();
+        let [a, b, c] = [0, 1, 2].map(|index| nodes[index].read().unwrap().id.clone());
+        let root = tree_guard.find_by_id("root").unwrap();
+        let r = root.read().unwrap().id.clone();
+
+        assert_eq!(nodemap_ids(tree_guard.path_to_root(&nodes[2]).unwrap()), vec![c.clone(), a.clone(), r.clone()]);
+        assert!(Arc::ptr_eq(&tree_guard.lowest_common_ancestor(&nodes[2], &nodes[1]).unwrap(), &root));
+        assert!(Arc::ptr_eq(&tree_guard.lowest_common_ancestor(&nodes[2], &nodes[0]).unwrap(), &nodes[0]));
+        assert_eq!(nodemap_ids(tree_guard.path_between(&nodes[2], &nodes[1]).unwrap()), vec![c, a, r, b]);
+        assert!(tree_guard.path_between(&nodes[2], &NodeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_tree_positional_children_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..4).map(|_| Node::new(None, None)).collect();
+        let [a, b, c, d] = [0, 1, 2, 3].map(|index| nodes[index].lock().unwrap().id.clone());
+        let tree_guard = tree.lock().unwrap();
+        let root = tree_guard.root.clone();
+        tree_guard.add_child(nodes[0].clone(), None).unwrap();
+        tree_guard.add_child(nodes[1].clone(), None).unwrap();
+        tree_guard.insert_child(&root, &nodes[2], 0).unwrap();
+        tree_guard.insert_child(&root, &nodes[3], 10).unwrap();
+        // root -> (c, a, b, d)
+
+        assert_eq!(node_ids(tree_guard.get_descendants(&root).unwrap()), vec![c.clone(), a.clone(), b.clone(), d.clone()]);
+        assert_eq!(node_ids(tree_guard.siblings(&nodes[0]).unwrap()), vec![c.clone(), b.clone(), d.clone()]);
+        assert_eq!(tree_guard.index_in_parent(&nodes[1]).unwrap(), Some(2));
+        assert_eq!(tree_guard.index_in_parent(&root).unwrap(), None);
+        assert!(Arc::ptr_eq(&tree_guard.next_sibling(&nodes[0]).unwrap().unwrap(), &nodes[1]));
+        assert!(Arc::ptr_eq(&tree_guard.prev_sibling(&nodes[0]).unwrap().unwrap(), &nodes[2]));
+        assert!(tree_guard.prev_sibling(&nodes[2]).unwrap().is_none() && tree_guard.next_sibling(&nodes[3]).unwrap().is_none());
+        assert!(Arc::ptr_eq(&tree_guard.first_child(&root).unwrap().unwrap(), &nodes[2]));
+        assert!(Arc::ptr_eq(&tree_guard.last_child(&root).unwrap().unwrap(), &nodes[3]));
+
+        tree_guard.move_node_at(&nodes[3], &root, 1).unwrap();
+        assert_eq!(node_ids(tree_guard.get_descendants(&root).unwrap()), vec![c.clone(), d.clone(), a.clone(), b.clone()]);
+
+        tree_guard.reorder_children(&root, &[a.clone(), b.clone(), c.clone(), d.clone()]).unwrap();
+        assert_eq!(node_ids(tree_guard.get_descendants(&root).unwrap()), vec![a.clone(), b.clone(), c.clone(), d.clone()]);
+        assert!(matches!(tree_guard.reorder_children(&root, &[a.clone(), b.clone(), c]), Err(TreeError::MalformedInput(_))));
+        assert!(matches!(tree_guard.reorder_children(&root, &[a.clone(), a, b, d]), Err(TreeError::MalformedInput(_))));
+    }
+
+    #[test]
+    fn test_treemap_positional_children_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..4).map(|_| NodeMap::new()).collect();
+        let [a, b, c, d] = [0, 1, 2, 3].map(|index| nodes[index].read().unwrap().id.clone());
+        let tree_guard = tree.read().unwrap();
+        let root = tree_guard.find_by_id("root").unwrap();
+        tree_guard.add_child(&nodes[0], None).unwrap();
+        tree_guard.add_child(&nodes[1], None).unwrap();
+        tree_guard.insert_child(&root, &nodes[2], 1).unwrap();
+        tree_guard.insert_child(&nodes[0], &nodes[3], 0).unwrap();
+        // root -> (a -> (d), c, b)
+
+        assert_eq!(nodemap_ids(tree_guard.get_descendants(&root).unwrap()), vec![a.clone(), d.clone(), c.clone(), b.clone()]);
+        assert_eq!(nodemap_ids(tree_guard.siblings(&nodes[2]).unwrap()), vec![a.clone(), b.clone()]);
+        assert!(tree_guard.siblings(&root).unwrap().is_empty());
+        assert_eq!(tree_guard.index_in_parent(&nodes[1]).unwrap(), Some(2));
+        assert!(Arc::ptr_eq(&tree_guard.next_sibling(&nodes[0]).unwrap().unwrap(), &nodes[2]));
+        assert!(Arc::ptr_eq(&tree_guard.prev_sibling(&nodes[1]).unwrap().unwrap(), &nodes[2]));
+        assert!(tree_guard.next_sibling(&nodes[3]).unwrap().is_none());
+        assert!(Arc::ptr_eq(&tree_guard.first_child(&root).unwrap().unwrap(), &nodes[0]));
+        assert!(Arc::ptr_eq(&tree_guard.last_child(&root).unwrap().unwrap(), &nodes[1]));
+        assert!(tree_guard.first_child(&nodes[1]).unwrap().is_none());
+
+        tree_guard.move_node_at(&nodes[3], &root, 0).unwrap();
+        assert_eq!(nodemap_ids(tree_guard.get_descendants(&root).unwrap()), vec![d.clone(), a.clone(), c.clone(), b.clone()]);
+
+        tree_guard.reorder_children(&root, &[b.clone(), c.clone(), a.clone(), d.clone()]).unwrap();
+        assert_eq!(nodemap_ids(tree_guard.get_descendants(&root).unwrap()), vec![b.clone(), c.clone(), a.clone(), d]);
+        assert!(matches!(tree_guard.reorder_children(&root, &[b, c, a]), Err(TreeError::MalformedInput(_))));
+        assert!(tree_guard.index_in_parent(&NodeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_treemap_reuses_freed_slots_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.read().unwrap();
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..3).map(|_| NodeMap::new()).collect();
+        tree_guard.add_child(&nodes[0], None).unwrap();
+        tree_guard.add_child(&nodes[1], Some(&nodes[0])).unwrap();
+        assert!(Arc::ptr_eq(&tree_guard.parent(&nodes[1]).unwrap().unwrap(), &nodes[0]));
+        assert_eq!(tree_guard.children(&nodes[0]).unwrap().len(), 1);
+
+        let slot = nodes[1].read().unwrap().index.unwrap();
+        tree_guard.remove_node(&nodes[1], false).unwrap();
+        assert!(nodes[1].read().unwrap().index.is_none());
+        assert!(tree_guard.children(&nodes[0]).unwrap().is_empty());
+
+        // The next node takes the freed slot under a new generation
+        tree_guard.add_child(&nodes[2], None).unwrap();
+        let reused = nodes[2].read().unwrap().index.unwrap();
+        assert_eq!((slot.to_string(), reused.to_string()), ("2v0".to_string(), "2v1".to_string()));
+        assert!(tree_guard.arena.read().unwrap().get(slot).is_none());
+
+        // A removed handle does not resolve to the node now in its slot, even when it claims the slot
+        nodes[1].write().unwrap().index = Some(reused);
+        assert!(matches!(tree_guard.parent(&nodes[1]), Err(TreeError::NotInTree(_))));
+        tree_guard.add_child(&nodes[1], None).unwrap();
+        assert_eq!(tree_guard.len(), 4);
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&nodes[2].read().unwrap().id).unwrap(), &nodes[2]));
+    }
+
+    #[test]
+    fn test_tree_id_index_follows_mutations_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..3).map(|_| Node::new(None, None)).collect();
+        let [a, b, c] = [0, 1, 2].map(|index| nodes[index].lock().unwrap().id.clone());
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(nodes[0].clone(), None).unwrap();
+        tree_guard.add_child(nodes[1].clone(), Some(nodes[0].clone())).unwrap();
+        tree_guard.add_child(nodes[2].clone(), Some(nodes[1].clone())).unwrap();
+        let root_id = tree_guard.root.lock().unwrap().id.clone();
+
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&root_id).unwrap(), &tree_guard.root));
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&c).unwrap(), &nodes[2]));
+
+        tree_guard.move_node(&nodes[2], &tree_guard.root).unwrap();
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&c).unwrap(), &nodes[2]));
+
+        tree_guard.remove_node(&nodes[0], true).unwrap();
+        assert!(tree_guard.find_by_id(&a).is_none());
+        assert!(tree_guard.find_by_id(&b).is_some());
+
+        tree_guard.prune(&nodes[1]).unwrap();
+        assert!(tree_guard.find_by_id(&b).is_none());
+
+        // A pruned node can come back, along with anything below it
+        let grandchild = Node::<i32>::new(None, None);
+        let grandchild_id = grandchild.lock().unwrap().id.clone();
+        nodes[1].lock().unwrap().children.lock().unwrap().push(grandchild.clone());
+        grandchild.lock().unwrap().parent = Some(Arc::downgrade(&nodes[1]));
+        tree_guard.add_child(nodes[1].clone(), None).unwrap();
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&grandchild_id).unwrap(), &grandchild));
+    }
+
+    #[test]
+    fn test_tree_id_index_rejects_duplicates_and_frees_dropped_nodes_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let node = Node::<i32>::new(None, None);
+        let node_id = node.lock().unwrap().id.clone();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.add_child(node.clone(), None).unwrap();
+
+        let twin = Node::<i32>::new(None, None);
+        twin.lock().unwrap().id = node_id.clone();
+        assert_eq!(tree_guard.add_child(twin, None), Err(TreeError::DuplicateId(node_id.clone())));
+
+        // The index only holds Weak references, the pruned node is freed once its last owner lets go
+        let weak_node = Arc::downgrade(&node);
+        tree_guard.prune(&node).unwrap();
+        drop(node);
+        assert!(weak_node.upgrade().is_none());
+        assert!(tree_guard.find_by_id(&node_id).is_none());
+    }
+
+    #[test]
+    fn test_tree_id_index_built_from_loaded_root_mt(){
+        let root = Node::<i32>::new(None, None);
+        let child = Node::<i32>::new(None, Some(Arc::downgrade(&root)));
+        root.lock().unwrap().children.lock().unwrap().push(child.clone());
+        let child_id = child.lock().unwrap().id.clone();
+
+        let tree = Tree::new(Some(root.clone())).unwrap();
+        assert!(Arc::ptr_eq(&tree.lock().unwrap().find_by_id(&child_id).unwrap(), &child));
+
+        let twin = Node::<i32>::new(None, Some(Arc::downgrade(&child)));
+        twin.lock().unwrap().id = child_id.clone();
+        child.lock().unwrap().children.lock().unwrap().push(twin);
+        drop(tree);
+        assert_eq!(Tree::new(Some(root)).err(), Some(TreeError::DuplicateId(child_id)));
+    }
+
+    #[test]
+    fn test_tree_id_index_rejects_stray_parents_and_keeps_other_entries_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let stray = Node::<i32>::new(None, None);
+        let stray_id = stray.lock().unwrap().id.clone();
+        let child = Node::<i32>::new(None, None);
+        assert_eq!(tree_guard.add_child(child.clone(), Some(stray.clone())), Err(TreeError::NotInTree(stray_id.clone())));
+        assert_eq!(tree_guard.insert_child(&stray, &child, 0), Err(TreeError::NotInTree(stray_id)));
+        assert!(child.lock().unwrap().parent.is_none());
+
+        // A node renamed on the tree shares an id with another one, pruning it leaves the other findable
+        let [first, second, renamed] = [0, 1, 2].map(|_| Node::<i32>::new(None, None));
+        tree_guard.add_child(first.clone(), None).unwrap();
+        tree_guard.add_child(second.clone(), None).unwrap();
+        tree_guard.add_child(renamed.clone(), Some(first.clone())).unwrap();
+        let second_id = second.lock().unwrap().id.clone();
+        renamed.lock().unwrap().id = second_id.clone();
+        tree_guard.prune(&first).unwrap();
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&second_id).unwrap(), &second));
+    }
+
+    #[test]
+    fn test_tree_add_many_in_any_order_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..4).map(|_| Node::new(None, None)).collect();
+        let [a, b, c, d] = [0, 1, 2, 3].map(|index| nodes[index].lock().unwrap().id.clone());
+        let tree_guard = tree.lock().unwrap();
+        // c and d arrive before their parent a
+        tree_guard.add_many(&[
+            (nodes[2].clone(), Some(nodes[0].clone())),
+            (nodes[0].clone(), None),
+            (nodes[3].clone(), Some(nodes[0].clone())),
+            (nodes[1].clone(), None),
+        ]).unwrap();
+
+        let root = tree_guard.root.clone();
+        assert_eq!(node_ids(tree_guard.get_descendants(&root).unwrap()), vec![a, c, d.clone(), b]);
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id(&d).unwrap(), &nodes[3]));
+    }
+
+    #[test]
+    fn test_tree_add_many_validates_before_adding_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let root = tree_guard.root.clone();
+        let nodes: Vec<Arc<Mutex<Node<i32>>>> = (0..3).map(|_| Node::new(None, None)).collect();
+        let stray = Node::<i32>::new(None, None);
+        let stray_id = stray.lock().unwrap().id.clone();
+
+        assert_eq!(tree_guard.add_many(&[(nodes[0].clone(), None), (nodes[1].clone(), Some(stray))]), Err(TreeError::NotInTree(stray_id)));
+        assert!(matches!(tree_guard.add_many(&[(nodes[0].clone(), None), (nodes[1].clone(), Some(nodes[2].clone())), (nodes[2].clone(), Some(nodes[1].clone()))]), Err(TreeError::CycleDetected(_))));
+        assert!(matches!(tree_guard.add_many(&[(nodes[0].clone(), None), (nodes[0].clone(), None)]), Err(TreeError::DuplicateId(_))));
+        assert_eq!(tree_guard.subtree_size(&root).unwrap(), 1);
+        assert!(nodes[0].lock().unwrap().parent.is_none());
+
+        // A parent counts as on the tree only when it is the indexed node, not one with the same id
+        // or one that has been pruned since
+        let root_id = root.lock().unwrap().id.clone();
+        let twin = Node::<i32>::new(None, None);
+        twin.lock().unwrap().id = root_id.clone();
+        assert_eq!(tree_guard.add_many(&[(nodes[0].clone(), Some(twin))]), Err(TreeError::NotInTree(root_id)));
+        tree_guard.add_child(nodes[2].clone(), None).unwrap();
+        tree_guard.prune(&nodes[2]).unwrap();
+        let pruned_id = nodes[2].lock().unwrap().id.clone();
+        assert_eq!(tree_guard.add_many(&[(nodes[0].clone(), Some(nodes[2].clone()))]), Err(TreeError::NotInTree(pruned_id)));
+        assert_eq!(tree_guard.subtree_size(&root).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_tree_extend_from_records_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let root = tree_guard.root.clone();
+        let nodes = tree_guard.extend(vec![
+            ("b".to_string(), Some("a".to_string()), Some(2)),
+            ("a".to_string(), None, Some(1)),
+        ]).unwrap();
+        assert_eq!(node_ids(nodes), vec!["b".to_string(), "a".to_string()]);
+        assert_eq!(node_ids(tree_guard.get_descendants(&root).unwrap()), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(tree_guard.find_by_id("b").unwrap().lock().unwrap().data, Some(2));
+
+        assert_eq!(tree_guard.extend(vec![("c".to_string(), Some("b".to_string()), None), ("d".to_string(), Some("x".to_string()), None)]).err(), Some(TreeError::NodeNotFound("x".to_string())));
+        assert_eq!(tree_guard.extend(vec![("a".to_string(), None, None)]).err(), Some(TreeError::DuplicateId("a".to_string())));
+        assert!(tree_guard.find_by_id("c").is_none());
+    }
+
+    #[test]
+    fn test_treemap_add_many_and_extend_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let nodes: Vec<Arc<RwLock<NodeMap<i32>>>> = (0..3).map(|_| NodeMap::new()).collect();
+        let [a, b, c] = [0, 1, 2].map(|index| nodes[index].read().unwrap().id.clone());
+        let tree_guard = tree.read().unwrap();
+        let root = tree_guard.find_by_id("root").unwrap();
+        nodes[1].write().unwrap().data = Some(5);
+        tree_guard.add_many(&[
+            (nodes[1].clone(), Some(nodes[0].clone())),
+            (nodes[0].clone(), None),
+            (nodes[2].clone(), Some(nodes[0].clone())),
+        ]).unwrap();
+        assert_eq!(nodemap_ids(tree_guard.get_descendants(&root).unwrap()), vec![a.clone(), b.clone(), c.clone()]);
+        assert_eq!(tree_guard.get_data(&b), Some(5));
+
+        let new_nodes = tree_guard.extend(vec![("x".to_string(), Some(c.clone()), Some(1)), ("y".to_string(), Some("x".to_string()), None)]).unwrap();
+        assert!(Arc::ptr_eq(&tree_guard.find_by_id("y").unwrap(), &new_nodes[1]));
+        assert_eq!(tree_guard.get_data("x"), Some(1));
+        assert_eq!(nodemap_ids(tree_guard.path_to_root(&new_nodes[1]).unwrap())[..3], ["y".to_string(), "x".to_string(), c]);
+
+        let stray = NodeMap::<i32>::new();
+        let fresh = NodeMap::<i32>::new();
+        assert!(matches!(tree_guard.add_many(&[(fresh.clone(), None), (NodeMap::new(), Some(stray))]), Err(TreeError::NotInTree(_))));
+        assert!(matches!(tree_guard.add_many(&[(fresh.clone(), None), (nodes[0].clone(), None)]), Err(TreeError::DuplicateId(_))));
+        assert!(fresh.read().unwrap().get_tree().is_none());
+        assert_eq!(tree_guard.subtree_size(&root).unwrap(), 6);
+    }
+
+    fn treemap_shape(tree: &TreeMap<i32>) -> Vec<(String, Option<String>, Option<i32>)> {
+        tree.pre_order(None, None).unwrap().map(|node| {
+            let id = node.read().unwrap().id.clone();
+            let parent_id = tree.parent(&node).unwrap().map(|parent| parent.read().unwrap().id.clone());
+            let data = tree.get_data(&id);
+            (id, parent_id, data)
+        }).collect()
+    }
+
+    fn tree_shape(tree: &Tree<i32>) -> Vec<(String, Option<String>, Option<i32>)> {
+        tree.pre_order(None, None).unwrap().map(|node| {
+            let node_guard = node.lock().unwrap();
+            let parent_id = node_guard.parent.as_ref().and_then(|parent| parent.upgrade()).map(|parent| parent.lock().unwrap().id.clone());
+            (node_guard.id.clone(), parent_id, node_guard.data)
+        }).collect()
+    }
+
+    #[test]
+    fn test_treemap_transaction_commits_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.write().unwrap();
+        let nodes = tree_guard.extend(vec![("a".to_string(), None, Some(1)), ("b".to_string(), None, None)]).unwrap();
+        let root = tree_guard.find_by_id("root").unwrap();
+        let c = NodeMap::<i32>::new();
+
+        let mut transaction = TreeMapTransaction::new();
+        transaction.add_child(c.clone(), Some(nodes[0].clone()))
+            .move_node_at(nodes[1].clone(), root.clone(), 0)
+            .set_data(c.clone(), Some(3));
+        assert_eq!(transaction.len(), 3);
+        tree_guard.commit(transaction).unwrap();
+
+        assert_eq!(nodemap_ids(tree_guard.get_descendants(&root).unwrap()), vec!["b".to_string(), "a".to_string(), c.read().unwrap().id.clone()]);
+        assert_eq!(tree_guard.get_data(&c.read().unwrap().id), Some(3));
+    }
+
+    #[test]
+    fn test_treemap_transaction_rolls_back_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.write().unwrap();
+        // root -> (a -> (c -> (e), d), b)
+        tree_guard.extend(vec![
+            ("a".to_string(), None, Some(1)),
+            ("b".to_string(), None, Some(2)),
+            ("c".to_string(), Some("a".to_string()), Some(3)),
+            ("d".to_string(), Some("a".to_string()), None),
+            ("e".to_string(), Some("c".to_string()), Some(5)),
+        ]).unwrap();
+        let before = treemap_shape(&tree_guard);
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|id| tree_guard.find_by_id(id).unwrap());
+        let new_node = NodeMap::<i32>::new();
+        new_node.write().unwrap().data = Some(9);
+
+        let mut transaction = TreeMapTransaction::new();
+        transaction.insert_child(b.clone(), new_node.clone(), 0)
+            .move_node_at(d.clone(), b.clone(), 0)
+            .set_data(b.clone(), Some(20))
+            .remove_node(c.clone(), false)
+            .reorder_children(b.clone(), vec![new_node.read().unwrap().id.clone(), "d".to_string()])
+            .remove_node(a.clone(), true)
+            // d now sits below b, so this move fails and everything above is undone
+            .move_node(d.clone(), new_node.clone())
+            .move_node(b.clone(), d.clone());
+        assert!(matches!(tree_guard.commit(transaction), Err(TreeError::CycleDetected(_))));
+
+        assert_eq!(treemap_shape(&tree_guard), before);
+        assert!(tree_guard.find_by_id(&new_node.read().unwrap().id).is_err());
+        assert!(new_node.read().unwrap().get_tree().is_none());
+        assert_eq!(new_node.read().unwrap().data, Some(9));
+    }
+
+    #[test]
+    fn test_tree_transaction_rolls_back_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        // root -> (a -> (c, d), b)
+        tree_guard.extend(vec![
+            ("a".to_string(), None, Some(1)),
+            ("b".to_string(), None, Some(2)),
+            ("c".to_string(), Some("a".to_string()), None),
+            ("d".to_string(), Some("a".to_string()), Some(4)),
+        ]).unwrap();
+        let before = tree_shape(&tree_guard);
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|id| tree_guard.find_by_id(id).unwrap());
+        let new_node = Node::<i32>::new(Some(9), None);
+
+        let mut transaction = TreeTransaction::new();
+        transaction.add_child(new_node.clone(), Some(b.clone()))
+            .move_node_at(d.clone(), b.clone(), 0)
+            .set_data(a.clone(), None)
+            .remove_node(a.clone(), true)
+            .remove_node(c.clone(), false)
+            .reorder_children(b.clone(), vec!["missing".to_string()]);
+        assert!(matches!(tree_guard.commit(transaction), Err(TreeError::MalformedInput(_))));
+
+        assert_eq!(tree_shape(&tree_guard), before);
+        assert!(tree_guard.find_by_id(&new_node.lock().unwrap().id).is_none());
+        assert!(new_node.lock().unwrap().parent.is_none());
+
+        let mut transaction = TreeTransaction::new();
+        transaction.remove_node(d.clone(), false).move_node(c.clone(), b.clone());
+        tree_guard.commit(transaction).unwrap();
+        assert!(tree_guard.find_by_id("d").is_none());
+        assert!(Arc::ptr_eq(&tree_guard.get_ancestors(&c).unwrap()[0], &b));
+    }
+
+    #[test]
+    fn test_treemap_undo_redo_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.write().unwrap();
+        assert!(matches!(tree_guard.undo(), Err(TreeError::HistoryDisabled)));
+        tree_guard.enable_history(10);
+        // root -> (a -> (c -> (e), d), b)
+        tree_guard.extend(vec![
+            ("a".to_string(), None, Some(1)),
+            ("b".to_string(), None, Some(2)),
+            ("c".to_string(), Some("a".to_string()), Some(3)),
+            ("d".to_string(), Some("a".to_string()), None),
+            ("e".to_string(), Some("c".to_string()), Some(5)),
+        ]).unwrap();
+        let built = treemap_shape(&tree_guard);
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|id| tree_guard.find_by_id(id).unwrap());
+
+        tree_guard.move_node_at(&d, &b, 0).unwrap();
+        tree_guard.set_data("b", Some(20));
+        tree_guard.remove_node(&c, false).unwrap();
+        tree_guard.remove_node(&a, true).unwrap();
+        let edited = treemap_shape(&tree_guard);
+        let kinds: Vec<Vec<&str>> = tree_guard.history().unwrap().iter().map(|entry| entry.iter().map(|(kind, _)| *kind).collect()).collect();
+        assert_eq!(kinds, vec![vec!["add"; 5], vec!["move"], vec!["set_data"], vec!["remove"], vec!["remove"]]);
+
+        for _ in 0..4 {
+            assert!(tree_guard.undo().unwrap());
+        }
+        assert_eq!(treemap_shape(&tree_guard), built);
+        // d went back to its old place under a, after c
+        assert_eq!(tree_guard.index_in_parent(&d).unwrap(), Some(1));
+
+        for _ in 0..4 {
+            assert!(tree_guard.redo().unwrap());
+        }
+        assert!(!tree_guard.redo().unwrap());
+        assert_eq!(treemap_shape(&tree_guard), edited);
+
+        while tree_guard.undo().unwrap() {}
+        assert_eq!(treemap_shape(&tree_guard), vec![(tree_guard.find_by_id("root").unwrap().read().unwrap().id.clone(), None, None)]);
+    }
+
+    #[test]
+    fn test_tree_history_checkpoints_and_limit_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.enable_history(3);
+        let [a, b, c] = [1, 2, 3].map(|value| Node::new(Some(value), None));
+        tree_guard.add_child(a.clone(), None).unwrap();
+        tree_guard.add_child(b.clone(), None).unwrap();
+        tree_guard.checkpoint("two").unwrap();
+        let two = tree_shape(&tree_guard);
+
+        tree_guard.add_child(c.clone(), Some(a.clone())).unwrap();
+        tree_guard.set_data(&c, Some(30));
+        tree_guard.reorder_children(&tree_guard.root, &node_ids(vec![b.clone(), a.clone()])).unwrap();
+        let edited = tree_shape(&tree_guard);
+
+        tree_guard.restore_checkpoint("two").unwrap();
+        assert_eq!(tree_shape(&tree_guard), two);
+        assert!(c.lock().unwrap().parent.is_none());
+        tree_guard.checkpoint("back").unwrap();
+        tree_guard.redo().unwrap();
+        tree_guard.checkpoint("three").unwrap();
+        tree_guard.restore_checkpoint("back").unwrap();
+        tree_guard.redo().unwrap();
+        tree_guard.redo().unwrap();
+        tree_guard.redo().unwrap();
+        assert_eq!(tree_shape(&tree_guard), edited);
+        assert!(matches!(tree_guard.restore_checkpoint("missing"), Err(TreeError::CheckpointNotFound(_))));
+
+        // Only the last 3 entries are kept, "two" now lies before the oldest one that can be undone
+        tree_guard.move_node(&c, &b).unwrap();
+        assert!(matches!(tree_guard.restore_checkpoint("two"), Err(TreeError::CheckpointNotFound(_))));
+        assert_eq!(tree_guard.history().unwrap().len(), 3);
+        tree_guard.restore_checkpoint("three").unwrap();
+        assert!(Arc::ptr_eq(&tree_guard.get_ancestors(&c).unwrap()[0], &a));
+        assert_eq!(c.lock().unwrap().data, Some(3));
+        // Something new drops whatever could be redone
+        tree_guard.remove_node(&c, false).unwrap();
+        assert!(!tree_guard.redo().unwrap());
+    }
+
+    #[test]
+    fn test_failed_undo_leaves_the_tree_as_it_was_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.enable_history(10);
+        tree_guard.extend(vec![("a".to_string(), None, None), ("b".to_string(), None, None)]).unwrap();
+        let before = tree_shape(&tree_guard);
+
+        // With a out of the index the tree no longer matches the entry, undoing it removes b and then fails on a
+        let a = tree_guard.index.write().unwrap().remove("a").unwrap();
+        assert_eq!(tree_guard.undo(), Err(TreeError::NotInTree("a".to_string())));
+        tree_guard.index.write().unwrap().insert("a".to_string(), a);
+        assert_eq!(tree_shape(&tree_guard), before);
+        assert!(tree_guard.find_by_id("b").is_some());
+        assert!(!tree_guard.undo().unwrap());
+    }
+
+    #[test]
+    fn test_events_for_and_hooks_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.write().unwrap();
+        let root_id = tree_guard.find_by_id("root").unwrap().read().unwrap().id.clone();
+        let pairs = tree_guard.pairs_from_records(vec![
+            ("a".to_string(), None, None),
+            ("b".to_string(), Some("a".to_string()), None),
+            ("c".to_string(), Some("a".to_string()), None),
+        ]).unwrap();
+        let ops: Vec<_> = pairs.iter().map(|(child, parent)| Op::Add {child: child.clone(), parent: parent.clone(), index: None}).collect();
+        let events = tree_guard.events_for(&ops).unwrap();
+        let placed: Vec<_> = events.iter().map(|event| (event.node_id.as_str(), event.new_parent.clone().unwrap(), event.new_index)).collect();
+        assert_eq!(placed, vec![("a", root_id.clone(), Some(0)), ("b", "a".to_string(), Some(0)), ("c", "a".to_string(), Some(1))]);
+        tree_guard.add_many(&pairs).unwrap();
+
+        let [a, b, c] = ["a", "b", "c"].map(|id| tree_guard.find_by_id(id).unwrap());
+        let events = tree_guard.events_for(&[
+            Op::Move {node: b.clone(), parent: a.clone(), index: None},
+            Op::Remove {node: c.clone(), keep_children: false},
+            Op::SetData {node: a.clone(), data: None},
+        ]).unwrap();
+        assert_eq!(events[0], Event {kind: "move", node_id: "b".to_string(), old_parent: Some("a".to_string()), new_parent: Some("a".to_string()), old_index: Some(0), new_index: Some(1)});
+        assert_eq!((events[1].kind, events[1].old_index, events[1].new_parent.clone()), ("remove", Some(0), None));
+        assert_eq!((events[2].kind, events[2].old_parent.clone(), events[2].new_index), ("set_data", Some(root_id), Some(0)));
+        assert_eq!(events.len(), 3);
+        // An op that would fail gives the error applying it would
+        let stray = NodeMap::<i32>::new();
+        assert_eq!(tree_guard.events_for(&[Op::Reorder {parent: a.clone(), ids: vec![]}]), Err(TreeError::MalformedInput("ids must name every child of 'a' exactly once".to_string())));
+        assert_eq!(tree_guard.events_for(&[Op::Add {child: NodeMap::new(), parent: Some(stray.clone()), index: None}]), Err(TreeError::NotInTree(stray.read().unwrap().id.clone())));
+
+        // Reorders move the children whose index changes, a node added earlier in the batch is
+        // placed where the add put it
+        let d = NodeMap::<i32>::new();
+        let d_id = d.read().unwrap().id.clone();
+        let batch_events = tree_guard.events_for(&[
+            Op::Reorder {parent: a.clone(), ids: vec!["c".to_string(), "b".to_string()]},
+            Op::Add {child: d.clone(), parent: Some(a.clone()), index: None},
+            Op::Move {node: d.clone(), parent: b.clone(), index: None},
+        ]).unwrap();
+        let moves: Vec<_> = batch_events.iter().map(|event| (event.kind, event.node_id.as_str(), event.old_parent.as_deref(), event.old_index, event.new_index)).collect();
+        assert_eq!(moves, vec![
+            ("move", "c", Some("a"), Some(1), Some(0)),
+            ("move", "b", Some("a"), Some(0), Some(1)),
+            ("add", d_id.as_str(), None, None, Some(2)),
+            ("move", d_id.as_str(), Some("a"), Some(2), Some(0)),
+        ]);
+
+        // undo_ops gives what undoing the last entry reverts, newest change first
+        tree_guard.enable_history(10);
+        tree_guard.move_node(&c, &b).unwrap();
+        let ops = tree_guard.undo_ops().unwrap();
+        assert!(matches!(&ops[..], [Op::Move {node, parent, index: Some(1)}] if Arc::ptr_eq(node, &c) && Arc::ptr_eq(parent, &a)));
+        tree_guard.undo().unwrap();
+        assert!(matches!(&tree_guard.redo_ops().unwrap()[..], [Op::Move {parent, ..}] if Arc::ptr_eq(parent, &b)));
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_by_hook = seen.clone();
+        let id = tree_guard.add_after_hook(Arc::new(move |event: &Event| {
+            seen_by_hook.lock().unwrap().push(event.node_id.clone());
+            Ok(())
+        }));
+        tree_guard.add_before_hook(Arc::new(|event: &Event| {
+            if event.kind == "remove" {Err("removing is not allowed".into())} else {Ok(())}
+        }));
+        let hooks = tree_guard.hooks();
+        assert!(hooks.before(&events[1..2]).is_err());
+        hooks.after(&events).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec!["b", "c", "a"]);
+        assert!(tree_guard.remove_hook(id));
+        assert!(!tree_guard.remove_hook(id));
+    }
+
+    #[test]
+    fn test_treemap_journal_replay_mt(){
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.write().unwrap();
+        tree_guard.enable_journal().unwrap();
+        tree_guard.enable_history(10);
+        // root -> (a -> (c, d), b)
+        tree_guard.extend(vec![
+            ("a".to_string(), None, Some(1)),
+            ("b".to_string(), None, Some(2)),
+            ("c".to_string(), Some("a".to_string()), Some(3)),
+            ("d".to_string(), Some("a".to_string()), None),
+        ]).unwrap();
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|id| tree_guard.find_by_id(id).unwrap());
+        let built = tree_guard.journal(0).unwrap();
+        assert_eq!(built.last_seq(), 4);
+
+        tree_guard.move_node_at(&d, &b, 0).unwrap();
+        tree_guard.set_data("b", Some(20));
+        tree_guard.reorder_children(&tree_guard.find_by_id("root").unwrap(), &["b".to_string(), "a".to_string()]).unwrap();
+        tree_guard.remove_node(&a, true).unwrap();
+        // Undo journals the mutations that put a back
+        tree_guard.undo().unwrap();
+        // A transaction that fails half way leaves no entries behind
+        let mut transaction = TreeMapTransaction::new();
+        transaction.set_data(c.clone(), Some(30)).move_node(b.clone(), d.clone());
+        assert!(tree_guard.commit(transaction).is_err());
+        let journal = tree_guard.journal(0).unwrap();
+        let kinds: Vec<&str> = journal.entries[4..].iter().map(|entry| match entry.op {
+            JournalOp::Add {..} => "add",
+            JournalOp::Move {..} => "move",
+            JournalOp::Remove {..} => "remove",
+            JournalOp::SetData {..} => "set_data",
+            JournalOp::Reorder {..} => "reorder",
+        }).collect();
+        assert_eq!(kinds, vec!["move", "set_data", "reorder", "remove", "add", "move"]);
+        assert!(journal.entries.iter().enumerate().all(|(position, entry)| entry.seq == position as u64 + 1));
+
+        let bytes = journal.to_bytes(|value| Ok(value.to_le_bytes().to_vec())).unwrap();
+        let decode = |bytes: &[u8]| bytes.try_into().map(i32::from_le_bytes).map_err(|_| TreeError::MalformedInput("bad i32".to_string()));
+        let read = Journal::from_bytes(&bytes, decode).unwrap();
+        assert_eq!(read, journal);
+        assert!(matches!(Journal::from_bytes(&bytes[..bytes.len() - 1], decode), Err(TreeError::MalformedInput(_))));
+
+        let replayed = TreeMap::replay(&read).unwrap();
+        assert_eq!(treemap_shape(&replayed.read().unwrap()), treemap_shape(&tree_guard));
+
+        // A copy rebuilt from the first entries catches up with the rest
+        let copy = TreeMap::replay(&built).unwrap();
+        copy.read().unwrap().apply(&journal.since(built.last_seq())).unwrap();
+        assert_eq!(treemap_shape(&copy.read().unwrap()), treemap_shape(&tree_guard));
+        // Entries that do not fit leave the copy as it was, the nodes of built are there already
+        let before = treemap_shape(&copy.read().unwrap());
+        assert!(matches!(copy.read().unwrap().apply(&journal), Err(TreeError::DuplicateId(_))));
+        assert_eq!(treemap_shape(&copy.read().unwrap()), before);
+    }
+
+    #[test]
+    fn test_json_round_trip_mt(){
+        let json = r#"{"id":"r","children":[{"id":"a","data":1,"children":[{"id":"c","data":3}]},{"id":"b"}]}"#;
+        let tree = Tree::<i32>::from_json(json).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        assert_eq!(tree_guard.find_by_id("c").unwrap().lock().unwrap().data, Some(3));
+        assert_eq!(tree_guard.to_json(None).unwrap(), json);
+        let indented = tree_guard.to_json(Some(2)).unwrap();
+        assert!(indented.starts_with("{\n  \"id\": \"r\",\n  \"children\": ["));
+
+        let treemap = TreeMap::<i32>::from_json(&indented).unwrap();
+        let treemap_guard = treemap.read().unwrap();
+        assert_eq!(treemap_shape(&treemap_guard), tree_shape(&tree_guard));
+        assert_eq!(treemap_guard.to_json(None).unwrap(), json);
+
+        // Payloads that are not Serialize go through a conversion of their own
+        let doubled = treemap_guard.to_json_with(None, &|data| Ok(serde_json::json!(data * 2))).unwrap();
+        assert!(doubled.contains(r#""data":6"#));
+        let failing = tree_guard.to_json_with(None, &|data| if *data == 3 {Err(TreeError::MalformedInput("three".to_string()))} else {Ok(serde_json::json!(data))});
+        assert_eq!(failing, Err(TreeError::MalformedInput("data of node 'c': three".to_string())));
+
+        assert!(matches!(Tree::<i32>::from_json(r#"{"children":[]}"#), Err(TreeError::MalformedInput(_))));
+        assert!(matches!(TreeMap::<i32>::from_json(r#"{"id":"r","data":"text"}"#), Err(TreeError::MalformedInput(_))));
+        assert!(matches!(TreeMap::<i32>::from_json(r#"{"id":"r","children":[{"id":"a"},{"id":"a"}]}"#), Err(TreeError::DuplicateId(_))));
+
+        // Nesting far beyond serde_json's recursion limit of 128
+        let mut chain = String::new();
+        for depth in 0..1000 {
+            chain.push_str(&format!(r#"{{"id":"{}","data":{},"children":["#, depth, depth));
+        }
+        chain.push_str(r#"{"id":"leaf"}"#);
+        chain.push_str(&"]}".repeat(1000));
+        let deep = Tree::<i32>::from_json(&chain).unwrap();
+        assert_eq!(deep.lock().unwrap().to_json(None).unwrap(), chain);
+        let deep_map = TreeMap::<i32>::from_json(&chain).unwrap();
+        let deep_map_guard = deep_map.read().unwrap();
+        assert_eq!(deep_map_guard.get_ancestors(&deep_map_guard.find_by_id("leaf").unwrap()).unwrap().len(), 1000);
+        let indented = deep_map_guard.to_json(Some(1)).unwrap();
+        assert_eq!(TreeMap::<i32>::from_json(&indented).unwrap().read().unwrap().to_json(None).unwrap(), chain);
+        assert!(matches!(Tree::<i32>::from_json(&chain[..chain.len() - 1]), Err(TreeError::MalformedInput(_))));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip_mt(){
+        let json = r#"{"id":"r","data":0,"children":[{"id":"a","data":1,"children":[{"id":"c","data":3},{"id":"d"}]},{"id":"b","data":2}]}"#;
+        let encode = |data: &i32| Ok(data.to_le_bytes().to_vec());
+        let decode = |bytes: &[u8]| bytes.try_into().map(i32::from_le_bytes).map_err(|_| TreeError::MalformedInput("bad i32".to_string()));
+
+        let tree = Tree::<i32>::from_json(json).unwrap();
+        let bytes = tree.lock().unwrap().to_snapshot(encode).unwrap();
+        let reloaded = Tree::from_snapshot(&bytes, decode).unwrap();
+        assert_eq!(tree_shape(&reloaded.lock().unwrap()), tree_shape(&tree.lock().unwrap()));
+        assert_eq!(reloaded.lock().unwrap().find_by_id("d").unwrap().lock().unwrap().parent.as_ref().unwrap().upgrade().unwrap().lock().unwrap().id, "a");
+
+        // Both trees write the same bytes for the same structure and payloads
+        let treemap = TreeMap::<i32>::from_json(json).unwrap();
+        assert_eq!(treemap.read().unwrap().to_snapshot(encode).unwrap(), bytes);
+        let reloaded_map = TreeMap::from_snapshot(&bytes, decode).unwrap();
+        let reloaded_map_guard = reloaded_map.read().unwrap();
+        assert_eq!(treemap_shape(&reloaded_map_guard), treemap_shape(&treemap.read().unwrap()));
+        reloaded_map_guard.move_node(&reloaded_map_guard.find_by_id("c").unwrap(), &reloaded_map_guard.find_by_id("b").unwrap()).unwrap();
+        assert_eq!(reloaded_map_guard.get_ancestors(&reloaded_map_guard.find_by_id("c").unwrap()).unwrap().len(), 2);
+
+        let mut corrupted = bytes.clone();
+        corrupted[20] ^= 1;
+        assert_eq!(Tree::from_snapshot(&corrupted, decode).err(), Some(TreeError::MalformedInput("checksum mismatch, the data is corrupted".to_string())));
+        assert!(matches!(TreeMap::from_snapshot(&bytes[..bytes.len() - 3], decode), Err(TreeError::MalformedInput(_))));
+        let mut newer = bytes[..bytes.len() - 8].to_vec();
+        newer[4] = 2;
+        newer.extend_from_slice(&codec::checksum(&newer).to_le_bytes());
+        assert_eq!(TreeMap::from_snapshot(&newer, decode).err(), Some(TreeError::MalformedInput("snapshot version 2 is not supported, expected 1".to_string())));
+
+        // A hand-built snapshot of a root with two children both called 'a'
+        let mut writer = codec::Writer::new(b"TRSN");
+        writer.u8(1);
+        writer.u64(3);
+        writer.u64s(&[u64::MAX, 0, 0]);
+        writer.u64s(&[1, 1, 1]);
+        writer.bytes(b"raa");
+        for _ in 0..3 {
+            writer.bool(false);
+        }
+        let duplicated = writer.finish_checked();
+        assert_eq!(Tree::from_snapshot(&duplicated, decode).err(), Some(TreeError::DuplicateId("a".to_string())));
+        assert_eq!(TreeMap::from_snapshot(&duplicated, decode).err(), Some(TreeError::DuplicateId("a".to_string())));
+    }
+
+    #[test]
+    fn test_records_round_trip_mt(){
+        let record = |id: &str, parent_id: Option<&str>, data: Option<i32>| (id.to_string(), parent_id.map(str::to_string), data);
+        // Children before their parents and the root in the middle
+        let records = vec![
+            record("c", Some("a"), Some(3)),
+            record("b", Some("r"), None),
+            record("r", None, Some(0)),
+            record("d", Some("a"), None),
+            record("a", Some("r"), Some(1)),
+        ];
+        let tree = Tree::from_records(records.clone()).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let expected = vec![record("r", None, Some(0)), record("b", Some("r"), None), record("a", Some("r"), Some(1)), record("c", Some("a"), Some(3)), record("d", Some("a"), None)];
+        assert_eq!(tree_guard.to_records(), expected);
+
+        let treemap = TreeMap::from_records(records).unwrap();
+        assert_eq!(treemap.read().unwrap().to_records(), expected);
+        assert_eq!(Tree::from_records(treemap.read().unwrap().to_records()).unwrap().lock().unwrap().to_records(), expected);
+
+        let orphan = TreeMap::from_records(vec![record("r", None, None), record("a", Some("x"), None)]);
+        assert_eq!(orphan.err(), Some(TreeError::MalformedInput("node 'a' is an orphan, its parent 'x' is not among the records".to_string())));
+        let two_roots = Tree::from_records(vec![record("r", None, None), record("s", None, None)]);
+        assert_eq!(two_roots.err(), Some(TreeError::MalformedInput("more than one root: 'r', 's'".to_string())));
+        assert!(matches!(TreeMap::from_records(vec![record("a", Some("b"), None), record("b", Some("a"), None)]), Err(TreeError::MalformedInput(_))));
+        let cycle = TreeMap::from_records(vec![record("r", None, None), record("a", Some("b"), None), record("b", Some("a"), None)]);
+        assert!(matches!(cycle, Err(TreeError::CycleDetected(_))));
+        assert!(matches!(Tree::from_records(vec![record("r", None, None), record("a", Some("r"), None), record("a", Some("r"), None)]), Err(TreeError::DuplicateId(_))));
+    }
+
+    #[test]
+    fn test_encodings_round_trip_mt(){
+        let record = |id: &str, parent_id: Option<&str>, data: Option<i32>| (id.to_string(), parent_id.map(str::to_string), data);
+        let records = vec![record("r", None, Some(0)), record("a", Some("r"), Some(1)), record("c", Some("a"), None), record("d", Some("a"), Some(4)), record("b", Some("r"), None)];
+        let tree = Tree::from_records(records.clone()).unwrap();
+        let treemap = TreeMap::from_records(records.clone()).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let treemap_guard = treemap.read().unwrap();
+
+        let sets = vec![("r".to_string(), 1, 10), ("a".to_string(), 2, 7), ("c".to_string(), 3, 4), ("d".to_string(), 5, 6), ("b".to_string(), 8, 9)];
+        assert_eq!(tree_guard.nested_sets(), sets);
+        assert_eq!(treemap_guard.nested_sets(), sets);
+        // The rows can come in any order
+        let rows: Vec<_> = sets.iter().rev().zip(records.iter().rev()).map(|((id, lft, rgt), (_, _, data))| (id.clone(), *lft, *rgt, *data)).collect();
+        assert_eq!(Tree::from_nested_sets(rows.clone()).unwrap().lock().unwrap().to_records(), records);
+        assert_eq!(TreeMap::from_nested_sets(rows).unwrap().read().unwrap().to_records(), records);
+        let overlap = vec![("r".to_string(), 1, 6, None::<i32>), ("a".to_string(), 2, 4, None), ("b".to_string(), 3, 5, None)];
+        assert_eq!(Tree::from_nested_sets(overlap).err(), Some(TreeError::MalformedInput("the numbers of node 'b' overlap those of 'a'".to_string())));
+
+        let paths = vec!["/r", "/r/a", "/r/a/c", "/r/a/d", "/r/b"];
+        assert_eq!(tree_guard.materialized_paths("/").unwrap(), paths);
+        assert_eq!(treemap_guard.materialized_paths("/").unwrap(), paths);
+        assert_eq!(treemap_guard.materialized_paths(".").unwrap()[2], ".r.a.c");
+        let rows: Vec<_> = paths.iter().zip(&records).map(|(path, (_, _, data))| (path.to_string(), *data)).collect();
+        assert_eq!(TreeMap::from_materialized_paths(rows.clone(), "/").unwrap().read().unwrap().to_records(), records);
+        assert_eq!(Tree::from_materialized_paths(rows, "/").unwrap().lock().unwrap().to_records(), records);
+        let misplaced = vec![("/r".to_string(), None::<i32>), ("/r/a".to_string(), None), ("/r/x/b".to_string(), None), ("/r/a/x".to_string(), None)];
+        assert_eq!(Tree::from_materialized_paths(misplaced, "/").err(), Some(TreeError::MalformedInput("path '/r/x/b' does not continue the path of 'x'".to_string())));
+        let slashed = TreeMap::from_records(vec![record("r", None, None), record("e/f", Some("r"), None)]).unwrap();
+        assert_eq!(slashed.read().unwrap().materialized_paths("/").err(), Some(TreeError::MalformedInput("node 'e/f' contains the separator '/'".to_string())));
+        assert!(matches!(Tree::<i32>::new(None).unwrap().lock().unwrap().materialized_paths(""), Err(TreeError::MalformedInput(_))));
+
+        let closure = tree_guard.closure_table();
+        let row = |ancestor: &str, descendant: &str, depth: usize| (ancestor.to_string(), descendant.to_string(), depth);
+        assert_eq!(&closure[..6], &[row("r", "r", 0), row("a", "a", 0), row("r", "a", 1), row("c", "c", 0), row("a", "c", 1), row("r", "c", 2)]);
+        assert_eq!(closure.len(), 11);
+        let structure: Vec<_> = records.iter().map(|(id, parent_id, _)| (id.clone(), parent_id.clone(), None)).collect();
+        assert_eq!(TreeMap::<i32>::from_closure_table(&closure).unwrap().read().unwrap().to_records(), structure);
+        assert_eq!(Tree::<i32>::from_closure_table(&closure).unwrap().lock().unwrap().to_records(), structure);
+        let missing = &closure[..closure.len() - 1];
+        assert!(matches!(Tree::<i32>::from_closure_table(missing), Err(TreeError::MalformedInput(_))));
+        let two_parents = [row("r", "r", 0), row("a", "a", 0), row("r", "a", 1), row("b", "b", 0), row("r", "b", 1), row("b", "a", 1)];
+        assert_eq!(TreeMap::<i32>::from_closure_table(&two_parents).err(), Some(TreeError::MalformedInput("node 'a' has more than one parent".to_string())));
+    }
+
+    #[test]
+    fn test_newick_round_trip_mt(){
+        let text = "((A:0.1,'C d''s':2.5e-1)B[internal]:0.3, E_f)root;";
+        let tree = Tree::from_newick(text, Ok).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let labels: Vec<_> = tree_guard.pre_order(None, None).unwrap().map(|node| {
+            let node_guard = node.lock().unwrap();
+            (node_guard.data.clone(), node_guard.branch_length)
+        }).collect();
+        assert_eq!(labels, vec![
+            (Some("root".to_string()), None),
+            (Some("B".to_string()), Some(0.3)),
+            (Some("A".to_string()), Some(0.1)),
+            (Some("C d's".to_string()), Some(0.25)),
+            (Some("E f".to_string()), None),
+        ]);
+        let newick = tree_guard.to_newick(|label| Ok(label.clone())).unwrap();
+        assert_eq!(newick, "((A:0.1,'C d''s':0.25)B:0.3,'E f')root;");
+        let copy = Tree::from_newick(&newick, Ok).unwrap();
+        assert_eq!(copy.lock().unwrap().to_newick(|label| Ok(label.clone())).unwrap(), newick);
+
+        // Unlabelled nodes and payloads other than strings
+        let numbers = Tree::from_newick("(1,(2,3):4,);", |label| label.parse::<i32>().map_err(|err| TreeError::MalformedInput(err.to_string()))).unwrap();
+        assert_eq!(numbers.lock().unwrap().to_newick(|number| Ok((number * 10).to_string())).unwrap(), "(10,(20,30):4,);");
+
+        for (text, message) in [
+            ("(A,B)", "newick: missing ';' at the end at position 5"),
+            ("(A,B;", "newick: '(' is never closed at position 4"),
+            ("(A,B));", "newick: unexpected ')' at position 5"),
+            ("(A:x,B);", "newick: invalid branch length 'x' at position 4"),
+            ("('A,B);", "newick: quoted label is never closed at position 7"),
+            ("(A,B)[root;", "newick: comment is never closed at position 5"),
+            ("(A,B); C", "newick: text after the closing ';' at position 7"),
+        ] {
+            assert_eq!(Tree::from_newick(text, Ok).err(), Some(TreeError::MalformedInput(message.to_string())), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_diagram_export_mt(){
+        let record = |id: &str, parent_id: Option<&str>, data: Option<&str>| (id.to_string(), parent_id.map(str::to_string), data.map(str::to_string));
+        let records = vec![record("r", None, Some("top")), record("a", Some("r"), Some("say \"hi\"")), record("c", Some("a"), None), record("b", Some("r"), None)];
+        let tree = Tree::from_records(records.clone()).unwrap();
+        let treemap = TreeMap::from_records(records).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let treemap_guard = treemap.read().unwrap();
+        let tree_label = |node: &Arc<Mutex<Node<String>>>| {
+            let node_guard = node.lock().unwrap();
+            Ok(node_guard.data.clone().unwrap_or_else(|| node_guard.id.clone()))
+        };
+        let treemap_label = |node: &Arc<RwLock<NodeMap<String>>>| {
+            let id = node.read().unwrap().id.clone();
+            Ok(treemap_guard.get_data(&id).unwrap_or(id))
+        };
+
+        let options = DiagramOptions {highlight: HashSet::from(["c".to_string(), "missing".to_string()]), ..Default::default()};
+        let dot = tree_guard.to_dot(&options, tree_label).unwrap();
+        assert_eq!(dot, [
+            "digraph tree {",
+            "    rankdir=TB;",
+            "    node [shape=box];",
+            "    \"r\" [label=\"top\"];",
+            "    \"a\" [label=\"say \\\"hi\\\"\"];",
+            "    \"c\" [label=\"c\", style=filled, fillcolor=\"#ffd54f\"];",
+            "    \"b\" [label=\"b\"];",
+            "    \"r\" -> \"a\";",
+            "    \"a\" -> \"c\";",
+            "    \"r\" -> \"b\";",
+            "}",
+            "",
+        ].join("\n"));
+        assert_eq!(treemap_guard.to_dot(&options, treemap_label).unwrap(), dot);
+
+        let options = DiagramOptions {max_depth: Some(1), direction: Direction::parse("lr").unwrap(), highlight: HashSet::from(["b".to_string()])};
+        let mermaid = treemap_guard.to_mermaid(&options, treemap_label).unwrap();
+        assert_eq!(mermaid, [
+            "flowchart LR",
+            "    n0[\"top\"]",
+            "    n1[\"say #quot;hi#quot;\"]",
+            "    n2[\"b\"]",
+            "    n0 --> n1",
+            "    n0 --> n2",
+            "    classDef highlight fill:#ffd54f",
+            "    class n2 highlight",
+            "",
+        ].join("\n"));
+        assert_eq!(tree_guard.to_mermaid(&options, tree_label).unwrap(), mermaid);
+
+        assert!(matches!(Direction::parse("up"), Err(TreeError::MalformedInput(_))));
+        let failing = tree_guard.to_dot(&DiagramOptions::default(), |_| Err(TreeError::MalformedInput("no label".to_string())));
+        assert_eq!(failing.err(), Some(TreeError::MalformedInput("no label".to_string())));
+    }
+
+    #[test]
+    fn test_render_mt(){
+        let record = |id: &str, parent_id: Option<&str>, data: Option<&str>| (id.to_string(), parent_id.map(str::to_string), data.map(str::to_string));
+        let records = vec![
+            record("r", None, Some("root")),
+            record("b", Some("r"), Some("two\nlines")),
+            record("d", Some("b"), None),
+            record("a", Some("r"), None),
+            record("c", Some("b"), None),
+            record("e", Some("d"), None),
+        ];
+        let tree = Tree::from_records(records.clone()).unwrap();
+        let treemap = TreeMap::from_records(records).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        let treemap_guard = treemap.read().unwrap();
+        let tree_label = |node: &Arc<Mutex<Node<String>>>| {
+            let node_guard = node.lock().unwrap();
+            Ok(node_guard.data.clone().unwrap_or_else(|| node_guard.id.clone()))
+        };
+        let treemap_label = |node: &Arc<RwLock<NodeMap<String>>>| {
+            let id = node.read().unwrap().id.clone();
+            Ok(treemap_guard.get_data(&id).unwrap_or(id))
+        };
+
+        let outline = tree_guard.render(None, &RenderOptions::default(), tree_label, None).unwrap();
+        assert_eq!(outline, "root\n├── two\n│   lines\n│   ├── d\n│   │   └── e\n│   └── c\n└── a\n");
+        assert_eq!(treemap_guard.render(None, &RenderOptions::default(), treemap_label, None).unwrap(), outline);
+
+        // Subtree, depth limit, ascii and sorted children
+        let options = RenderOptions {max_depth: Some(1), style: RenderStyle::Ascii};
+        let mut by_id = |mut children: Vec<Arc<RwLock<NodeMap<String>>>>| {
+            children.sort_by_key(|child| child.read().unwrap().id.clone());
+            Ok(children)
+        };
+        let b = treemap_guard.find_by_id("b").unwrap();
+        assert_eq!(treemap_guard.render(Some(&b), &options, treemap_label, Some(&mut by_id)).unwrap(), "two\nlines\n|-- c\n`-- d\n");
+        let root = treemap_guard.find_by_id("r").unwrap();
+        assert_eq!(treemap_guard.render(Some(&root), &RenderOptions {max_depth: Some(0), ..Default::default()}, treemap_label, None).unwrap(), "root\n");
+        let elsewhere = TreeMap::<String>::new(None);
+        let stranger = elsewhere.read().unwrap().find_by_id("root").unwrap();
+        assert!(matches!(treemap_guard.render(Some(&stranger), &options, treemap_label, None), Err(TreeError::NotInTree(_))));
+        drop(tree_guard);
+
+        // The subtree is drawn with the tree unlocked, a sort has to give back every child once
+        let subtree = tree.lock().unwrap().subtree(None, None).unwrap();
+        let tree_size = |_: &Arc<Mutex<Node<String>>>| {
+            let tree_guard = tree.lock().unwrap();
+            Ok(tree_guard.subtree_size(&tree_guard.root).unwrap().to_string())
+        };
+        assert!(subtree.render(RenderStyle::BoxDrawing, tree_size, None).unwrap().starts_with("6\n├── 6\n"));
+        let mut first_only = |children: Vec<Arc<Mutex<Node<String>>>>| Ok(children.into_iter().take(1).collect());
+        assert!(matches!(subtree.render(RenderStyle::BoxDrawing, tree_label, Some(&mut first_only)), Err(TreeError::MalformedInput(_))));
+    }
+
+    #[test]
+    fn test_roll_back_reverts_every_change_mt(){
+        let changes: Vec<Change<String, i32>> = ["a", "b", "c"].iter().map(|id| Change::DataSet {node: id.to_string(), data: None}).collect();
+        let mut reverted = Vec::new();
+        let outcome = transaction::roll_back(changes, |change| {
+            reverted.push(change.node().clone());
+            if change.node() == "b" {
+                Err(TreeError::NodeNotFound("b".to_string()))
+            } else {
+                Ok(change)
+            }
+        });
+        // The change after the failing one is still reverted before the failure is reported
+        assert_eq!(reverted, vec!["c", "b", "a"]);
+        assert_eq!(outcome, Err(TreeError::NodeNotFound("b".to_string())));
+        assert_eq!(transaction::roll_back(vec![Change::<String, i32>::DataSet {node: "a".to_string(), data: None}], Ok), Ok(()));
+    }
+
+    #[test]
+    fn test_commit_validates_before_applying_mt(){
+        let tree = Tree::<i32>::new(None).unwrap();
+        let tree_guard = tree.lock().unwrap();
+        tree_guard.extend(vec![("a".to_string(), None, Some(1)), ("b".to_string(), None, None)]).unwrap();
+        let before = tree_shape(&tree_guard);
+        let [a, b] = ["a", "b"].map(|id| tree_guard.find_by_id(id).unwrap());
+        let stray = Node::<i32>::new(None, None);
+        let new_node = Node::<i32>::new(None, None);
+
+        // A parent that is not on the tree fails the add without anything being applied first
+        let mut transaction = TreeTransaction::new();
+        transaction.set_data(a.clone(), Some(10)).move_node(b.clone(), a.clone()).add_child(new_node.clone(), Some(stray.clone()));
+        assert_eq!(tree_guard.commit(transaction), Err(TreeError::NotInTree(stray.lock().unwrap().id.clone())));
+        assert_eq!(tree_shape(&tree_guard), before);
+
+        // Each operation is checked against what the ones before it leave
+        let mut transaction = TreeTransaction::new();
+        transaction.remove_node(a.clone(), false).move_node(b.clone(), a.clone());
+        assert_eq!(tree_guard.commit(transaction), Err(TreeError::NotInTree("a".to_string())));
+        let mut transaction = TreeTransaction::new();
+        transaction.add_child(new_node.clone(), Some(a.clone())).move_node(b.clone(), new_node.clone()).remove_node(a.clone(), true);
+        tree_guard.commit(transaction).unwrap();
+        assert_eq!(node_ids(tree_guard.get_descendants(&tree_guard.root).unwrap()), vec![new_node.lock().unwrap().id.clone(), "b".to_string()]);
+
+        let tree = TreeMap::<i32>::new(None);
+        let tree_guard = tree.write().unwrap();
+        let nodes = tree_guard.extend(vec![("a".to_string(), None, Some(1))]).unwrap();
+        let before = treemap_shape(&tree_guard);
+        let stray = NodeMap::<i32>::new();
+        let mut transaction = TreeMapTransaction::new();
+        transaction.set_data(nodes[0].clone(), Some(10)).add_child(NodeMap::new(), Some(stray.clone()));
+        assert_eq!(tree_guard.commit(transaction), Err(TreeError::NotInTree(stray.read().unwrap().id.clone())));
+        let mut transaction = TreeMapTransaction::new();
+        transaction.remove_node(nodes[0].clone(), false).set_data(nodes[0].clone(), Some(10));
+        assert_eq!(tree_guard.commit(transaction), Err(TreeError::NotInTree("a".to_string())));
+        assert_eq!(treemap_shape(&tree_guard), before);
+    }
+}
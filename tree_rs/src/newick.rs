@@ -0,0 +1,219 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{Node, Result, Tree, TreeError};
+
+// Newick, "(A:0.1,(C,D)B:0.2)root;". A node's children come in parentheses before its label, the
+// label is followed by ":length" for the edge to its parent. Labels are unquoted, where '_' stands
+// for a blank, or 'quoted' with '' for a quote. [comments] may appear between any two tokens and
+// are skipped.
+
+// Characters that end an unquoted label or length
+const DELIMITERS: &str = "()[]':;,";
+
+// A node as read from the text, nodes come in pre-order so a parent is before its children
+struct Parsed {
+    parent: Option<usize>,
+    label: Option<String>,
+    length: Option<f64>,
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.position += next.len_utf8();
+        Some(next)
+    }
+
+    fn error(&self, message: &str) -> TreeError {
+        TreeError::MalformedInput(format!("newick: {} at position {}", message, self.position))
+    }
+
+    // Whitespace and comments
+    fn skip(&mut self) -> Result<()> {
+        loop {
+            match self.peek() {
+                Some(next) if next.is_whitespace() => {
+                    self.next();
+                },
+                Some('[') => {
+                    let end = self.text[self.position..].find(']').ok_or_else(|| self.error("comment is never closed"))?;
+                    self.position += end + 1;
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> &'a str {
+        let start = self.position;
+        while self.peek().is_some_and(|next| !next.is_whitespace() && !DELIMITERS.contains(next)) {
+            self.next();
+        }
+        &self.text[start..self.position]
+    }
+
+    // The optional label and ":length" after a leaf or a closing ')'
+    fn properties(&mut self, node: &mut Parsed) -> Result<()> {
+        self.skip()?;
+        if self.peek() == Some('\'') {
+            self.next();
+            let mut label = String::new();
+            loop {
+                match self.next() {
+                    Some('\'') if self.peek() == Some('\'') => {
+                        self.next();
+                        label.push('\'');
+                    },
+                    Some('\'') => break,
+                    Some(next) => label.push(next),
+                    None => return Err(self.error("quoted label is never closed")),
+                }
+            }
+            node.label = Some(label);
+        } else {
+            let label = self.unquoted();
+            node.label = (!label.is_empty()).then(|| label.replace('_', " "));
+        }
+        self.skip()?;
+        if self.peek() == Some(':') {
+            self.next();
+            self.skip()?;
+            let length = self.unquoted();
+            node.length = Some(length.parse().map_err(|_| self.error(&format!("invalid branch length '{}'", length)))?);
+        }
+        Ok(())
+    }
+
+    fn parse(mut self) -> Result<Vec<Parsed>> {
+        let mut nodes: Vec<Parsed> = Vec::new();
+        // Nodes whose '(' has been read but not their ')'
+        let mut open: Vec<usize> = Vec::new();
+        loop {
+            // A subtree starts, either an internal node or a leaf
+            self.skip()?;
+            nodes.push(Parsed {parent: open.last().copied(), label: None, length: None});
+            let index = nodes.len() - 1;
+            if self.peek() == Some('(') {
+                self.next();
+                open.push(index);
+                continue;
+            }
+            self.properties(&mut nodes[index])?;
+            // Then it is followed by a sibling, closes its parents or ends the tree
+            loop {
+                self.skip()?;
+                match self.peek() {
+                    Some(',') if !open.is_empty() => {
+                        self.next();
+                        break;
+                    },
+                    Some(')') if !open.is_empty() => {
+                        self.next();
+                        let index = open.pop().unwrap();
+                        self.properties(&mut nodes[index])?;
+                    },
+                    Some(';') if open.is_empty() => {
+                        self.next();
+                        self.skip()?;
+                        if self.position < self.text.len() {
+                            return Err(self.error("text after the closing ';'"));
+                        }
+                        return Ok(nodes);
+                    },
+                    Some(';') | None if !open.is_empty() => return Err(self.error("'(' is never closed")),
+                    None => return Err(self.error("missing ';' at the end")),
+                    Some(next) => return Err(self.error(&format!("unexpected '{}'", next))),
+                }
+            }
+        }
+    }
+}
+
+fn write_label(newick: &mut String, label: &str) {
+    if label.is_empty() {
+        newick.push_str("''");
+    } else if label.chars().any(|next| next.is_whitespace() || next == '_' || DELIMITERS.contains(next)) {
+        newick.push('\'');
+        newick.push_str(&label.replace('\'', "''"));
+        newick.push('\'');
+    } else {
+        newick.push_str(label);
+    }
+}
+
+impl<T> Tree<T> {
+    // Builds a tree from Newick text, data turns every label into the node's payload and the branch
+    // lengths go to Node::branch_length. Nodes get new ids, as labels need neither be there nor unique.
+    pub fn from_newick(text: &str, mut data: impl FnMut(String) -> Result<T>) -> Result<Arc<Mutex<Tree<T>>>> {
+        let parsed = Parser {text, position: 0}.parse()?;
+        let mut nodes: Vec<Arc<Mutex<Node<T>>>> = Vec::with_capacity(parsed.len());
+        for Parsed {parent, label, length} in parsed {
+            let parent = parent.map(|parent| &nodes[parent]);
+            let node = Node::new(label.map(&mut data).transpose()?, parent.map(Arc::downgrade));
+            node.lock().unwrap().branch_length = length;
+            if let Some(parent) = parent {
+                parent.lock().unwrap().children.lock().unwrap().push(node.clone());
+            }
+            nodes.push(node);
+        }
+        Tree::new(Some(nodes.swap_remove(0)))
+    }
+
+    // The tree as Newick text, label turns a payload into the node's label and nodes without data
+    // are left unlabelled
+    pub fn to_newick(&self, mut label: impl FnMut(&T) -> Result<String>) -> Result<String> {
+        enum Step<T> {
+            Enter(Arc<Mutex<Node<T>>>),
+            Close(Arc<Mutex<Node<T>>>),
+            Comma,
+        }
+        let mut newick = String::new();
+        let mut steps = vec![Step::Enter(self.root.clone())];
+        while let Some(step) = steps.pop() {
+            let node = match step {
+                Step::Comma => {
+                    newick.push(',');
+                    continue;
+                },
+                Step::Enter(node) => {
+                    let children = node.lock().unwrap().children.lock().unwrap().clone();
+                    if !children.is_empty() {
+                        newick.push('(');
+                        steps.push(Step::Close(node));
+                        for (position, child) in children.into_iter().enumerate().rev() {
+                            steps.push(Step::Enter(child));
+                            if position > 0 {
+                                steps.push(Step::Comma);
+                            }
+                        }
+                        continue;
+                    }
+                    node
+                },
+                Step::Close(node) => {
+                    newick.push(')');
+                    node
+                },
+            };
+            let node_guard = node.lock().unwrap();
+            if let Some(data) = &node_guard.data {
+                write_label(&mut newick, &label(data)?);
+            }
+            if let Some(length) = node_guard.branch_length {
+                newick.push(':');
+                newick.push_str(&length.to_string());
+            }
+        }
+        newick.push(';');
+        Ok(newick)
+    }
+}
@@ -0,0 +1,8 @@
+use pyo3::PyObject;
+use crate::{Node, NodeMap, Tree, TreeMap};
+
+// The structures as the pyo3Tree bindings use them, holding a reference to a python object as payload
+pub type PyNode = Node<PyObject>;
+pub type PyTree = Tree<PyObject>;
+pub type PyNodeMap = NodeMap<PyObject>;
+pub type PyTreeMap = TreeMap<PyObject>;
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::{Node, NodeMap, Result, Tree, TreeError, TreeMap};
+
+// A node as a flat (id, parent_id, data) row, parent_id is None for the root
+pub type Record<T> = (String, Option<String>, Option<T>);
+
+// Takes out the one record without a parent. Every other record has to name a parent among the
+// records, in any order, otherwise it is an orphan.
+fn split_root<T>(mut records: Vec<Record<T>>) -> Result<(Record<T>, Vec<Record<T>>)> {
+    {
+        let ids: HashSet<&str> = records.iter().map(|(id, _, _)| id.as_str()).collect();
+        let orphan = records.iter().find_map(|(id, parent_id, _)| parent_id.as_deref().filter(|parent_id| !ids.contains(parent_id)).map(|parent_id| (id, parent_id)));
+        if let Some((id, parent_id)) = orphan {
+            return Err(TreeError::MalformedInput(format!("node '{}' is an orphan, its parent '{}' is not among the records", id, parent_id)));
+        }
+    }
+    let roots: Vec<usize> = records.iter().enumerate().filter(|(_, (_, parent_id, _))| parent_id.is_none()).map(|(position, _)| position).collect();
+    match roots[..] {
+        [root] => {
+            let root = records.remove(root);
+            Ok((root, records))
+        },
+        [] => Err(TreeError::MalformedInput("no record without a parent_id to be the root".to_string())),
+        _ => {
+            let ids: Vec<&str> = roots.iter().map(|position| records[*position].0.as_str()).collect();
+            Err(TreeError::MalformedInput(format!("more than one root: '{}'", ids.join("', '"))))
+        }
+    }
+}
+
+impl<T> Tree<T> {
+    // Builds a tree from flat records in any order, the one record without a parent_id becomes the
+    // root. Siblings keep the order of their records.
+    pub fn from_records(records: Vec<Record<T>>) -> Result<Arc<Mutex<Tree<T>>>> {
+        let ((root_id, _, root_data), records) = split_root(records)?;
+        let root = Node::new(root_data, None);
+        root.lock().unwrap().id = root_id;
+        let tree = Tree::new(Some(root))?;
+        tree.lock().unwrap().extend(records)?;
+        Ok(tree)
+    }
+
+    // Every node as a record, in pre-order so a parent comes before its children
+    pub fn to_records(&self) -> Vec<Record<T>> where T: Clone {
+        self.pre_order(None, None).unwrap().map(|node| {
+            let node_guard = node.lock().unwrap();
+            let parent_id = node_guard.parent.as_ref().and_then(|parent| parent.upgrade()).map(|parent| parent.lock().unwrap().id.clone());
+            (node_guard.id.clone(), parent_id, node_guard.data.clone())
+        }).collect()
+    }
+}
+
+impl<T> TreeMap<T> {
+    // Builds a tree from flat records in any order, the one record without a parent_id becomes the
+    // root. Siblings keep the order of their records.
+    pub fn from_records(records: Vec<Record<T>>) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        let ((root_id, _, root_data), records) = split_root(records)?;
+        let root = NodeMap::new();
+        {
+            let mut root_guard = root.write().unwrap();
+            root_guard.id = root_id;
+            root_guard.data = root_data;
+        }
+        let tree = TreeMap::new(Some(root));
+        tree.read().unwrap().extend(records)?;
+        Ok(tree)
+    }
+
+    // Every node as a record, in pre-order so a parent comes before its children
+    pub fn to_records(&self) -> Vec<Record<T>> where T: Clone {
+        let arena = self.arena.read().unwrap();
+        let data_guard = self.data.read().unwrap();
+        arena.subtree(arena.root()).into_iter().map(|slot| {
+            let node = arena.node(slot);
+            (node.id.clone(), node.parent.map(|parent| arena.node(parent).id.clone()), data_guard.get(&node.id).cloned())
+        }).collect()
+    }
+}
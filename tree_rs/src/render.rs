@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::traversal::{ChildSource, NodeChildren, NodeMapChildren};
+use crate::{Node, NodeMap, Result, Tree, TreeError, TreeMap};
+
+// Characters the outline is drawn with
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RenderStyle {
+    // ├── └── │
+    #[default]
+    BoxDrawing,
+    // |-- `-- |
+    Ascii,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    // Levels below the start node to draw, all of them when None
+    pub max_depth: Option<usize>,
+    pub style: RenderStyle,
+}
+
+// Reorders the children of a node before they are drawn
+pub type SortChildren<'a, N> = &'a mut dyn FnMut(Vec<N>) -> Result<Vec<N>>;
+
+// The nodes from a start node down to a depth in pre-order, with their ids and the positions of
+// their children. It is taken while the tree is locked and drawn once the lock is released, so the
+// label and sort closures are free to use the tree.
+pub struct Subtree<M> {
+    pub(crate) nodes: Vec<(String, Arc<M>)>,
+    pub(crate) children: Vec<Vec<usize>>,
+}
+
+impl<M> Subtree<M> {
+    fn collect<S: ChildSource<Node = Arc<M>>>(source: S, start: Arc<M>, max_depth: Option<usize>, id: impl Fn(&Arc<M>) -> String) -> Self {
+        let mut subtree = Subtree {nodes: Vec::new(), children: Vec::new()};
+        // (node, depth, position of its parent)
+        let mut stack: Vec<(Arc<M>, usize, Option<usize>)> = vec![(start, 0, None)];
+        while let Some((node, depth, parent)) = stack.pop() {
+            let position = subtree.nodes.len();
+            if let Some(parent) = parent {
+                subtree.children[parent].push(position);
+            }
+            if max_depth.map_or(true, |max_depth| depth < max_depth) {
+                stack.extend(source.children_of(&node).into_iter().rev().map(|child| (child, depth + 1, Some(position))));
+            }
+            subtree.nodes.push((id(&node), node));
+            subtree.children.push(Vec::new());
+        }
+        subtree
+    }
+
+    // Positions of the children of the node at position, in the order sort gives them
+    fn sorted_children(&self, position: usize, sort: &mut Option<SortChildren<Arc<M>>>) -> Result<Vec<usize>> {
+        let children = &self.children[position];
+        let Some(sort) = sort.as_mut() else {
+            return Ok(children.clone());
+        };
+        let mut positions: HashMap<*const M, usize> = children.iter().map(|&child| (Arc::as_ptr(&self.nodes[child].1), child)).collect();
+        let sorted = sort(children.iter().map(|&child| self.nodes[child].1.clone()).collect())?;
+        let sorted: Vec<usize> = sorted.iter().filter_map(|node| positions.remove(&Arc::as_ptr(node))).collect();
+        if sorted.len() != children.len() {
+            return Err(TreeError::MalformedInput(format!("sorting the children of '{}' did not give back each of them once", self.nodes[position].0)));
+        }
+        Ok(sorted)
+    }
+
+    // One line per node, children below their parent and indented one level further:
+    //   root
+    //   ├── a
+    //   │   └── c
+    //   └── b
+    // A label over several lines keeps its further lines in line with the first.
+    pub fn render(&self, style: RenderStyle, mut label: impl FnMut(&Arc<M>) -> Result<String>, mut sort: Option<SortChildren<Arc<M>>>) -> Result<String> {
+        let (branch, last, pipe, blank) = match style {
+            RenderStyle::BoxDrawing => ("├── ", "└── ", "│   ", "    "),
+            RenderStyle::Ascii => ("|-- ", "`-- ", "|   ", "    "),
+        };
+        let mut text = String::new();
+        // (position, prefix of its first line, prefix of everything below it)
+        let mut stack = vec![(0, String::new(), String::new())];
+        while let Some((position, prefix, indent)) = stack.pop() {
+            let label = label(&self.nodes[position].1)?;
+            let children = self.sorted_children(position, &mut sort)?;
+
+            let mut lines = label.split('\n');
+            text.push_str(&prefix);
+            text.push_str(lines.next().unwrap());
+            text.push('\n');
+            for line in lines {
+                text.push_str(&indent);
+                text.push_str(line);
+                text.push('\n');
+            }
+
+            let count = children.len();
+            for (place, child) in children.into_iter().enumerate().rev() {
+                let (connector, below) = if place + 1 == count { (last, blank) } else { (branch, pipe) };
+                stack.push((child, format!("{}{}", indent, connector), format!("{}{}", indent, below)));
+            }
+        }
+        Ok(text)
+    }
+}
+
+impl<T> Tree<T> {
+    // The nodes render and the diagrams draw, start defaults to the root and has to be on the tree
+    pub fn subtree(&self, start: Option<&Arc<Mutex<Node<T>>>>, max_depth: Option<usize>) -> Result<Subtree<Mutex<Node<T>>>> {
+        Ok(Subtree::collect(NodeChildren::new(), self.walk_start(start)?, max_depth, |node| node.lock().unwrap().id.clone()))
+    }
+
+    // The tree, or the subtree under start, as an indented outline, label gives the text of a node
+    pub fn render(&self, start: Option<&Arc<Mutex<Node<T>>>>, options: &RenderOptions, label: impl FnMut(&Arc<Mutex<Node<T>>>) -> Result<String>, sort: Option<SortChildren<Arc<Mutex<Node<T>>>>>) -> Result<String> {
+        self.subtree(start, options.max_depth)?.render(options.style, label, sort)
+    }
+}
+
+impl<T> TreeMap<T> {
+    // The nodes render and the diagrams draw, start defaults to the root and has to be on the tree
+    pub fn subtree(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>, max_depth: Option<usize>) -> Result<Subtree<RwLock<NodeMap<T>>>> {
+        Ok(Subtree::collect(NodeMapChildren(self.arena.clone()), self.walk_start(start)?, max_depth, |node| node.read().unwrap().id.clone()))
+    }
+
+    // The tree, or the subtree under start, as an indented outline, label gives the text of a node
+    pub fn render(&self, start: Option<&Arc<RwLock<NodeMap<T>>>>, options: &RenderOptions, label: impl FnMut(&Arc<RwLock<NodeMap<T>>>) -> Result<String>, sort: Option<SortChildren<Arc<RwLock<NodeMap<T>>>>>) -> Result<String> {
+        self.subtree(start, options.max_depth)?.render(options.style, label, sort)
+    }
+}
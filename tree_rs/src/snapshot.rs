@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::codec::{Reader, Writer};
+use crate::{Node, NodeMap, Result, Tree, TreeError, TreeMap};
+
+// Snapshot layout, all integers u64 little endian:
+//   "TRSN", version (u8), node count n
+//   n parent indices, the nodes are in pre-order so a parent always comes before its children and
+//   siblings keep their order. The root has NO_PARENT.
+//   n id lengths followed by the ids back to back, each id is stored once
+//   n payloads, a flag and the encoded bytes
+//   checksum of everything before it
+const MAGIC: &[u8] = b"TRSN";
+const VERSION: u8 = 1;
+const NO_PARENT: u64 = u64::MAX;
+
+fn encode(parents: &[u64], ids: &[String], mut payload: impl FnMut(usize) -> Result<Option<Vec<u8>>>) -> Result<Vec<u8>> {
+    let mut writer = Writer::new(MAGIC);
+    writer.u8(VERSION);
+    writer.u64(parents.len() as u64);
+    writer.u64s(parents);
+    writer.u64s(&ids.iter().map(|id| id.len() as u64).collect::<Vec<_>>());
+    writer.bytes(ids.concat().as_bytes());
+    for position in 0..parents.len() {
+        let payload = payload(position)?;
+        writer.bool(payload.is_some());
+        if let Some(payload) = payload {
+            writer.bytes(&payload);
+        }
+    }
+    Ok(writer.finish_checked())
+}
+
+// The parent indices, ids and payloads of a snapshot, checked to describe a single tree
+#[allow(clippy::type_complexity)]
+fn decode<T>(bytes: &[u8], mut decode_data: impl FnMut(&[u8]) -> Result<T>) -> Result<(Vec<u64>, Vec<String>, Vec<Option<T>>)> {
+    let mut reader = Reader::checked(bytes, MAGIC)?;
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(TreeError::MalformedInput(format!("snapshot version {} is not supported, expected {}", version, VERSION)));
+    }
+    let count = usize::try_from(reader.u64()?).map_err(|_| TreeError::MalformedInput("length out of range".to_string()))?;
+    let parents = reader.u64s(count)?;
+    if count == 0 || parents[0] != NO_PARENT || parents.iter().enumerate().skip(1).any(|(position, parent)| *parent >= position as u64) {
+        return Err(TreeError::MalformedInput("snapshot does not describe a tree".to_string()));
+    }
+
+    let lengths = reader.u64s(count)?;
+    let blob = std::str::from_utf8(reader.bytes()?).map_err(|_| TreeError::MalformedInput("invalid utf-8 in string".to_string()))?;
+    let mut ids = Vec::with_capacity(count);
+    let mut start = 0usize;
+    for len in lengths {
+        let id = usize::try_from(len).ok().and_then(|len| blob.get(start..start.checked_add(len)?))
+            .ok_or_else(|| TreeError::MalformedInput("ids do not match their lengths".to_string()))?;
+        start += id.len();
+        ids.push(id.to_string());
+    }
+    if start != blob.len() {
+        return Err(TreeError::MalformedInput("ids do not match their lengths".to_string()));
+    }
+
+    let payloads = (0..count).map(|_| reader.opt_data(&mut decode_data)).collect::<Result<Vec<_>>>()?;
+    reader.finish()?;
+    Ok((parents, ids, payloads))
+}
+
+impl<T> Tree<T> {
+    // The tree in the binary snapshot format, encode turns each payload into bytes
+    pub fn to_snapshot(&self, mut encode_data: impl FnMut(&T) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        let mut nodes = Vec::new();
+        let mut parents = Vec::new();
+        let mut ids = Vec::new();
+        let mut stack = vec![(self.root.clone(), NO_PARENT)];
+        while let Some((node, parent)) = stack.pop() {
+            let index = nodes.len() as u64;
+            {
+                let node_guard = node.lock().unwrap();
+                ids.push(node_guard.id.clone());
+                stack.extend(node_guard.children.lock().unwrap().iter().rev().map(|child| (child.clone(), index)));
+            }
+            parents.push(parent);
+            nodes.push(node);
+        }
+        encode(&parents, &ids, |position| nodes[position].lock().unwrap().data.as_ref().map(&mut encode_data).transpose())
+    }
+
+    // Rebuilds a tree from to_snapshot's bytes, decode turns the bytes of a payload back into a T
+    pub fn from_snapshot(bytes: &[u8], decode_data: impl FnMut(&[u8]) -> Result<T>) -> Result<Arc<Mutex<Tree<T>>>> {
+        let (parents, ids, payloads) = decode(bytes, decode_data)?;
+        let mut seen = HashSet::with_capacity(ids.len());
+        if let Some(id) = ids.iter().find(|id| !seen.insert(id.as_str())) {
+            return Err(TreeError::DuplicateId(id.clone()));
+        }
+        let mut nodes: Vec<Arc<Mutex<Node<T>>>> = Vec::with_capacity(ids.len());
+        for ((parent, id), data) in parents.into_iter().zip(ids).zip(payloads) {
+            let parent = nodes.get(parent as usize);
+            let node = Node::new(data, parent.map(Arc::downgrade));
+            node.lock().unwrap().id = id;
+            if let Some(parent) = parent {
+                parent.lock().unwrap().children.lock().unwrap().push(node.clone());
+            }
+            nodes.push(node);
+        }
+        Tree::new(Some(nodes.swap_remove(0)))
+    }
+}
+
+impl<T> TreeMap<T> {
+    // The tree in the binary snapshot format, encode turns each payload into bytes
+    pub fn to_snapshot(&self, mut encode_data: impl FnMut(&T) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        let arena = self.arena.read().unwrap();
+        let data_guard = self.data.read().unwrap();
+        let mut parents = Vec::with_capacity(arena.len());
+        let mut ids = Vec::with_capacity(arena.len());
+        let mut stack = vec![(arena.root(), NO_PARENT)];
+        while let Some((slot, parent)) = stack.pop() {
+            let index = ids.len() as u64;
+            let node = arena.node(slot);
+            stack.extend(node.children.iter().rev().map(|child| (*child, index)));
+            parents.push(parent);
+            ids.push(node.id.clone());
+        }
+        encode(&parents, &ids, |position| data_guard.get(&ids[position]).map(&mut encode_data).transpose())
+    }
+
+    // Rebuilds a tree from to_snapshot's bytes, decode turns the bytes of a payload back into a T
+    pub fn from_snapshot(bytes: &[u8], decode_data: impl FnMut(&[u8]) -> Result<T>) -> Result<Arc<RwLock<TreeMap<T>>>> {
+        let (parents, ids, payloads) = decode(bytes, decode_data)?;
+        let mut payloads = payloads.into_iter();
+        let root = NodeMap::new();
+        {
+            let mut root_guard = root.write().unwrap();
+            root_guard.id = ids[0].clone();
+            root_guard.data = payloads.next().unwrap();
+        }
+        let tree = TreeMap::new(Some(root));
+        {
+            let tree_guard = tree.read().unwrap();
+            let mut arena = tree_guard.arena.write().unwrap();
+            let mut data_guard = tree_guard.data.write().unwrap();
+            data_guard.reserve(ids.len());
+            // A parent comes before its children, so its slot is known by the time they are added
+            let mut slots = Vec::with_capacity(ids.len());
+            slots.push(arena.root());
+            for ((id, parent), data) in ids.iter().zip(parents).skip(1).zip(payloads) {
+                if arena.contains_id(id) {
+                    return Err(TreeError::DuplicateId(id.clone()));
+                }
+                if let Some(data) = data {
+                    data_guard.insert(id.clone(), data);
+                }
+                let node = NodeMap::new();
+                let slot = arena.insert(id.clone(), node.clone(), slots[parent as usize], None);
+                {
+                    let mut node_guard = node.write().unwrap();
+                    node_guard.id = id.clone();
+                    node_guard.index = Some(slot);
+                    node_guard.tree = Some(tree_guard.this.clone());
+                }
+                slots.push(slot);
+            }
+        }
+        Ok(tree)
+    }
+}
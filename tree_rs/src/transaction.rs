@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::handles::Handles;
+use crate::{insert_at, reordered, Event, Node, NodeMap, Result, Tree, TreeError, TreeMap};
+
+// A mutation waiting in a Transaction, N is the node handle of the tree it is meant for
+pub enum Op<N, T> {
+    Add {child: N, parent: Option<N>, index: Option<usize>},
+    Move {node: N, parent: N, index: Option<usize>},
+    Remove {node: N, keep_children: bool},
+    SetData {node: N, data: Option<T>},
+    Reorder {parent: N, ids: Vec<String>},
+}
+
+impl<N: Clone, T> Op<N, T> {
+    // The same operation with the payload left out, all events_for needs to know about it
+    pub fn without_data(&self) -> Self {
+        match self {
+            Op::Add {child, parent, index} => Op::Add {child: child.clone(), parent: parent.clone(), index: *index},
+            Op::Move {node, parent, index} => Op::Move {node: node.clone(), parent: parent.clone(), index: *index},
+            Op::Remove {node, keep_children} => Op::Remove {node: node.clone(), keep_children: *keep_children},
+            Op::SetData {node, ..} => Op::SetData {node: node.clone(), data: None},
+            Op::Reorder {parent, ids} => Op::Reorder {parent: parent.clone(), ids: ids.clone()},
+        }
+    }
+}
+
+// Mutations buffered until they are committed to a tree, where either all of them are applied or,
+// when one fails, the ones before it are rolled back and the tree is left as it was
+pub struct Transaction<N, T> {
+    ops: Vec<Op<N, T>>,
+}
+
+pub type TreeTransaction<T> = Transaction<Arc<Mutex<Node<T>>>, T>;
+pub type TreeMapTransaction<T> = Transaction<Arc<RwLock<NodeMap<T>>>, T>;
+
+impl<N, T> Transaction<N, T> {
+    pub fn new() -> Self {
+        Self {ops: Vec::new()}
+    }
+
+    pub fn add_child(&mut self, child: N, parent: Option<N>) -> &mut Self {
+        self.push(Op::Add {child, parent, index: None})
+    }
+
+    pub fn insert_child(&mut self, parent: N, child: N, index: usize) -> &mut Self {
+        self.push(Op::Add {child, parent: Some(parent), index: Some(index)})
+    }
+
+    pub fn move_node(&mut self, node: N, parent: N) -> &mut Self {
+        self.push(Op::Move {node, parent, index: None})
+    }
+
+    pub fn move_node_at(&mut self, node: N, parent: N, index: usize) -> &mut Self {
+        self.push(Op::Move {node, parent, index: Some(index)})
+    }
+
+    pub fn remove_node(&mut self, node: N, keep_children: bool) -> &mut Self {
+        self.push(Op::Remove {node, keep_children})
+    }
+
+    pub fn set_data(&mut self, node: N, data: Option<T>) -> &mut Self {
+        self.push(Op::SetData {node, data})
+    }
+
+    pub fn reorder_children(&mut self, parent: N, ids: Vec<String>) -> &mut Self {
+        self.push(Op::Reorder {parent, ids})
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn ops(&self) -> &[Op<N, T>] {
+        &self.ops
+    }
+
+    fn push(&mut self, op: Op<N, T>) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+}
+
+impl<N, T> Default for Transaction<N, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A mutation that has been applied to a tree, holding what it takes to revert it. Reverting a
+// change returns the change that undoes the revert, so the same value serves undo and redo.
+pub enum Change<N, T> {
+    // Reverting removes the node again, leaving its children on the tree when keep_children
+    Added {node: N, keep_children: bool},
+    // parent and index are where the node was before it moved
+    Moved {node: N, parent: N, index: usize},
+    // parent and index are where the node was. adopted counts the children that took its place
+    // when it was removed with keep_children, subtree lists the removed descendants of a TreeMap
+    // node with their parents in pre-order, as a removed NodeMap does not keep them.
+    Removed {node: N, parent: N, index: usize, adopted: usize, subtree: Vec<(N, N)>},
+    // data is what the node held before
+    DataSet {node: N, data: Option<T>},
+    // ids is the order of the children before
+    Reordered {parent: N, ids: Vec<String>},
+}
+
+impl<N, T> Change<N, T> {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::Added {..} => "add",
+            Change::Moved {..} => "move",
+            Change::Removed {..} => "remove",
+            Change::DataSet {..} => "set_data",
+            Change::Reordered {..} => "reorder",
+        }
+    }
+
+    // The node the change is about, the parent for a reorder
+    pub fn node(&self) -> &N {
+        match self {
+            Change::Added {node, ..} | Change::Moved {node, ..} | Change::Removed {node, ..} | Change::DataSet {node, ..} => node,
+            Change::Reordered {parent, ..} => parent,
+        }
+    }
+
+    // What reverting the change does as an operation, without the payload, for events_for
+    pub fn revert_op(&self) -> Op<N, T> where N: Clone {
+        match self {
+            Change::Added {node, keep_children} => Op::Remove {node: node.clone(), keep_children: *keep_children},
+            Change::Moved {node, parent, index} => Op::Move {node: node.clone(), parent: parent.clone(), index: Some(*index)},
+            Change::Removed {node, parent, index, ..} => Op::Add {child: node.clone(), parent: Some(parent.clone()), index: Some(*index)},
+            Change::DataSet {node, ..} => Op::SetData {node: node.clone(), data: None},
+            Change::Reordered {parent, ids} => Op::Reorder {parent: parent.clone(), ids: ids.clone()},
+        }
+    }
+}
+
+// Reverts the changes a failed commit already made, newest first. A change that cannot be reverted
+// means the tree no longer matches what was recorded, the remaining changes are still reverted and
+// the first such error is returned, as the tree is then not back where the commit started.
+pub(crate) fn roll_back<N, T>(changes: Vec<Change<N, T>>, mut revert: impl FnMut(Change<N, T>) -> Result<Change<N, T>>) -> Result<()> {
+    let mut first_failure = None;
+    for change in changes.into_iter().rev() {
+        if let Err(error) = revert(change) {
+            first_failure.get_or_insert(error);
+        }
+    }
+    first_failure.map_or(Ok(()), Err)
+}
+
+// Checks every operation against the tree as the operations before it would leave it, without
+// touching the tree. An operation that passes fails the same way applying it would, so a commit
+// that validates is not expected to need rolling back.
+pub(crate) fn validate<H: Handles>(tree: &H, ops: &[Op<H::Node, H::Data>]) -> Result<()> {
+    let mut scratch = Scratch::new(tree);
+    ops.iter().try_for_each(|op| scratch.apply(op).map(drop))
+}
+
+// The tree with the operations of a batch applied on top, kept apart from it. Only what the
+// operations changed is held here, everything else is read from the tree.
+pub(crate) struct Scratch<'a, H: Handles> {
+    tree: &'a H,
+    members: HashMap<usize, bool>,
+    ids: HashMap<String, bool>,
+    parents: HashMap<usize, Option<H::Node>>,
+    children: HashMap<usize, Vec<H::Node>>,
+}
+
+impl<'a, H: Handles> Scratch<'a, H> {
+    pub(crate) fn new(tree: &'a H) -> Self {
+        Self {tree, members: HashMap::new(), ids: HashMap::new(), parents: HashMap::new(), children: HashMap::new()}
+    }
+
+    fn on_tree(&self, node: &H::Node) -> bool {
+        self.members.get(&H::key(node)).copied().unwrap_or_else(|| self.tree.contains(node))
+    }
+
+    fn id_taken(&self, id: &str) -> bool {
+        self.ids.get(id).copied().unwrap_or_else(|| self.tree.id_taken(id))
+    }
+
+    fn parent(&self, node: &H::Node) -> Option<H::Node> {
+        match self.parents.get(&H::key(node)) {
+            Some(parent) => parent.clone(),
+            None => self.tree.parent_of(node)
+        }
+    }
+
+    fn children(&self, node: &H::Node) -> Vec<H::Node> {
+        match self.children.get(&H::key(node)) {
+            Some(children) => children.clone(),
+            None => self.tree.children_of(node)
+        }
+    }
+
+    fn children_mut(&mut self, node: &H::Node) -> &mut Vec<H::Node> {
+        let tree = self.tree;
+        self.children.entry(H::key(node)).or_insert_with(|| tree.children_of(node))
+    }
+
+    // The node and everything below it in pre-order
+    fn subtree(&self, node: &H::Node) -> Vec<H::Node> {
+        let mut subtree = Vec::new();
+        let mut stack = vec![node.clone()];
+        while let Some(node) = stack.pop() {
+            stack.extend(self.children(&node).into_iter().rev());
+            subtree.push(node);
+        }
+        subtree
+    }
+
+    // The node's parent and its index among the parent's children
+    fn position(&self, node: &H::Node) -> Result<(H::Node, usize)> {
+        if !self.on_tree(node) {
+            return Err(TreeError::NotInTree(H::id_of(node)));
+        }
+        let Some(parent) = self.parent(node) else {
+            return Err(TreeError::RootNode(H::id_of(node)));
+        };
+        let index = self.index_of(node, &parent).unwrap();
+        Ok((parent, index))
+    }
+
+    fn index_of(&self, node: &H::Node, parent: &H::Node) -> Option<usize> {
+        self.children(parent).iter().position(|child| H::key(child) == H::key(node))
+    }
+
+    fn leave(&mut self, node: &H::Node, on_tree: bool) {
+        self.members.insert(H::key(node), on_tree);
+        self.ids.insert(H::id_of(node), on_tree);
+    }
+
+    fn link(&mut self, node: &H::Node, parent: &H::Node, index: Option<usize>) {
+        insert_at(self.children_mut(parent), node.clone(), index);
+        self.parents.insert(H::key(node), Some(parent.clone()));
+    }
+
+    // Applies the op to the scratch view and returns the events it causes, or the error applying it
+    // to the tree would give
+    pub(crate) fn apply(&mut self, op: &Op<H::Node, H::Data>) -> Result<Vec<Event>> {
+        let event = |kind, node: &H::Node, old: Option<(&H::Node, usize)>, new: Option<(&H::Node, usize)>| Event {
+            kind,
+            node_id: H::id_of(node),
+            old_parent: old.map(|(parent, _)| H::id_of(parent)),
+            new_parent: new.map(|(parent, _)| H::id_of(parent)),
+            old_index: old.map(|(_, index)| index),
+            new_index: new.map(|(_, index)| index),
+        };
+        let events = match op {
+            Op::Add {child, parent, index} => {
+                if self.tree.attached_elsewhere(child, self.parent(child).is_some()) {
+                    return Err(TreeError::AttachedElsewhere(H::id_of(child)));
+                }
+                let subtree = self.subtree(child);
+                if let Some(taken) = subtree.iter().map(H::id_of).find(|id| self.id_taken(id)) {
+                    return Err(TreeError::DuplicateId(taken));
+                }
+                let parent = match parent {
+                    Some(parent) if !self.on_tree(parent) => return Err(TreeError::NotInTree(H::id_of(parent))),
+                    Some(parent) => parent.clone(),
+                    None => self.tree.root()
+                };
+                for node in &subtree {
+                    self.leave(node, true);
+                }
+                self.link(child, &parent, *index);
+                vec![event("add", child, None, Some((&parent, self.index_of(child, &parent).unwrap())))]
+            },
+            Op::Move {node, parent, index} => {
+                let (from_parent, from_index) = self.position(node)?;
+                if H::key(node) == H::key(parent) {
+                    return Err(TreeError::CycleDetected(H::id_of(node)));
+                }
+                if !self.on_tree(parent) {
+                    return Err(TreeError::NotInTree(H::id_of(parent)));
+                }
+                let mut ancestor = self.parent(parent);
+                while let Some(current) = ancestor {
+                    if H::key(&current) == H::key(node) {
+                        return Err(TreeError::CycleDetected(H::id_of(node)));
+                    }
+                    ancestor = self.parent(&current);
+                }
+                self.children_mut(&from_parent).remove(from_index);
+                self.link(node, parent, *index);
+                vec![event("move", node, Some((&from_parent, from_index)), Some((parent, self.index_of(node, parent).unwrap())))]
+            },
+            Op::Remove {node, keep_children} => {
+                let (parent, index) = self.position(node)?;
+                let mut removed = vec![event("remove", node, Some((&parent, index)), None)];
+                // Without keep_children the descendants leave the tree too, each from where it is
+                if !*keep_children {
+                    for descendant in self.subtree(node).into_iter().skip(1) {
+                        let descendant_parent = self.parent(&descendant).unwrap();
+                        let descendant_index = self.index_of(&descendant, &descendant_parent).unwrap();
+                        removed.push(event("remove", &descendant, Some((&descendant_parent, descendant_index)), None));
+                    }
+                }
+                self.children_mut(&parent).remove(index);
+                self.parents.insert(H::key(node), None);
+                self.leave(node, false);
+                if *keep_children {
+                    let children = std::mem::take(self.children_mut(node));
+                    for (offset, child) in children.into_iter().enumerate() {
+                        self.link(&child, &parent, Some(index + offset));
+                    }
+                } else {
+                    for descendant in self.subtree(node).into_iter().skip(1) {
+                        self.leave(&descendant, false);
+                        if !H::KEEPS_SUBTREE {
+                            self.parents.insert(H::key(&descendant), None);
+                            self.children.insert(H::key(&descendant), Vec::new());
+                        }
+                    }
+                    if !H::KEEPS_SUBTREE {
+                        self.children.insert(H::key(node), Vec::new());
+                    }
+                }
+                removed
+            },
+            Op::SetData {node, ..} => {
+                if !H::EDITS_DETACHED && !self.on_tree(node) {
+                    return Err(TreeError::NotInTree(H::id_of(node)));
+                }
+                // The node stays where it is, which is reported as both its old and new place
+                let place = self.parent(node).map(|parent| {
+                    let index = self.index_of(node, &parent).unwrap();
+                    (parent, index)
+                });
+                let place = place.as_ref().map(|(parent, index)| (parent, *index));
+                vec![event("set_data", node, place, place)]
+            },
+            Op::Reorder {parent, ids} => {
+                if !H::EDITS_DETACHED && !self.on_tree(parent) {
+                    return Err(TreeError::NotInTree(H::id_of(parent)));
+                }
+                let children = self.children(parent);
+                let Some(new_children) = reordered(&children, ids, H::id_of) else {
+                    return Err(TreeError::MalformedInput(format!("ids must name every child of '{}' exactly once", H::id_of(parent))));
+                };
+                // A move within the parent for every child whose index the new order changes
+                let events = new_children.iter().enumerate()
+                    .map(|(new_index, child)| (self.index_of(child, parent).unwrap(), new_index, child))
+                    .filter(|(old_index, new_index, _)| old_index != new_index)
+                    .map(|(old_index, new_index, child)| event("move", child, Some((parent, old_index)), Some((parent, new_index))))
+                    .collect();
+                self.children.insert(H::key(parent), new_children);
+                events
+            },
+        };
+        Ok(events)
+    }
+}
+
+// Committing a Transaction to a Tree or TreeMap
+pub trait Commit: Handles {
+    // Checks every operation against the tree as the ones before it leave it, then applies them in
+    // order. An invalid transaction returns the error of its first bad operation without changing
+    // the tree. Commit under the lock of the tree (the write lock of a TreeMap) so other threads
+    // only ever see the tree before or after the transaction. With the history on the transaction
+    // is recorded as one entry.
+    fn commit(&self, transaction: Transaction<Self::Node, Self::Data>) -> Result<()> {
+        validate(self, &transaction.ops)?;
+        let mut changes = Vec::with_capacity(transaction.len());
+        // A transaction that was rolled back leaves nothing in the journal
+        let journal_mark = self.journal_mark();
+        for op in transaction.ops {
+            match self.apply_op(op) {
+                Ok(change) => changes.push(change),
+                Err(error) => {
+                    roll_back(changes, |change| self.revert(change))?;
+                    self.journal_rewind(journal_mark);
+                    return Err(error);
+                }
+            }
+        }
+        self.record(changes);
+        Ok(())
+    }
+}
+
+impl<T> Commit for TreeMap<T> {}
+impl<T> Commit for Tree<T> {}
+
+impl<T> TreeMap<T> {
+    pub(crate) fn move_recorded(&self, node: &Arc<RwLock<NodeMap<T>>>, parent: &Arc<RwLock<NodeMap<T>>>, index: Option<usize>) -> Result<Change<Arc<RwLock<NodeMap<T>>>, T>> {
+        let (from_parent, from_index) = self.position_of(node)?;
+        self.relocate(node, parent, index)?;
+        Ok(Change::Moved {node: node.clone(), parent: from_parent, index: from_index})
+    }
+
+    pub(crate) fn remove_recorded(&self, node: &Arc<RwLock<NodeMap<T>>>, keep_children: bool) -> Result<Change<Arc<RwLock<NodeMap<T>>>, T>> {
+        let (parent, index) = self.position_of(node)?;
+        let (adopted, subtree) = if keep_children {
+            (self.children(node)?.len(), Vec::new())
+        } else {
+            let subtree = self.get_descendants(node)?.into_iter().map(|descendant| {
+                let descendant_parent = self.parent(&descendant)?.unwrap();
+                Ok((descendant, descendant_parent))
+            }).collect::<Result<Vec<_>>>()?;
+            (0, subtree)
+        };
+        self.remove_from_tree(node, keep_children)?;
+        Ok(Change::Removed {node: node.clone(), parent, index, adopted, subtree})
+    }
+
+    pub(crate) fn set_data_recorded(&self, node: &Arc<RwLock<NodeMap<T>>>, data: Option<T>) -> Result<Change<Arc<RwLock<NodeMap<T>>>, T>> {
+        self.check_on_tree(node)?;
+        let id = node.read().unwrap().id.clone();
+        let previous = self.store_data(&id, data);
+        Ok(Change::DataSet {node: node.clone(), data: previous})
+    }
+
+    pub(crate) fn reorder_recorded(&self, parent: &Arc<RwLock<NodeMap<T>>>, ids: &[String]) -> Result<Change<Arc<RwLock<NodeMap<T>>>, T>> {
+        let previous = self.children(parent)?.iter().map(|child| child.read().unwrap().id.clone()).collect();
+        self.reorder(parent, ids)?;
+        Ok(Change::Reordered {parent: parent.clone(), ids: previous})
+    }
+
+    // The node's parent and its index among the parent's children
+    fn position_of(&self, node: &Arc<RwLock<NodeMap<T>>>) -> Result<(Arc<RwLock<NodeMap<T>>>, usize)> {
+        let arena = self.arena.read().unwrap();
+        let index = arena.index_of(node)?;
+        match arena.position(index) {
+            Some((parent, position)) => Ok((arena.node(parent).handle.clone(), position)),
+            None => Err(TreeError::RootNode(arena.node(index).id.clone()))
+        }
+    }
+}
+
+impl<T> Tree<T> {
+    pub(crate) fn move_recorded(&self, node: &Arc<Mutex<Node<T>>>, parent: &Arc<Mutex<Node<T>>>, index: Option<usize>) -> Result<Change<Arc<Mutex<Node<T>>>, T>> {
+        let (from_parent, from_index) = self.position_of(node)?;
+        self.relocate(node, parent, index)?;
+        Ok(Change::Moved {node: node.clone(), parent: from_parent, index: from_index})
+    }
+
+    pub(crate) fn remove_recorded(&self, node: &Arc<Mutex<Node<T>>>, keep_children: bool) -> Result<Change<Arc<Mutex<Node<T>>>, T>> {
+        let (parent, index) = self.position_of(node)?;
+        let adopted = if keep_children {node.lock().unwrap().children.lock().unwrap().len()} else {0};
+        self.remove_from_tree(node, keep_children)?;
+        Ok(Change::Removed {node: node.clone(), parent, index, adopted, subtree: Vec::new()})
+    }
+
+    pub(crate) fn set_data_recorded(&self, node: &Arc<Mutex<Node<T>>>, data: Option<T>) -> Change<Arc<Mutex<Node<T>>>, T> {
+        let previous = std::mem::replace(&mut node.lock().unwrap().data, data);
+        Change::DataSet {node: node.clone(), data: previous}
+    }
+
+    pub(crate) fn reorder_recorded(&self, parent: &Arc<Mutex<Node<T>>>, ids: &[String]) -> Result<Change<Arc<Mutex<Node<T>>>, T>> {
+        let previous = child_ids(parent);
+        self.reorder(parent, ids)?;
+        Ok(Change::Reordered {parent: parent.clone(), ids: previous})
+    }
+
+    // The node's parent and its index among the parent's children
+    fn position_of(&self, node: &Arc<Mutex<Node<T>>>) -> Result<(Arc<Mutex<Node<T>>>, usize)> {
+        if Arc::ptr_eq(node, &self.root) {
+            return Err(TreeError::RootNode(node.lock().unwrap().id.clone()));
+        }
+        let parent = node.lock().unwrap().parent.as_ref().and_then(|parent_weak| parent_weak.upgrade());
+        match (parent, self.index_in_parent(node)?) {
+            (Some(parent), Some(index)) => Ok((parent, index)),
+            _ => Err(TreeError::NotInTree(node.lock().unwrap().id.clone()))
+        }
+    }
+}
+
+fn child_ids<T>(parent: &Arc<Mutex<Node<T>>>) -> Vec<String> {
+    let parent_guard = parent.lock().unwrap();
+    let children = parent_guard.children.lock().unwrap();
+    children.iter().map(|child| child.lock().unwrap().id.clone()).collect()
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::arena::Arena;
+use crate::{Node, NodeMap};
+
+// Resolves the children of a node so the same walks run over Tree and TreeMap. Children are only
+// looked up when the walk reaches a node, nothing is collected up front and no tree lock is held
+// between steps.
+pub trait ChildSource {
+    type Node: Clone;
+
+    fn children_of(&self, node: &Self::Node) -> Vec<Self::Node>;
+}
+
+// Tree nodes own their children, nothing outside the node is needed
+pub struct NodeChildren<T>(PhantomData<fn() -> T>);
+
+impl<T> NodeChildren<T> {
+    pub fn new() -> Self {
+        NodeChildren(PhantomData)
+    }
+}
+
+impl<T> Default for NodeChildren<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ChildSource for NodeChildren<T> {
+    type Node = Arc<Mutex<Node<T>>>;
+
+    fn children_of(&self, node: &Self::Node) -> Vec<Self::Node> {
+        node.lock().unwrap().children.lock().unwrap().clone()
+    }
+}
+
+// NodeMap children are slots in the arena of the TreeMap the walk started on. A node that has been
+// removed from the tree since has no children.
+pub struct NodeMapChildren<T>(pub(crate) Arc<RwLock<Arena<T>>>);
+
+impl<T> ChildSource for NodeMapChildren<T> {
+    type Node = Arc<RwLock<NodeMap<T>>>;
+
+    fn children_of(&self, node: &Self::Node) -> Vec<Self::Node> {
+        let arena = self.0.read().unwrap();
+        match arena.slot_of(node) {
+            Some(index) => arena.handles(&arena.node(index).children),
+            None => Vec::new()
+        }
+    }
+}
+
+fn within_depth(depth: usize, max_depth: Option<usize>) -> bool {
+    max_depth.map_or(true, |max_depth| depth <= max_depth)
+}
+
+// Parent before children, children in the order they were added. max_depth counts the start node as 0.
+pub struct PreOrderIter<S: ChildSource> {
+    source: S,
+    stack: Vec<(S::Node, usize)>,
+    max_depth: Option<usize>,
+}
+
+impl<S: ChildSource> PreOrderIter<S> {
+    pub fn new(source: S, start: S::Node, max_depth: Option<usize>) -> Self {
+        Self {source, stack: vec![(start, 0)], max_depth}
+    }
+}
+
+impl<S: ChildSource> Iterator for PreOrderIter<S> {
+    type Item = S::Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, depth) = self.stack.pop()?;
+        if within_depth(depth + 1, self.max_depth) {
+            let children = self.source.children_of(&node);
+            self.stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+        Some(node)
+    }
+}
+
+// Children before their parent, the start node comes last
+pub struct PostOrderIter<S: ChildSource> {
+    source: S,
+    // The flag records whether the node's children have already been pushed
+    stack: Vec<(S::Node, usize, bool)>,
+    max_depth: Option<usize>,
+}
+
+impl<S: ChildSource> PostOrderIter<S> {
+    pub fn new(source: S, start: S::Node, max_depth: Option<usize>) -> Self {
+        Self {source, stack: vec![(start, 0, false)], max_depth}
+    }
+}
+
+impl<S: ChildSource> Iterator for PostOrderIter<S> {
+    type Item = S::Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, depth, expanded) = self.stack.pop()?;
+            if expanded || !within_depth(depth + 1, self.max_depth) {
+                return Some(node);
+            }
+            let children = self.source.children_of(&node);
+            self.stack.push((node, depth, true));
+            self.stack.extend(children.into_iter().rev().map(|child| (child, depth + 1, false)));
+        }
+    }
+}
+
+// One level at a time, left to right
+pub struct BreadthFirstIter<S: ChildSource> {
+    source: S,
+    queue: VecDeque<(S::Node, usize)>,
+    max_depth: Option<usize>,
+}
+
+impl<S: ChildSource> BreadthFirstIter<S> {
+    pub fn new(source: S, start: S::Node, max_depth: Option<usize>) -> Self {
+        Self {source, queue: VecDeque::from([(start, 0)]), max_depth}
+    }
+}
+
+impl<S: ChildSource> Iterator for BreadthFirstIter<S> {
+    type Item = S::Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, depth) = self.queue.pop_front()?;
+        if within_depth(depth + 1, self.max_depth) {
+            let children = self.source.children_of(&node);
+            self.queue.extend(children.into_iter().map(|child| (child, depth + 1)));
+        }
+        Some(node)
+    }
+}
+
+// Breadth first, grouped so each item holds every node of one level
+pub struct LevelOrderIter<S: ChildSource> {
+    source: S,
+    level: Vec<S::Node>,
+    depth: usize,
+    max_depth: Option<usize>,
+}
+
+impl<S: ChildSource> LevelOrderIter<S> {
+    pub fn new(source: S, start: S::Node, max_depth: Option<usize>) -> Self {
+        Self {source, level: vec![start], depth: 0, max_depth}
+    }
+}
+
+impl<S: ChildSource> Iterator for LevelOrderIter<S> {
+    type Item = Vec<S::Node>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.level.is_empty() {
+            return None;
+        }
+        let next_level: Vec<S::Node> = if within_depth(self.depth + 1, self.max_depth) {
+            self.level.iter().flat_map(|node| self.source.children_of(node)).collect()
+        } else {
+            Vec::new()
+        };
+        self.depth += 1;
+        Some(std::mem::replace(&mut self.level, next_level))
+    }
+}